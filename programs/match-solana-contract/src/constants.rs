@@ -24,6 +24,158 @@ pub const LOCATION_PREFERENCE_TAG: &[u8] = b"LOCATION_PREFERENCE_STATE";
 #[constant]
 pub const OFFER_TAG: &[u8] = b"OFFER_STATE";
 
+#[constant]
+pub const CATEGORY_TAG: &[u8] = b"CATEGORY_STATE";
+
+#[constant]
+pub const MAX_CATEGORY_NAME_LEN: usize = 32;
+
+#[constant]
+pub const GEO_CELL_TAG: &[u8] = b"GEO_CELL_STATE";
+
+#[constant]
+pub const DEFAULT_GEOHASH_PRECISION: u8 = 5;
+
+#[constant]
+pub const MAX_GEOHASH_PRECISION: u8 = 12;
+
+#[constant]
+pub const POOL_PARTICIPANT_TAG: &[u8] = b"POOL_PARTICIPANT_STATE";
+
+#[constant]
+pub const LOCATION_DECIMALS: u8 = 7;
+
+// Written to every User, Store, Request and Offer, which lets the migrate_*
+// instructions tell a current account from one in the deployed legacy layout.
+#[constant]
+pub const ACCOUNT_VERSION: u8 = 1;
+
+#[constant]
+pub const MAX_LATITUDE: i32 = 900_000_000;
+
+#[constant]
+pub const MAX_LONGITUDE: i32 = 1_800_000_000;
+
+#[constant]
+pub const LOCATION_GRID_SIZE: i32 = 100_000;
+
+#[constant]
+pub const MAX_ENCRYPTED_LOCATION_LEN: usize = 256;
+
+#[constant]
+pub const MAX_ENCRYPTED_LOCATION_KEY_LEN: usize = 128;
+
+#[constant]
+pub const LOCATION_KEY_TAG: &[u8] = b"LOCATION_KEY_STATE";
+
+#[constant]
+pub const CONTACT_TAG: &[u8] = b"CONTACT_STATE";
+
+#[constant]
+pub const MAX_ENCRYPTED_CONTACT_LEN: usize = 256;
+
+#[constant]
+pub const USER_STATS_TAG: &[u8] = b"USER_STATS_STATE";
+
+#[constant]
+pub const REVIEW_TAG: &[u8] = b"REVIEW_STATE";
+
+#[constant]
+pub const MAX_RATING: u8 = 5;
+
+#[constant]
+pub const ON_TIME_PAYMENT_WINDOW: u64 = 24 * 60 * 60;
+
+#[constant]
+pub const SELLER_BOND_TAG: &[u8] = b"SELLER_BOND_STATE";
+
+#[constant]
+pub const BOND_VAULT_TAG: &[u8] = b"BOND_VAULT_STATE";
+
+#[constant]
+pub const LISTING_TAG: &[u8] = b"LISTING_STATE";
+
+#[constant]
+pub const USERNAME_TAG: &[u8] = b"USERNAME_STATE";
+
+#[constant]
+pub const MIN_USERNAME_LEN: usize = 3;
+
+// Also the seed length limit, since records are seeded by the username.
+#[constant]
+pub const MAX_USERNAME_LEN: usize = 32;
+
+// Store names, request names and listing titles. Offers copy the store name.
+#[constant]
+pub const MAX_NAME_LEN: usize = 64;
+
+#[constant]
+pub const MAX_DESCRIPTION_LEN: usize = 512;
+
+#[constant]
+pub const MAX_IMAGES: usize = 5;
+
+#[constant]
+pub const MAX_IMAGE_URL_LEN: usize = 200;
+
+#[constant]
+pub const MAX_OFFERS_PER_REQUEST: usize = 32;
+
+// Settling an auction takes every offer and its seller's stats as accounts,
+// which has to fit in one transaction.
+#[constant]
+pub const MAX_AUCTION_OFFERS: usize = 8;
+
+// Offers a store tracks at once. Sellers prune finished and idle offers to
+// make room for new ones.
+#[constant]
+pub const MAX_OFFERS_PER_STORE: usize = 64;
+
+#[constant]
+pub const ROLE_BUYER: u8 = 1 << 0;
+
+#[constant]
+pub const ROLE_SELLER: u8 = 1 << 1;
+
+#[constant]
+pub const ALL_ROLES: u8 = ROLE_BUYER | ROLE_SELLER;
+
+#[constant]
+pub const MINUTES_PER_DAY: u16 = 24 * 60;
+
+#[constant]
+pub const MAX_UTC_OFFSET_MINUTES: i16 = 14 * 60;
+
+#[constant]
+pub const MAX_VERIFICATION_LEVEL: u8 = 3;
+
+#[constant]
+pub const STORE_DELEGATE_TAG: &[u8] = b"STORE_DELEGATE_STATE";
+
+#[constant]
+pub const DELEGATE_CREATE_OFFERS: u8 = 1 << 0;
+
+#[constant]
+pub const DELEGATE_UPDATE_OFFERS: u8 = 1 << 1;
+
+#[constant]
+pub const DELEGATE_CONFIRM_DELIVERY: u8 = 1 << 2;
+
+#[constant]
+pub const DELEGATE_ALL_PERMISSIONS: u8 =
+    DELEGATE_CREATE_OFFERS | DELEGATE_UPDATE_OFFERS | DELEGATE_CONFIRM_DELIVERY;
+
+#[constant]
+pub const DEFAULT_BOND_UNSTAKE_COOLDOWN: u64 = 7 * 24 * 60 * 60;
+
+#[constant]
+pub const DEFAULT_ABANDON_TIMEOUT: u64 = 30 * 24 * 60 * 60;
+
+// How long after payment a buyer waits for the seller to confirm delivery
+// before they can complete the offer without it.
+#[constant]
+pub const DELIVERY_CONFIRMATION_TIMEOUT: u64 = 14 * 24 * 60 * 60;
+
 #[constant]
 pub const USER_COUNTER: &[u8] = b"USER_COUNTER";
 
@@ -38,3 +190,6 @@ pub const REQUEST_PAYMENT_COUNTER: &[u8] = b"REQUEST_PAYMENT_COUNTER";
 
 #[constant]
 pub const OFFER_COUNTER: &[u8] = b"OFFER_COUNTER";
+
+#[constant]
+pub const LISTING_COUNTER: &[u8] = b"LISTING_COUNTER";
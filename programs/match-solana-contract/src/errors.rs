@@ -31,5 +31,183 @@ pub enum MarketplaceError {
     #[msg("request already paid.")]
     RequestAlreadyPaid,
     #[msg("request not paid.")]
-    RequestNotPaid
+    RequestNotPaid,
+    #[msg("Invalid budget.")]
+    InvalidBudget,
+    #[msg("Offer price below request budget.")]
+    OfferBelowBudget,
+    #[msg("Offer price above request budget.")]
+    OfferAboveBudget,
+    #[msg("Price feed required.")]
+    PriceFeedRequired,
+    #[msg("Invalid bidding deadline.")]
+    InvalidBiddingDeadline,
+    #[msg("Bidding closed.")]
+    BiddingClosed,
+    #[msg("Bidding not closed.")]
+    BiddingNotClosed,
+    #[msg("Auction requests are settled automatically.")]
+    AuctionRequest,
+    #[msg("Not an auction request.")]
+    NotAuctionRequest,
+    #[msg("Auction already settled.")]
+    AuctionAlreadySettled,
+    #[msg("No offers.")]
+    NoOffers,
+    #[msg("Invalid offer.")]
+    InvalidOffer,
+    #[msg("Invalid reveal deadline.")]
+    InvalidRevealDeadline,
+    #[msg("Sealed bid required.")]
+    SealedBidRequired,
+    #[msg("Not a sealed bid request.")]
+    NotSealedBidRequest,
+    #[msg("Reveal closed.")]
+    RevealClosed,
+    #[msg("Reveal not closed.")]
+    RevealNotClosed,
+    #[msg("Offer already revealed.")]
+    OfferAlreadyRevealed,
+    #[msg("Offer not revealed.")]
+    OfferNotRevealed,
+    #[msg("Price commitment mismatch.")]
+    CommitmentMismatch,
+    #[msg("Invalid quantity.")]
+    InvalidQuantity,
+    #[msg("Quantity exceeds the remaining request quantity.")]
+    QuantityExceeded,
+    #[msg("Offer already paid.")]
+    OfferAlreadyPaid,
+    #[msg("Fulfilment already completed.")]
+    FulfilmentAlreadyCompleted,
+    #[msg("Invalid pool deadline.")]
+    InvalidPoolDeadline,
+    #[msg("Not a pooled request.")]
+    NotPooledRequest,
+    #[msg("Pooled requests are paid through the pool.")]
+    PooledRequest,
+    #[msg("Pool closed.")]
+    PoolClosed,
+    #[msg("Pool still open.")]
+    PoolStillOpen,
+    #[msg("Pool not funded.")]
+    PoolNotFunded,
+    #[msg("Pool already funded.")]
+    PoolAlreadyFunded,
+    #[msg("Participant already funded.")]
+    ParticipantAlreadyFunded,
+    #[msg("Pool has escrowed funds.")]
+    PoolNotEmpty,
+    #[msg("Invalid category name.")]
+    InvalidCategoryName,
+    #[msg("Category inactive.")]
+    CategoryInactive,
+    #[msg("Invalid geohash precision.")]
+    InvalidGeohashPrecision,
+    #[msg("Invalid geohash.")]
+    InvalidGeohash,
+    #[msg("Geo cell does not match the location.")]
+    InvalidGeoCell,
+    #[msg("Seller is out of range.")]
+    SellerOutOfRange,
+    #[msg("Invalid location.")]
+    InvalidLocation,
+    #[msg("Account is not in the legacy layout.")]
+    InvalidLegacyAccount,
+    #[msg("Encrypted location is empty or too long.")]
+    InvalidEncryptedLocation,
+    #[msg("Contact can only be shared between the buyer and an accepted seller.")]
+    InvalidContactParties,
+    #[msg("Invalid encrypted contact.")]
+    InvalidEncryptedContact,
+    #[msg("Rating must be between 1 and 5.")]
+    InvalidRating,
+    #[msg("Only the buyer and the seller of a completed offer can review.")]
+    InvalidReviewer,
+    #[msg("Seller bond required.")]
+    BondRequired,
+    #[msg("Seller bond below the minimum.")]
+    InsufficientBond,
+    #[msg("Invalid bond mint.")]
+    InvalidBondMint,
+    #[msg("Invalid bond amount.")]
+    InvalidBondAmount,
+    #[msg("No unstake requested.")]
+    NoUnstakeRequested,
+    #[msg("Unstake cooldown not elapsed.")]
+    UnstakeCooldown,
+    #[msg("Request cancelled.")]
+    RequestCancelled,
+    #[msg("Request cannot be cancelled.")]
+    RequestNotCancellable,
+    #[msg("Request not abandoned yet.")]
+    RequestNotAbandoned,
+    #[msg("Store has live accepted offers.")]
+    StoreHasLiveOffers,
+    #[msg("Listing is out of stock.")]
+    ListingOutOfStock,
+    #[msg("Invalid delegate permissions.")]
+    InvalidDelegatePermissions,
+    #[msg("Signer is not allowed to act for this store.")]
+    UnauthorizedDelegate,
+    #[msg("Offer can no longer be updated.")]
+    OfferNotUpdatable,
+    #[msg("Delivery already confirmed.")]
+    DeliveryAlreadyConfirmed,
+    #[msg("Invalid opening hours.")]
+    InvalidOpeningHours,
+    #[msg("Invalid timezone offset.")]
+    InvalidTimezone,
+    #[msg("Store is away.")]
+    StoreAway,
+    #[msg("Invalid verification level.")]
+    InvalidVerificationLevel,
+    #[msg("Invalid verification expiry.")]
+    InvalidVerificationExpiry,
+    #[msg("Signer is not a store verifier.")]
+    UnauthorizedVerifier,
+    #[msg("Store is not verified.")]
+    StoreNotVerified,
+    #[msg("Role has requests or stores in flight.")]
+    RoleInUse,
+    #[msg("User still has open requests or stores.")]
+    UserHasOpenActivity,
+    #[msg("Invalid username.")]
+    InvalidUsername,
+    #[msg("Username is taken.")]
+    UsernameTaken,
+    #[msg("Name is empty or too long.")]
+    InvalidName,
+    #[msg("Description is too long.")]
+    DescriptionTooLong,
+    #[msg("Too many images.")]
+    TooManyImages,
+    #[msg("Image URL is empty or too long.")]
+    InvalidImageUrl,
+    #[msg("Request has reached its offer limit.")]
+    TooManyOffers,
+    #[msg("Price is out of range.")]
+    PriceOutOfRange,
+    #[msg("Offer is not accepted.")]
+    OfferNotAccepted,
+    #[msg("Signer is not the program upgrade authority.")]
+    UnauthorizedAdmin,
+    #[msg("Account is already migrated.")]
+    AccountAlreadyMigrated,
+    #[msg("Location is not on the coarse grid.")]
+    LocationNotCoarse,
+    #[msg("Encrypted location does not match the commitment.")]
+    LocationCommitmentMismatch,
+    #[msg("Offer was withdrawn.")]
+    OfferWithdrawn,
+    #[msg("Seller has not confirmed delivery.")]
+    DeliveryNotConfirmed,
+    #[msg("Store has reached its offer limit.")]
+    StoreOfferLimit,
+    #[msg("Username has no record, claim it first.")]
+    UsernameNotClaimed,
+    #[msg("Seller already has a live offer on this request.")]
+    SellerAlreadyOffered,
+    #[msg("Request of an offer was not passed.")]
+    OfferRequestMissing,
 }
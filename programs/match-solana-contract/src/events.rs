@@ -1,12 +1,114 @@
 use anchor_lang::prelude::*;
 
+use crate::states::{CoinPayment, OpeningHours, RequestMode};
+
+#[event]
+pub struct UsernameChanged {
+    pub user_id: u64,
+    pub old_username: String,
+    pub new_username: String,
+}
+
+#[event]
+pub struct UserClosed {
+    pub user_id: u64,
+    pub authority: Pubkey,
+    pub closed_at: u64,
+}
+
 #[event]
 pub struct StoreCreated {
     pub seller_address: Pubkey,
     pub store_id: u64,
     pub store_name: String,
-    pub latitude: i128,
-    pub longitude: i128,
+    pub latitude: Option<i32>,
+    pub longitude: Option<i32>,
+    pub geohash: String,
+    pub service_radius: u64,
+    pub opening_hours: [OpeningHours; 7],
+    pub utc_offset_minutes: i16,
+    pub is_away: bool,
+}
+
+#[event]
+pub struct StoreUpdated {
+    pub seller_address: Pubkey,
+    pub store_id: u64,
+    pub store_name: String,
+    pub latitude: Option<i32>,
+    pub longitude: Option<i32>,
+    pub geohash: String,
+    pub service_radius: u64,
+    pub opening_hours: [OpeningHours; 7],
+    pub utc_offset_minutes: i16,
+    pub is_away: bool,
+}
+
+#[event]
+pub struct StoreVerified {
+    pub store_id: u64,
+    pub verification_level: u8,
+    pub verified_until: u64,
+    pub verified_by: Pubkey,
+}
+
+#[event]
+pub struct StoreClosed {
+    pub seller_address: Pubkey,
+    pub store_id: u64,
+    pub store_name: String,
+    pub geohash: String,
+}
+
+#[event]
+pub struct ListingCreated {
+    pub listing_id: u64,
+    pub store_id: u64,
+    pub title: String,
+    pub price: u64,
+    pub stock: u64,
+    pub category: String,
+    pub created_at: u64,
+}
+
+#[event]
+pub struct ListingUpdated {
+    pub listing_id: u64,
+    pub store_id: u64,
+    pub title: String,
+    pub price: u64,
+    pub stock: u64,
+    pub updated_at: u64,
+}
+
+#[event]
+pub struct ListingClosed {
+    pub listing_id: u64,
+    pub store_id: u64,
+    pub closed_at: u64,
+}
+
+#[event]
+pub struct ListingPurchased {
+    pub listing_id: u64,
+    pub buyer_address: Pubkey,
+    pub request_id: u64,
+    pub offer_id: u64,
+    pub quantity: u64,
+    pub price: u64,
+    pub stock: u64,
+}
+
+#[event]
+pub struct StoreTransferred {
+    pub seller_address: Pubkey,
+    pub previous_seller_address: Pubkey,
+    pub store_id: u64,
+    pub store_name: String,
+    pub latitude: Option<i32>,
+    pub longitude: Option<i32>,
+    pub geohash: String,
+    pub service_radius: u64,
 }
 
 #[event]
@@ -14,8 +116,8 @@ pub struct RequestCreated {
     pub request_id: u64,
     pub buyer_address: Pubkey,
     pub request_name: String,
-    pub latitude: i128,
-    pub longitude: i128,
+    pub latitude: Option<i32>,
+    pub longitude: Option<i32>,
     pub images: Vec<String>,
     pub lifecycle: u8,
     pub description: String,
@@ -25,6 +127,20 @@ pub struct RequestCreated {
     pub locked_seller_id: u64,
     pub created_at: u64,
     pub updated_at: u64,
+    pub min_price: Option<u64>,
+    pub max_price: Option<u64>,
+    pub budget_currency: CoinPayment,
+    pub mode: RequestMode,
+    pub bidding_deadline: u64,
+    pub sealed_bids: bool,
+    pub reveal_deadline: u64,
+    pub quantity: u64,
+    pub pooled: bool,
+    pub pool_deadline: u64,
+    pub category: String,
+    pub geohash: String,
+    pub max_seller_distance: u64,
+    pub min_verification_level: u8,
 }
 
 #[event]
@@ -37,6 +153,89 @@ pub struct OfferCreated {
     pub images: Vec<String>,
     pub seller_id: u64,
     pub seller_ids: Vec<u64>,
+    pub quantity: u64,
+    pub store_id: u64,
+}
+
+#[event]
+pub struct OfferCommitted {
+    pub offer_id: u64,
+    pub seller_address: Pubkey,
+    pub store_name: String,
+    pub price_commitment: [u8; 32],
+    pub request_id: u64,
+    pub images: Vec<String>,
+    pub seller_id: u64,
+    pub seller_ids: Vec<u64>,
+    pub quantity: u64,
+    pub store_id: u64,
+}
+
+#[event]
+pub struct OfferUpdated {
+    pub offer_id: u64,
+    pub request_id: u64,
+    pub seller_id: u64,
+    pub price: u64,
+    pub images: Vec<String>,
+    pub quantity: u64,
+    pub updated_by: Pubkey,
+}
+
+#[event]
+pub struct DeliveryConfirmed {
+    pub request_id: u64,
+    pub offer_id: u64,
+    pub seller_id: u64,
+    pub confirmed_by: Pubkey,
+    pub delivered_at: u64,
+}
+
+#[event]
+pub struct OfferWithdrawn {
+    pub offer_id: u64,
+    pub request_id: u64,
+    pub store_id: u64,
+}
+
+#[event]
+pub struct StoreDelegateSet {
+    pub store_id: u64,
+    pub delegate: Pubkey,
+    pub permissions: u8,
+}
+
+#[event]
+pub struct StoreDelegateRemoved {
+    pub store_id: u64,
+    pub delegate: Pubkey,
+}
+
+#[event]
+pub struct OfferRevealed {
+    pub offer_id: u64,
+    pub request_id: u64,
+    pub seller_id: u64,
+    pub price: u64,
+}
+
+#[event]
+pub struct RequestCancelled {
+    pub request_id: u64,
+    pub cancelled_at: u64,
+}
+
+#[event]
+pub struct RequestDepositRefunded {
+    pub request_id: u64,
+    pub amount: u64,
+}
+
+#[event]
+pub struct RequestDepositForfeited {
+    pub request_id: u64,
+    pub amount: u64,
+    pub treasury: Pubkey,
 }
 
 #[event]
@@ -46,6 +245,34 @@ pub struct RequestAccepted {
     pub seller_id: u64,
     pub updated_at: u64,
     pub sellers_price_quote: u64,
+    pub quantity: u64,
+    pub accepted_quantity: u64,
+}
+
+#[event]
+pub struct FulfilmentCompleted {
+    pub request_id: u64,
+    pub offer_id: u64,
+    pub seller_id: u64,
+    pub quantity: u64,
+    pub fulfilled_quantity: u64,
+}
+
+#[event]
+pub struct AuctionSettled {
+    pub request_id: u64,
+    pub offer_id: u64,
+    pub seller_id: u64,
+    pub price: u64,
+    pub offers_count: u64,
+    pub settled_at: u64,
+}
+
+#[event]
+pub struct AuctionFailed {
+    pub request_id: u64,
+    pub offers_count: u64,
+    pub failed_at: u64,
 }
 
 #[event]
@@ -54,8 +281,106 @@ pub struct OfferAccepted {
     pub buyer_address: Pubkey,
     pub is_accepted: bool,
 }
+#[event]
+pub struct PreciseLocationShared {
+    pub request_id: u64,
+    pub offer_id: u64,
+    pub seller_id: u64,
+    pub encrypted_location: Vec<u8>,
+    pub encrypted_location_key: Vec<u8>,
+}
+
+#[event]
+pub struct ReviewSubmitted {
+    pub request_id: u64,
+    pub offer_id: u64,
+    pub reviewer: Pubkey,
+    pub reviewee: Pubkey,
+    pub rating: u8,
+    pub comment_hash: [u8; 32],
+    pub rating_count: u64,
+    pub rating_sum: u64,
+}
+
+#[event]
+pub struct BondStaked {
+    pub seller: Pubkey,
+    pub mint: Option<Pubkey>,
+    pub amount: u64,
+    pub total: u64,
+}
+
+#[event]
+pub struct BondUnstakeRequested {
+    pub seller: Pubkey,
+    pub amount: u64,
+    pub available_at: u64,
+}
+
+#[event]
+pub struct BondWithdrawn {
+    pub seller: Pubkey,
+    pub amount: u64,
+    pub remaining: u64,
+}
+
+#[event]
+pub struct BondSlashed {
+    pub seller: Pubkey,
+    pub buyer: Pubkey,
+    pub amount: u64,
+    pub remaining: u64,
+}
+
+#[event]
+pub struct ContactShared {
+    pub request_id: u64,
+    pub sender: Pubkey,
+    pub recipient: Pubkey,
+    pub updated_at: u64,
+}
+
 #[event]
 pub struct LocationEnabled {
     pub user_id: u64,
     pub location_enabled: bool,
 }
+
+#[event]
+pub struct PoolJoined {
+    pub request_id: u64,
+    pub participant: Pubkey,
+    pub user_id: u64,
+    pub quantity: u64,
+    pub deposit: u64,
+    pub pool_claimed_quantity: u64,
+}
+
+#[event]
+pub struct PoolFunded {
+    pub request_id: u64,
+    pub participant: Pubkey,
+    pub share: u64,
+    pub pool_funded_participants: u64,
+}
+
+#[event]
+pub struct PoolExecuted {
+    pub request_id: u64,
+    pub offer_id: u64,
+    pub amount: u64,
+}
+
+#[event]
+pub struct PoolRefunded {
+    pub request_id: u64,
+    pub participant: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct CategoryUpdated {
+    pub name: String,
+    pub active: bool,
+    pub updated_at: u64,
+}
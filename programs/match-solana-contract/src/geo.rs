@@ -0,0 +1,258 @@
+use anchor_lang::prelude::*;
+
+use crate::constants::*;
+use crate::errors::MarketplaceError;
+use crate::states::{LegacyLocation, Location};
+
+const GEOHASH_ALPHABET: &[u8; 32] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+const LEGACY_LOCATION_SCALE: i128 = 100_000_000_000;
+
+const MICRODEGREE: i128 = 1_000_000;
+
+// Mean earth radius in meters times pi / 180, i.e. the length of one degree of arc.
+const METERS_PER_DEGREE: i128 = 111_195;
+
+impl Location {
+    pub fn new(latitude: i32, longitude: i32) -> Result<Self> {
+        let location = Location {
+            latitude,
+            longitude,
+        };
+
+        if !location.is_valid() {
+            return err!(MarketplaceError::InvalidLocation);
+        }
+
+        Ok(location)
+    }
+
+    // Buyer locations arrive already snapped to the grid, instruction data is
+    // public so precise coordinates must never be sent.
+    pub fn new_coarse(latitude: i32, longitude: i32) -> Result<Self> {
+        let location = Location::new(latitude, longitude)?;
+        let coarse = location.coarsen();
+
+        if coarse.latitude != latitude || coarse.longitude != longitude {
+            return err!(MarketplaceError::LocationNotCoarse);
+        }
+
+        Ok(location)
+    }
+
+    pub fn is_valid(&self) -> bool {
+        (-MAX_LATITUDE..=MAX_LATITUDE).contains(&self.latitude)
+            && (-MAX_LONGITUDE..=MAX_LONGITUDE).contains(&self.longitude)
+    }
+
+    // Snaps to the south-west corner of the LOCATION_GRID_SIZE cell, which is
+    // what gets published for requests.
+    pub fn coarsen(&self) -> Location {
+        Location {
+            latitude: self.latitude.div_euclid(LOCATION_GRID_SIZE) * LOCATION_GRID_SIZE,
+            longitude: self.longitude.div_euclid(LOCATION_GRID_SIZE) * LOCATION_GRID_SIZE,
+        }
+    }
+
+    pub fn latitude_microdegrees(&self) -> i128 {
+        self.latitude as i128 * MICRODEGREE / 10i128.pow(LOCATION_DECIMALS as u32)
+    }
+
+    pub fn longitude_microdegrees(&self) -> i128 {
+        self.longitude as i128 * MICRODEGREE / 10i128.pow(LOCATION_DECIMALS as u32)
+    }
+}
+
+impl TryFrom<LegacyLocation> for Location {
+    type Error = Error;
+
+    // Legacy coordinates were degrees scaled by 1e18.
+    fn try_from(legacy: LegacyLocation) -> Result<Self> {
+        let latitude = i32::try_from(legacy.latitude / LEGACY_LOCATION_SCALE)
+            .map_err(|_| MarketplaceError::InvalidLegacyAccount)?;
+        let longitude = i32::try_from(legacy.longitude / LEGACY_LOCATION_SCALE)
+            .map_err(|_| MarketplaceError::InvalidLegacyAccount)?;
+
+        Location::new(latitude, longitude).map_err(|_| MarketplaceError::InvalidLegacyAccount.into())
+    }
+}
+
+pub fn encode_geohash(location: &Location, precision: u8) -> String {
+    let mut latitude_range = (-MAX_LATITUDE as i64, MAX_LATITUDE as i64);
+    let mut longitude_range = (-MAX_LONGITUDE as i64, MAX_LONGITUDE as i64);
+    let mut geohash = String::with_capacity(precision as usize);
+    let mut even_bit = true;
+
+    for _ in 0..precision {
+        let mut index = 0;
+
+        for _ in 0..5 {
+            let (value, range) = if even_bit {
+                (location.longitude as i64, &mut longitude_range)
+            } else {
+                (location.latitude as i64, &mut latitude_range)
+            };
+
+            let mid = (range.0 + range.1).div_euclid(2);
+
+            index <<= 1;
+
+            if value >= mid {
+                index |= 1;
+                range.0 = mid;
+            } else {
+                range.1 = mid;
+            }
+
+            even_bit = !even_bit;
+        }
+
+        geohash.push(GEOHASH_ALPHABET[index] as char);
+    }
+
+    geohash
+}
+
+// Equirectangular approximation over microdegrees, with cos(mean latitude)
+// from Bhaskara I's rational approximation.
+pub fn distance_in_meters(from: &Location, to: &Location) -> u64 {
+    let from_latitude = from.latitude_microdegrees();
+    let to_latitude = to.latitude_microdegrees();
+
+    let mut delta_longitude = to.longitude_microdegrees() - from.longitude_microdegrees();
+
+    if delta_longitude > 180 * MICRODEGREE {
+        delta_longitude -= 360 * MICRODEGREE;
+    } else if delta_longitude < -180 * MICRODEGREE {
+        delta_longitude += 360 * MICRODEGREE;
+    }
+
+    let delta_latitude = to_latitude - from_latitude;
+    let mean_latitude = (from_latitude + to_latitude) / 2;
+    let mean_latitude_squared = mean_latitude * mean_latitude;
+    let quarter_turn_squared = 32_400 * MICRODEGREE * MICRODEGREE;
+
+    let cosine = (quarter_turn_squared - 4 * mean_latitude_squared) * MICRODEGREE
+        / (quarter_turn_squared + mean_latitude_squared);
+
+    let x = delta_longitude * cosine / MICRODEGREE;
+    let y = delta_latitude;
+
+    let distance = integer_sqrt((x * x + y * y) as u128) as i128;
+
+    (distance * METERS_PER_DEGREE / MICRODEGREE) as u64
+}
+
+fn integer_sqrt(value: u128) -> u128 {
+    if value < 2 {
+        return value;
+    }
+
+    let mut x = value;
+    let mut y = x.div_ceil(2);
+
+    while y < x {
+        x = y;
+        y = (x + value / x) / 2;
+    }
+
+    x
+}
+
+pub fn is_valid_geohash(geohash: &str) -> bool {
+    !geohash.is_empty()
+        && geohash.len() <= MAX_GEOHASH_PRECISION as usize
+        && geohash.bytes().all(|c| GEOHASH_ALPHABET.contains(&c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_code(error: Error) -> u32 {
+        match error {
+            Error::AnchorError(error) => error.error_code_number,
+            Error::ProgramError(error) => panic!("unexpected program error {error:?}"),
+        }
+    }
+
+    #[test]
+    fn encodes_known_geohashes() {
+        let jutland = Location::new(576_491_100, 104_074_400).unwrap();
+        let sydney = Location::new(-338_800_000, 1_512_100_000).unwrap();
+
+        assert_eq!(encode_geohash(&jutland, 11), "u4pruydqqvj");
+        assert_eq!(encode_geohash(&jutland, DEFAULT_GEOHASH_PRECISION), "u4pru");
+        assert_eq!(encode_geohash(&sydney, 7), "r3gx2b7");
+        assert_eq!(encode_geohash(&sydney, 0), "");
+    }
+
+    #[test]
+    fn measures_known_distances() {
+        let origin = Location::new(0, 0).unwrap();
+        let one_degree_north = Location::new(10_000_000, 0).unwrap();
+        let paris = Location::new(488_566_000, 23_522_000).unwrap();
+        let london = Location::new(515_074_000, -1_278_000).unwrap();
+
+        assert_eq!(distance_in_meters(&origin, &origin), 0);
+        assert_eq!(distance_in_meters(&origin, &one_degree_north), METERS_PER_DEGREE as u64);
+
+        // 343.5 km by haversine
+        let paris_london = distance_in_meters(&paris, &london);
+        assert!((341_000..=346_000).contains(&paris_london), "{paris_london}");
+        assert_eq!(paris_london, distance_in_meters(&london, &paris));
+    }
+
+    #[test]
+    fn measures_across_the_antimeridian() {
+        let east = Location::new(0, 1_799_000_000).unwrap();
+        let west = Location::new(0, -1_799_000_000).unwrap();
+
+        assert_eq!(distance_in_meters(&east, &west), 22_239);
+    }
+
+    #[test]
+    fn coarsens_to_the_south_west_corner() {
+        let location = Location::new(438_345_678, -338_345_678).unwrap();
+        let coarse = location.coarsen();
+
+        assert_eq!(coarse.latitude, 438_300_000);
+        assert_eq!(coarse.longitude, -338_400_000);
+        assert_eq!(coarse.coarsen().latitude, coarse.latitude);
+        assert_eq!(coarse.coarsen().longitude, coarse.longitude);
+
+        assert!(Location::new_coarse(coarse.latitude, coarse.longitude).is_ok());
+        assert_eq!(
+            error_code(Location::new_coarse(location.latitude, location.longitude).unwrap_err()),
+            u32::from(MarketplaceError::LocationNotCoarse)
+        );
+    }
+
+    #[test]
+    fn converts_legacy_locations() {
+        let legacy = LegacyLocation {
+            latitude: 43_830_000_000_000_000_000,
+            longitude: -33_800_000_000_000_000_000,
+        };
+        let location = Location::try_from(legacy).unwrap();
+
+        assert_eq!(location.latitude, 438_300_000);
+        assert_eq!(location.longitude, -338_000_000);
+
+        let out_of_range = LegacyLocation {
+            latitude: 91 * 10i128.pow(18),
+            longitude: 0,
+        };
+        let overflowing = LegacyLocation {
+            latitude: 0,
+            longitude: i128::MAX,
+        };
+
+        for legacy in [out_of_range, overflowing] {
+            assert_eq!(
+                error_code(Location::try_from(legacy).unwrap_err()),
+                u32::from(MarketplaceError::InvalidLegacyAccount)
+            );
+        }
+    }
+}
@@ -2,8 +2,10 @@ pub mod constants;
 pub mod events;
 pub mod states;
 pub mod errors;
+pub mod geo;
 use anchor_lang::prelude::*;
 use solana_program::system_instruction;
+use solana_program::hash::hashv;
 use anchor_spl::
     token_interface::{
         TokenAccount, Mint,
@@ -13,7 +15,7 @@ use anchor_spl::
 use pyth_solana_receiver_sdk::price_update::{get_feed_id_from_hex, PriceUpdateV2};
 use solana_program::pubkey::Pubkey;
 declare_id!("gSh52u5Nt39rb8CSHQhUhF1cSdFsL9JebSoPZmazFrZ");
-use crate::{constants::*, events::*, states::*, errors::*};
+use crate::{constants::*, events::*, states::*, errors::*, geo::*};
 use solana_program::pubkey;
 use std::mem::size_of;
 
@@ -51,13 +53,408 @@ pub mod marketplace {
         Ok(())
     }
 
+    pub fn initialize_listing_counter(ctx: Context<InitializeListingCounter>) -> Result<()> {
+        let listing_counter = &mut ctx.accounts.listing_counter;
+
+        listing_counter.current = 1;
+
+        msg!("Counters initialized: Listings");
+
+        Ok(())
+    }
+
+    pub fn initialize_admin(ctx: Context<InitializeAdmin>) -> Result<()> {
+        let admin = &mut ctx.accounts.admin;
+
+        admin.authority = ctx.accounts.authority.key();
+        admin.geohash_precision = DEFAULT_GEOHASH_PRECISION;
+        admin.bond_unstake_cooldown = DEFAULT_BOND_UNSTAKE_COOLDOWN;
+        admin.treasury = PORTAL_CLIENT_PUBKEY;
+        admin.abandon_timeout = DEFAULT_ABANDON_TIMEOUT;
+
+        msg!("Admin initialized: {}", admin.authority);
+
+        Ok(())
+    }
+
+    pub fn set_geohash_precision(ctx: Context<UpdateAdmin>, precision: u8) -> Result<()> {
+        let admin = &mut ctx.accounts.admin;
+
+        if precision == 0 || precision > MAX_GEOHASH_PRECISION {
+            return err!(MarketplaceError::InvalidGeohashPrecision);
+        }
+
+        admin.geohash_precision = precision;
+
+        msg!("Geohash precision updated: {}", precision);
+
+        Ok(())
+    }
+
+    pub fn migrate_user(ctx: Context<MigrateAccount>) -> Result<()> {
+        let accounts = &ctx.accounts;
+
+        migrate_legacy_account(&accounts.account, &accounts.authority, &accounts.system_program, |legacy: LegacyUser| {
+            Ok(User {
+                id: legacy.id,
+                username: legacy.username,
+                // plaintext phones are dropped, owners set a hash again
+                phone_hash: [0; 32],
+                location: legacy.location.try_into()?,
+                created_at: legacy.created_at,
+                updated_at: legacy.updated_at,
+                account_type: legacy.account_type,
+                authority: legacy.authority,
+                location_enabled: legacy.location_enabled,
+                roles: 0,
+                version: ACCOUNT_VERSION,
+            })
+        })?;
+
+        Ok(())
+    }
+
+    // Owners are migrated before their stores and requests, which are counted
+    // in the owner's open stores and open requests.
+    pub fn migrate_store(ctx: Context<MigrateStore>) -> Result<()> {
+        let geohash_precision = ctx.accounts.admin.geohash_precision;
+
+        let accounts = &ctx.accounts;
+
+        let store = migrate_legacy_account(&accounts.account, &accounts.authority, &accounts.system_program, |legacy: LegacyStore| {
+            if legacy.authority != accounts.user.authority {
+                return err!(MarketplaceError::InvalidLegacyAccount);
+            }
+
+            let location: Location = legacy.location.try_into()?;
+
+            Ok(Store {
+                authority: legacy.authority,
+                id: legacy.id,
+                name: legacy.name,
+                description: legacy.description,
+                // plaintext store phones are dropped, sellers share their
+                // contact per deal with `share_contact`
+                geohash: encode_geohash(&location, geohash_precision),
+                location,
+                service_radius: 0,
+                creator: legacy.authority,
+                offer_ids: vec![],
+                opening_hours: Default::default(),
+                utc_offset_minutes: 0,
+                is_away: false,
+                verification_level: 0,
+                verified_until: 0,
+                version: ACCOUNT_VERSION,
+            })
+        })?;
+
+        let geo_cell = &mut ctx.accounts.geo_cell;
+
+        if store.geohash != geo_cell.geohash {
+            return err!(MarketplaceError::InvalidGeoCell);
+        }
+
+        geo_cell.store_ids.push(store.id);
+        resize_account(geo_cell, &ctx.accounts.authority, &ctx.accounts.system_program)?;
+
+        let user_stats = &mut ctx.accounts.user_stats;
+        touch_user_stats(user_stats, ctx.accounts.user.authority, ctx.accounts.user.id);
+        user_stats.open_stores = user_stats.open_stores.checked_add(1).unwrap();
+
+        Ok(())
+    }
+
+    // Legacy requests were for a single unit, so quantities follow from the lifecycle.
+    pub fn migrate_request(ctx: Context<MigrateRequest>) -> Result<()> {
+        let geohash_precision = ctx.accounts.admin.geohash_precision;
+        let category = ctx.accounts.category.name.clone();
+
+        let accounts = &ctx.accounts;
+
+        let request = migrate_legacy_account(&accounts.account, &accounts.authority, &accounts.system_program, |legacy: LegacyRequest| {
+            if legacy.authority != accounts.user.authority {
+                return err!(MarketplaceError::InvalidLegacyAccount);
+            }
+
+            let location = Location::try_from(legacy.location)?.coarsen();
+
+            let accepted = matches!(
+                legacy.lifecycle,
+                RequestLifecycle::AcceptedByBuyer
+                    | RequestLifecycle::RequestLocked
+                    | RequestLifecycle::Paid
+                    | RequestLifecycle::Completed
+            );
+            let paid = legacy.paid
+                || matches!(legacy.lifecycle, RequestLifecycle::Paid | RequestLifecycle::Completed);
+            let fulfilled = legacy.lifecycle == RequestLifecycle::Completed;
+
+            Ok(Request {
+                authority: legacy.authority,
+                id: legacy.id,
+                name: legacy.name,
+                buyer_id: legacy.buyer_id,
+                description: legacy.description,
+                images: legacy.images,
+                sellers_price_quote: legacy.sellers_price_quote,
+                seller_ids: legacy.seller_ids,
+                offer_ids: legacy.offer_ids,
+                locked_seller_id: legacy.locked_seller_id,
+                geohash: encode_geohash(&location, geohash_precision),
+                location,
+                created_at: legacy.created_at,
+                updated_at: legacy.updated_at,
+                lifecycle: legacy.lifecycle,
+                paid,
+                accepted_offer_id: legacy.accepted_offer_id,
+                min_price: None,
+                max_price: None,
+                budget_currency: CoinPayment::Solana,
+                mode: RequestMode::Standard,
+                bidding_deadline: 0,
+                sealed_bids: false,
+                reveal_deadline: 0,
+                quantity: 1,
+                accepted_quantity: accepted as u64,
+                paid_quantity: paid as u64,
+                fulfilled_quantity: fulfilled as u64,
+                pooled: false,
+                pool_deadline: 0,
+                pool_claimed_quantity: 0,
+                pool_escrowed: 0,
+                pool_participants: 0,
+                pool_funded_participants: 0,
+                category,
+                max_seller_distance: 0,
+                precise_location_commitment: [0; 32],
+                encrypted_location: Vec::new(),
+                deposit: 0,
+                min_verification_level: 0,
+                version: ACCOUNT_VERSION,
+            })
+        })?;
+
+        if request.geohash != ctx.accounts.geo_cell.geohash {
+            return err!(MarketplaceError::InvalidGeoCell);
+        }
+
+        if request.accepted_quantity < request.quantity {
+            ctx.accounts.category.request_ids.push(request.id);
+            resize_account(&ctx.accounts.category, &ctx.accounts.authority, &ctx.accounts.system_program)?;
+            ctx.accounts.geo_cell.request_ids.push(request.id);
+            resize_account(&ctx.accounts.geo_cell, &ctx.accounts.authority, &ctx.accounts.system_program)?;
+        }
+
+        if request.lifecycle != RequestLifecycle::Completed {
+            let user_stats = &mut ctx.accounts.user_stats;
+            touch_user_stats(user_stats, ctx.accounts.user.authority, ctx.accounts.user.id);
+            user_stats.open_requests = user_stats.open_requests.checked_add(1).unwrap();
+        }
+
+        Ok(())
+    }
+
+    // Requests are migrated first, the offer's payment state is taken from its request.
+    pub fn migrate_offer(ctx: Context<MigrateOffer>) -> Result<()> {
+        let request = &ctx.accounts.request;
+        let store = &ctx.accounts.store;
+
+        let accounts = &ctx.accounts;
+
+        let offer = migrate_legacy_account(&accounts.account, &accounts.authority, &accounts.system_program, |legacy: LegacyOffer| {
+            if legacy.request_id != request.id || legacy.authority != store.authority {
+                return err!(MarketplaceError::InvalidLegacyAccount);
+            }
+
+            let is_paid = legacy.is_accepted && request.paid;
+            let is_completed = legacy.is_accepted && request.lifecycle == RequestLifecycle::Completed;
+
+            Ok(Offer {
+                authority: legacy.authority,
+                id: legacy.id,
+                request_id: legacy.request_id,
+                price: legacy.price,
+                images: legacy.images,
+                store_name: legacy.store_name,
+                seller_id: legacy.seller_id,
+                is_accepted: legacy.is_accepted,
+                created_at: legacy.created_at,
+                updated_at: legacy.updated_at,
+                price_commitment: [0; 32],
+                is_revealed: true,
+                quantity: 1,
+                is_paid,
+                is_completed,
+                store_id: store.id,
+                is_delivered: is_completed,
+                is_withdrawn: false,
+                version: ACCOUNT_VERSION,
+            })
+        })?;
+
+        let store = &mut ctx.accounts.store;
+        store.offer_ids.push(offer.id);
+        resize_account(store, &ctx.accounts.authority, &ctx.accounts.system_program)?;
+
+        Ok(())
+    }
+
+    /// A zero minimum means bonds in that currency are not accepted. With both
+    /// minimums at zero, sellers can offer without a bond.
+    pub fn set_bond_config(
+        ctx: Context<UpdateAdmin>,
+        min_bond_lamports: u64,
+        bond_mint: Pubkey,
+        min_bond_tokens: u64,
+        bond_unstake_cooldown: u64,
+    ) -> Result<()> {
+        let admin = &mut ctx.accounts.admin;
+
+        admin.min_bond_lamports = min_bond_lamports;
+        admin.bond_mint = bond_mint;
+        admin.min_bond_tokens = min_bond_tokens;
+        admin.bond_unstake_cooldown = bond_unstake_cooldown;
+
+        msg!(
+            "Bond config updated: {} lamports, {} of {}, {}s cooldown",
+            min_bond_lamports,
+            min_bond_tokens,
+            bond_mint,
+            bond_unstake_cooldown
+        );
+
+        Ok(())
+    }
+
+    pub fn set_request_deposit_config(
+        ctx: Context<UpdateAdmin>,
+        request_deposit: u64,
+        treasury: Pubkey,
+        abandon_timeout: u64,
+    ) -> Result<()> {
+        let admin = &mut ctx.accounts.admin;
+
+        admin.request_deposit = request_deposit;
+        admin.treasury = treasury;
+        admin.abandon_timeout = abandon_timeout;
+
+        msg!(
+            "Request deposit config updated: {} lamports to {}, {}s timeout",
+            request_deposit,
+            treasury,
+            abandon_timeout
+        );
+
+        Ok(())
+    }
+
+    /// The admin authority can always verify stores; `verifier` is an extra
+    /// key allowed to do so, `Pubkey::default()` to have none.
+    pub fn set_store_verifier(ctx: Context<UpdateAdmin>, verifier: Pubkey) -> Result<()> {
+        let admin = &mut ctx.accounts.admin;
+
+        admin.store_verifier = verifier;
+
+        msg!("Store verifier updated: {}", verifier);
+
+        Ok(())
+    }
+
+    /// Level 0 revokes a verification. A `verified_until` of 0 never expires.
+    pub fn verify_store(
+        ctx: Context<VerifyStore>,
+        verification_level: u8,
+        verified_until: u64,
+    ) -> Result<()> {
+        let admin = &ctx.accounts.admin;
+        let authority = &ctx.accounts.authority;
+
+        if authority.key() != admin.authority && authority.key() != admin.store_verifier {
+            return err!(MarketplaceError::UnauthorizedVerifier);
+        }
+
+        if verification_level > MAX_VERIFICATION_LEVEL {
+            return err!(MarketplaceError::InvalidVerificationLevel);
+        }
+
+        if verified_until != 0 && verified_until <= Clock::get().unwrap().unix_timestamp as u64 {
+            return err!(MarketplaceError::InvalidVerificationExpiry);
+        }
+
+        let store = &mut ctx.accounts.store;
+
+        store.verification_level = verification_level;
+        store.verified_until = verified_until;
+
+        emit!(StoreVerified {
+            store_id: store.id,
+            verification_level,
+            verified_until,
+            verified_by: authority.key(),
+        });
+
+        Ok(())
+    }
+
+    pub fn create_geo_cell(ctx: Context<CreateGeoCell>, geohash: String) -> Result<()> {
+        let geo_cell = &mut ctx.accounts.geo_cell;
+
+        if !is_valid_geohash(&geohash) {
+            return err!(MarketplaceError::InvalidGeohash);
+        }
+
+        geo_cell.geohash = geohash;
+        geo_cell.request_ids = Vec::new();
+        geo_cell.store_ids = Vec::new();
+
+        Ok(())
+    }
+
+    pub fn create_category(ctx: Context<CreateCategory>, name: String) -> Result<()> {
+        let category = &mut ctx.accounts.category;
+
+        if name.is_empty() || name.len() > MAX_CATEGORY_NAME_LEN {
+            return err!(MarketplaceError::InvalidCategoryName);
+        }
+
+        category.name = name;
+        category.active = true;
+        category.created_at = Clock::get().unwrap().unix_timestamp as u64;
+        category.updated_at = Clock::get().unwrap().unix_timestamp as u64;
+        category.request_ids = Vec::new();
+
+        emit!(CategoryUpdated {
+            name: category.name.clone(),
+            active: category.active,
+            updated_at: category.updated_at,
+        });
+
+        Ok(())
+    }
+
+    pub fn set_category_active(ctx: Context<UpdateCategory>, active: bool) -> Result<()> {
+        let category = &mut ctx.accounts.category;
+
+        category.active = active;
+        category.updated_at = Clock::get().unwrap().unix_timestamp as u64;
+
+        emit!(CategoryUpdated {
+            name: category.name.clone(),
+            active: category.active,
+            updated_at: category.updated_at,
+        });
+
+        Ok(())
+    }
+
     pub fn create_user(
         ctx: Context<CreateUser>,
         username: String,
-        phone: String,
-        latitude: i128,
-        longitude: i128,
-        account_type: AccountType,
+        phone_hash: [u8; 32],
+        latitude: i32,
+        longitude: i32,
+        roles: u8,
     ) -> Result<()> {
         let user = &mut ctx.accounts.user;
         let user_counter = &mut ctx.accounts.user_counter;
@@ -66,39 +463,49 @@ pub mod marketplace {
             return err!(MarketplaceError::UserAlreadyExists);
         }
 
-        if account_type != AccountType::Buyer && account_type != AccountType::Seller {
+        if roles == 0 || roles & !ALL_ROLES != 0 {
             return err!(MarketplaceError::InvalidAccountType);
         }
-     
 
         user.id = user_counter.current;
 
         user_counter.current = user_counter.current.checked_add(1).unwrap();
 
         user.username = username;
-        user.phone = phone;
-        user.location = Location {
-            latitude,
-            longitude,
-        };
-        user.account_type = account_type;
+        user.phone_hash = phone_hash;
+        user.location = Location::new_coarse(latitude, longitude)?;
+        user.account_type = primary_account_type(roles);
+        user.roles = roles;
+        user.version = ACCOUNT_VERSION;
         user.created_at = Clock::get().unwrap().unix_timestamp;
         user.updated_at = Clock::get().unwrap().unix_timestamp;
         user.authority = ctx.accounts.authority.key();
         user.location_enabled = true;
 
-        msg!("UserCreated: {:?}", user);
+        // the stats outlive a closed profile, so a recreated profile keeps
+        // its history under the new user id
+        let user_stats = &mut ctx.accounts.user_stats;
+        touch_user_stats(user_stats, user.authority, user.id);
+        user_stats.user_id = user.id;
+        user_stats.last_active_at = user_stats.updated_at;
+
+        let username_record = &mut ctx.accounts.username_record;
+        username_record.authority = user.authority;
+        username_record.user_id = user.id;
+        username_record.username = user.username.to_ascii_lowercase();
+        username_record.created_at = user_stats.updated_at;
+
+        msg!("UserCreated: {}", user.id);
 
         Ok(())
     }
 
     pub fn update_user(
         ctx: Context<UpdateUser>,
-        username: String,
-        phone: String,
-        latitude: i128,
-        longitude: i128,
-        account_type: AccountType,
+        phone_hash: [u8; 32],
+        latitude: i32,
+        longitude: i32,
+        roles: u8,
     ) -> Result<()> {
         let user = &mut ctx.accounts.user;
 
@@ -106,17 +513,110 @@ pub mod marketplace {
             return err!(MarketplaceError::InvalidUser);
         }
 
-        user.username = username;
-        user.phone = phone;
-        user.location = Location {
-            latitude,
-            longitude,
-        };
+        if roles == 0 || roles & !ALL_ROLES != 0 {
+            return err!(MarketplaceError::InvalidAccountType);
+        }
+
+        let user_stats = &mut ctx.accounts.user_stats;
+        touch_user_stats(user_stats, user.authority, user.id);
+
+        let removed_roles = user.roles() & !roles;
+
+        // Accepted offers that are not paid yet keep their store open, since
+        // stores cannot close or change hands while they have live offers.
+
+        if (removed_roles & ROLE_BUYER != 0 && user_stats.open_requests > 0)
+            || (removed_roles & ROLE_SELLER != 0 && user_stats.open_stores > 0)
+        {
+            return err!(MarketplaceError::RoleInUse);
+        }
+
+        user.phone_hash = phone_hash;
+        user.location = Location::new_coarse(latitude, longitude)?;
         user.updated_at = Clock::get().unwrap().unix_timestamp;
-        user.account_type = account_type;
+        user.account_type = primary_account_type(roles);
+        user.roles = roles;
         user.authority = ctx.accounts.authority.key();
 
-        msg!("UserUpdated: {:?}", user);
+        msg!("UserUpdated: {}", user.id);
+
+        Ok(())
+    }
+
+    /// Moves the user to a new username record, releasing the old one. Only the
+    /// letter case changes when both names normalize to the same record.
+    pub fn rename_user(ctx: Context<RenameUser>, username: String) -> Result<()> {
+        let user = &mut ctx.accounts.user;
+        let old_username = user.username.clone();
+        let new_username_record = &mut ctx.accounts.new_username_record;
+
+        if new_username_record.authority != Pubkey::default() && new_username_record.authority != user.authority {
+            return err!(MarketplaceError::UsernameTaken);
+        }
+
+        if !old_username.eq_ignore_ascii_case(&username) {
+            release_username_record(&ctx.accounts.old_username_record, &old_username, &ctx.accounts.authority, ctx.program_id)?;
+        }
+
+        if new_username_record.authority == Pubkey::default() {
+            new_username_record.created_at = Clock::get().unwrap().unix_timestamp as u64;
+        }
+
+        new_username_record.authority = user.authority;
+        new_username_record.user_id = user.id;
+        new_username_record.username = username.to_ascii_lowercase();
+
+        user.username = username;
+        user.updated_at = Clock::get().unwrap().unix_timestamp;
+
+        emit!(UsernameChanged {
+            user_id: user.id,
+            old_username,
+            new_username: user.username.clone(),
+        });
+
+        Ok(())
+    }
+
+    /// Reserves the current username of a user created before the registry
+    /// existed. Anyone can pay for it, so existing users can be backfilled
+    /// before new users register their names.
+    pub fn claim_username(ctx: Context<ClaimUsername>) -> Result<()> {
+        let user = &ctx.accounts.user;
+        let username_record = &mut ctx.accounts.username_record;
+
+        username_record.authority = user.authority;
+        username_record.user_id = user.id;
+        username_record.username = user.username.to_ascii_lowercase();
+        username_record.created_at = Clock::get().unwrap().unix_timestamp as u64;
+
+        Ok(())
+    }
+
+    /// Stores refuse to close or change hands while they have live accepted
+    /// offers, so a user without open requests or stores has nothing in
+    /// flight. Closing deletes the profile, the stats are kept and carry
+    /// over to a profile the wallet creates later.
+    pub fn close_user(ctx: Context<CloseUser>) -> Result<()> {
+        let user_stats = &mut ctx.accounts.user_stats;
+        touch_user_stats(user_stats, ctx.accounts.user.authority, ctx.accounts.user.id);
+
+        if user_stats.open_requests > 0 || user_stats.open_stores > 0 {
+            return err!(MarketplaceError::UserHasOpenActivity);
+        }
+
+        release_username_record(
+            &ctx.accounts.username_record,
+            &ctx.accounts.user.username,
+            &ctx.accounts.authority,
+            ctx.program_id,
+        )?;
+
+        emit!(UserClosed {
+            user_id: ctx.accounts.user.id,
+            authority: ctx.accounts.authority.key(),
+            closed_at: Clock::get().unwrap().unix_timestamp as u64,
+        });
 
         Ok(())
     }
@@ -126,723 +626,4890 @@ pub mod marketplace {
         ctx: Context<CreateStore>,
         name: String,
         description: String,
-        phone: String,
-        latitude: i128,
-        longitude: i128
+        latitude: i32,
+        longitude: i32,
+        service_radius: u64,
     ) -> Result<()> {
         let user = &mut ctx.accounts.user;
         let store_counter = &mut ctx.accounts.store_counter;
 
-        if user.account_type != AccountType::Seller {
+        if !user.has_role(ROLE_SELLER) {
             return err!(MarketplaceError::OnlySellersAllowed);
         }
 
+        check_name(&name)?;
+        check_description(&description)?;
+
         let store = &mut ctx.accounts.store;
 
         store.id = store_counter.current;
         store.name = name;
         store.description = description;
-        store.phone = phone;
-        store.location = Location {
-            latitude,
-            longitude,
-        };
+        store.location = Location::new(latitude, longitude)?;
         store.authority = ctx.accounts.authority.key();
+        store.geohash = encode_geohash(&store.location, ctx.accounts.admin.geohash_precision);
+        store.service_radius = service_radius;
+        store.creator = ctx.accounts.authority.key();
+        store.version = ACCOUNT_VERSION;
+
+        if store.geohash != ctx.accounts.geo_cell.geohash {
+            return err!(MarketplaceError::InvalidGeoCell);
+        }
 
         store_counter.current = store_counter.current.checked_add(1).unwrap();
 
+        let geo_cell = &mut ctx.accounts.geo_cell;
+        geo_cell.store_ids.push(store.id);
+        resize_account(geo_cell, &ctx.accounts.authority, &ctx.accounts.system_program)?;
+
+        let user_stats = &mut ctx.accounts.user_stats;
+        touch_user_stats(user_stats, user.authority, user.id);
+        user_stats.open_stores = user_stats.open_stores.checked_add(1).unwrap();
+
         emit!(StoreCreated {
             seller_address: *ctx.accounts.user.to_account_info().key,
             store_id: store.id,
             store_name: store.name.clone(),
-            latitude: store.location.latitude,
-            longitude: store.location.longitude,
+            latitude: ctx.accounts.user.location_enabled.then_some(store.location.latitude),
+            longitude: ctx.accounts.user.location_enabled.then_some(store.location.longitude),
+            geohash: event_geohash(&ctx.accounts.user, &store.geohash),
+            service_radius: store.service_radius,
+            opening_hours: store.opening_hours,
+            utc_offset_minutes: store.utc_offset_minutes,
+            is_away: store.is_away,
         });
 
         Ok(())
     }
 
-    pub fn create_request(
-        ctx: Context<CreateRequest>,
+    pub fn update_store(
+        ctx: Context<UpdateStore>,
         name: String,
         description: String,
-        images: Vec<String>,
-        latitude: i128,
-        longitude: i128,
+        latitude: i32,
+        longitude: i32,
+        service_radius: u64,
     ) -> Result<()> {
-        let user = &mut ctx.accounts.user;
-        let request_counter = &mut ctx.accounts.request_counter;
+        check_name(&name)?;
+        check_description(&description)?;
 
-        if user.account_type != AccountType::Buyer {
-            return err!(MarketplaceError::OnlyBuyersAllowed);
-        }
+        let store = &mut ctx.accounts.store;
+        let location = Location::new(latitude, longitude)?;
+        let geohash = encode_geohash(&location, ctx.accounts.admin.geohash_precision);
 
-        let request = &mut ctx.accounts.request;
+        if geohash != store.geohash {
+            let new_geo_cell = match ctx.accounts.new_geo_cell.as_mut() {
+                Some(new_geo_cell) if new_geo_cell.geohash == geohash => new_geo_cell,
+                _ => return err!(MarketplaceError::InvalidGeoCell),
+            };
 
-        request.id = request_counter.current;
-        request.name = name;
-        request.buyer_id = user.id;
-        request.sellers_price_quote = 0;
-        request.seller_ids = Vec::new();
-        request.offer_ids = Vec::new();
-        request.locked_seller_id = 0;
-        request.description = description;
-        request.images = images;
-        request.created_at = Clock::get().unwrap().unix_timestamp as u64;
-        request.lifecycle = RequestLifecycle::Pending;
-        request.location = Location {
-            latitude,
-            longitude,
-        };
-        request.paid = false;
-        request.updated_at = Clock::get().unwrap().unix_timestamp as u64;
-        request.authority = ctx.accounts.authority.key();
+            let store_id = store.id;
+            ctx.accounts.geo_cell.store_ids.retain(|id| *id != store_id);
+            new_geo_cell.store_ids.push(store_id);
+            resize_account(new_geo_cell, &ctx.accounts.authority, &ctx.accounts.system_program)?;
+        }
 
-        request_counter.current = request_counter.current.checked_add(1).unwrap();
+        store.name = name;
+        store.description = description;
+        store.location = location;
+        store.geohash = geohash;
+        store.service_radius = service_radius;
+        resize_account(store, &ctx.accounts.authority, &ctx.accounts.system_program)?;
 
-        emit!(RequestCreated {
-            request_id: request.id,
-            buyer_address: *ctx.accounts.user.to_account_info().key,
-            request_name: request.name.clone(),
-            latitude: request.location.latitude,
-            longitude: request.location.longitude,
-            images: request.images.clone(),
-            lifecycle: request.lifecycle.clone() as u8,
-            description: request.description.clone(),
-            buyer_id: request.buyer_id,
-            seller_ids: request.seller_ids.clone(),
-            sellers_price_quote: request.sellers_price_quote,
-            locked_seller_id: request.locked_seller_id,
-            created_at: request.created_at,
-            updated_at: request.updated_at,
-        });
+        emit!(store_updated_event(store, &ctx.accounts.user));
 
         Ok(())
     }
 
-    pub fn delete_request(ctx: Context<RemoveRequest>) -> Result<()> {
-        let request = &mut ctx.accounts.request;
-        let authority = &ctx.accounts.authority;
-    
-        if request.authority != authority.key() {
-            return err!(MarketplaceError::InvalidUser);
-        }
-    
-        if request.lifecycle != RequestLifecycle::Pending {
-            return err!(MarketplaceError::RequestLocked);
+    pub fn set_store_hours(
+        ctx: Context<SetStoreAvailability>,
+        opening_hours: [OpeningHours; 7],
+        utc_offset_minutes: i16,
+    ) -> Result<()> {
+        if opening_hours
+            .iter()
+            .any(|day| day.open >= MINUTES_PER_DAY || day.close > MINUTES_PER_DAY)
+        {
+            return err!(MarketplaceError::InvalidOpeningHours);
         }
-    
-        Ok(())
-    }
 
-    pub fn mark_request_as_completed(ctx: Context<MarkAsCompleteRequest>) -> Result<()> {
-        let request = &mut ctx.accounts.request;
-        let authority = &ctx.accounts.authority;
-    
-        if request.authority != authority.key() {
-            return err!(MarketplaceError::InvalidUser);
-        }
-    
-        if request.lifecycle != RequestLifecycle::Paid {
-            return err!(MarketplaceError::RequestNotPaid);
+        if utc_offset_minutes.abs() > MAX_UTC_OFFSET_MINUTES {
+            return err!(MarketplaceError::InvalidTimezone);
         }
 
-        if request.updated_at + TIME_TO_LOCK  > Clock::get().unwrap().unix_timestamp as u64 {
-            return err!(MarketplaceError::RequestNotLocked);
-        }
-    
-        request.lifecycle = RequestLifecycle::Completed;
-        request.updated_at = Clock::get().unwrap().unix_timestamp as u64;
-    
+        let store = &mut ctx.accounts.store;
+        store.opening_hours = opening_hours;
+        store.utc_offset_minutes = utc_offset_minutes;
+
+        emit!(store_updated_event(store, &ctx.accounts.user));
+
         Ok(())
     }
 
-    pub fn pay_for_request_token(ctx: Context<PayForRequestToken>,coin: CoinPayment) -> Result<()> {
-        let request = &mut ctx.accounts.request;
-        let offer = &mut ctx.accounts.offer;
-        let authority = &ctx.accounts.authority;
-        let mint = &ctx.accounts.mint;
-        let from_ata = &ctx.accounts.from_ata;
-        let to_ata = &ctx.accounts.to_ata;
-        let token_program = &ctx.accounts.token_program;
-        let request_payment_counter = &mut ctx.accounts.request_payment_counter;
-        let request_payment_info = &mut ctx.accounts.request_payment_info;
-    
-        if request.authority != authority.key() {
-            return err!(MarketplaceError::InvalidUser);
-        }
-    
-        if request.lifecycle != RequestLifecycle::AcceptedByBuyer {
-            return err!(MarketplaceError::RequestNotAccepted);
+    pub fn set_store_away(ctx: Context<SetStoreAvailability>, is_away: bool) -> Result<()> {
+        let store = &mut ctx.accounts.store;
+        store.is_away = is_away;
+
+        emit!(store_updated_event(store, &ctx.accounts.user));
+
+        Ok(())
+    }
+
+    /// Closes a store and returns its rent to the seller. Every offer made
+    /// through the store must be passed writable in `remaining_accounts`,
+    /// together with the requests of accepted offers that were since
+    /// cancelled and of offers that were never accepted. Offers that were
+    /// never accepted are withdrawn, freeing their slot on the request.
+    pub fn close_store(ctx: Context<CloseStore>) -> Result<()> {
+        let store = &ctx.accounts.store;
+        let (finished, idle) = verify_store_offers(store, ctx.remaining_accounts, ctx.program_id)?;
+
+        if store.offer_ids.iter().any(|id| !finished.contains(id) && !idle.contains(id)) {
+            return err!(MarketplaceError::StoreHasLiveOffers);
         }
 
-        if request.updated_at + TIME_TO_LOCK  > Clock::get().unwrap().unix_timestamp as u64 {
-            return err!(MarketplaceError::RequestNotLocked);
+        withdraw_idle_offers(store, ctx.remaining_accounts, &idle)?;
+
+        let store_id = store.id;
+        ctx.accounts.geo_cell.store_ids.retain(|id| *id != store_id);
+
+        let user_stats = &mut ctx.accounts.user_stats;
+        user_stats.open_stores = user_stats.open_stores.checked_sub(1).unwrap();
+
+        emit!(StoreClosed {
+            seller_address: *ctx.accounts.user.to_account_info().key,
+            store_id: store.id,
+            store_name: store.name.clone(),
+            geohash: event_geohash(&ctx.accounts.user, &store.geohash),
+        });
+
+        Ok(())
+    }
+
+    /// Drops completed offers, and offers on cancelled requests, from the
+    /// store so `close_store` does not need to be passed them. Offers that
+    /// were never accepted are withdrawn and dropped too, which frees room
+    /// once the store reaches `MAX_OFFERS_PER_STORE`.
+    pub fn prune_store_offers(ctx: Context<PruneStoreOffers>) -> Result<()> {
+        let store = &mut ctx.accounts.store;
+        let (finished, idle) = verify_store_offers(store, ctx.remaining_accounts, ctx.program_id)?;
+
+        withdraw_idle_offers(store, ctx.remaining_accounts, &idle)?;
+
+        store.offer_ids.retain(|id| !finished.contains(id) && !idle.contains(id));
+
+        Ok(())
+    }
+
+    /// Hands a store to another seller, who must co-sign. Takes the same
+    /// `remaining_accounts` as `close_store`: the store cannot change hands
+    /// while accepted offers are in progress, and offers that were never
+    /// accepted are withdrawn since they stay attributed to the old owner.
+    pub fn transfer_store(ctx: Context<TransferStore>) -> Result<()> {
+        let store = &mut ctx.accounts.store;
+        let new_user = &ctx.accounts.new_user;
+
+        if !new_user.has_role(ROLE_SELLER) {
+            return err!(MarketplaceError::OnlySellersAllowed);
         }
 
-        if !offer.is_accepted {
-            return err!(MarketplaceError::RequestNotAccepted);
+        if new_user.authority == store.authority {
+            return err!(MarketplaceError::InvalidUser);
         }
 
+        let (finished, idle) = verify_store_offers(store, ctx.remaining_accounts, ctx.program_id)?;
 
-        if request.locked_seller_id != offer.seller_id {
-            return err!(MarketplaceError::InvalidSeller);
+        if store.offer_ids.iter().any(|id| !finished.contains(id) && !idle.contains(id)) {
+            return err!(MarketplaceError::StoreHasLiveOffers);
         }
 
-        if request.paid {
-            return err!(MarketplaceError::RequestAlreadyPaid);
-        }
+        withdraw_idle_offers(store, ctx.remaining_accounts, &idle)?;
 
-        request.paid = true;
-        request.lifecycle = RequestLifecycle::Paid;
-        request_payment_info.authority = authority.key();
-        request_payment_info.request_id = request.id;
-        request_payment_info.buyer_id = request.buyer_id;
-        request_payment_info.price = offer.price;
-        request_payment_info.seller_id = offer.seller_id;
-        request_payment_info.created_at = Clock::get().unwrap().unix_timestamp as u64;
-        request_payment_info.updated_at = Clock::get().unwrap().unix_timestamp as u64;
-        request_payment_info.token = coin.clone();
-        request_payment_info.id = request_payment_counter.current;
-        request_payment_info.seller_authority = offer.authority;
-        request_payment_counter.current = request_payment_counter.current.checked_add(1).unwrap();
-        
+        store.offer_ids.clear();
+        store.authority = new_user.authority;
 
+        let user_stats = &mut ctx.accounts.user_stats;
+        user_stats.open_stores = user_stats.open_stores.checked_sub(1).unwrap();
 
-        match coin {
-            CoinPayment::Pyusdt => {
-                let price_update = &mut ctx.accounts.price_update;
-                let current_price = price_update.get_price_no_older_than(
-                    &Clock::get()?,
-                    MAXIMUM_AGE,
-                    &get_feed_id_from_hex(SOL_USD_PRICE_FEED)?,
-                )?;
-        
-                let sol_price_in_usd = current_price.price as u64;
-                let sol_price_for_offer = offer.price;
-        
-                let sol_amount_in_usd = sol_price_for_offer * sol_price_in_usd;
-                
-                let pyusd_amount = sol_amount_in_usd / 100000000000;
+        let new_user_stats = &mut ctx.accounts.new_user_stats;
+        touch_user_stats(new_user_stats, new_user.authority, new_user.id);
+        new_user_stats.open_stores = new_user_stats.open_stores.checked_add(1).unwrap();
+        // verification vouches for the business owner, not the storefront
+        store.verification_level = 0;
+        store.verified_until = 0;
+
+        emit!(StoreTransferred {
+            seller_address: *new_user.to_account_info().key,
+            previous_seller_address: *ctx.accounts.user.to_account_info().key,
+            store_id: store.id,
+            store_name: store.name.clone(),
+            latitude: new_user.location_enabled.then_some(store.location.latitude),
+            longitude: new_user.location_enabled.then_some(store.location.longitude),
+            geohash: event_geohash(new_user, &store.geohash),
+            service_radius: store.service_radius,
+        });
 
-                request_payment_info.amount = pyusd_amount;
+        Ok(())
+    }
 
-                let accounts = TransferChecked {
-                    from: from_ata.to_account_info(),
-                    to: to_ata.to_account_info(),
-                    authority: authority.to_account_info(),
-                    mint: mint.to_account_info(),
-                };
-                
-                let ctx = CpiContext::new(
-                    token_program.to_account_info(),
-                    accounts
-                );
+    pub fn create_listing(
+        ctx: Context<CreateListing>,
+        title: String,
+        description: String,
+        images: Vec<String>,
+        price: u64,
+        stock: u64,
+    ) -> Result<()> {
+        check_name(&title)?;
+        check_description(&description)?;
+        check_images(&images)?;
 
-                transfer_checked(ctx, pyusd_amount, mint.decimals)?;
-            },
-            _ => {
-                return err!(MarketplaceError::InvalidCoinPayment);
-            },
-            
+        if !ctx.accounts.category.active {
+            return err!(MarketplaceError::CategoryInactive);
         }
-        
+
+        let listing = &mut ctx.accounts.listing;
+        let listing_counter = &mut ctx.accounts.listing_counter;
+
+        listing.id = listing_counter.current;
+        listing.store_id = ctx.accounts.store.id;
+        listing.title = title;
+        listing.description = description;
+        listing.images = images;
+        listing.price = price;
+        listing.stock = stock;
+        listing.category = ctx.accounts.category.name.clone();
+        listing.created_at = Clock::get().unwrap().unix_timestamp as u64;
+        listing.updated_at = Clock::get().unwrap().unix_timestamp as u64;
+
+        listing_counter.current = listing_counter.current.checked_add(1).unwrap();
+
+        emit!(ListingCreated {
+            listing_id: listing.id,
+            store_id: listing.store_id,
+            title: listing.title.clone(),
+            price: listing.price,
+            stock: listing.stock,
+            category: listing.category.clone(),
+            created_at: listing.created_at,
+        });
+
         Ok(())
     }
 
+    pub fn update_listing(
+        ctx: Context<UpdateListing>,
+        title: String,
+        description: String,
+        images: Vec<String>,
+        price: u64,
+        stock: u64,
+    ) -> Result<()> {
+        check_name(&title)?;
+        check_description(&description)?;
+        check_images(&images)?;
 
-    pub fn pay_for_request(ctx: Context<PayForRequest>,coin: CoinPayment) -> Result<()> {
+        let listing = &mut ctx.accounts.listing;
+
+        listing.title = title;
+        listing.description = description;
+        listing.images = images;
+        listing.price = price;
+        listing.stock = stock;
+        listing.updated_at = Clock::get().unwrap().unix_timestamp as u64;
+        resize_account(listing, &ctx.accounts.authority, &ctx.accounts.system_program)?;
+
+        emit!(ListingUpdated {
+            listing_id: listing.id,
+            store_id: listing.store_id,
+            title: listing.title.clone(),
+            price: listing.price,
+            stock: listing.stock,
+            updated_at: listing.updated_at,
+        });
+
+        Ok(())
+    }
+
+    /// Removes a listing from its store and refunds its rent. Purchases made
+    /// from it are separate requests and are not affected.
+    pub fn close_listing(ctx: Context<CloseListing>) -> Result<()> {
+        let listing = &ctx.accounts.listing;
+
+        emit!(ListingClosed {
+            listing_id: listing.id,
+            store_id: listing.store_id,
+            closed_at: Clock::get().unwrap().unix_timestamp as u64,
+        });
+
+        Ok(())
+    }
+
+    /// Buys `quantity` units of a listing in one step. The request, accepted
+    /// offer and payment receipt are created as if the buyer had gone through
+    /// `create_request`, `create_offer`, `accept_offer` and a payment, so the
+    /// purchase is completed with `mark_request_as_completed`. The request is
+    /// placed at the store, coarsened like any request, and in the listing's
+    /// category.
+    pub fn buy_listing(ctx: Context<BuyListing>, quantity: u64, coin: CoinPayment) -> Result<()> {
+        let user = &ctx.accounts.user;
+        let seller = &ctx.accounts.seller;
+        let listing = &mut ctx.accounts.listing;
+        let store = &mut ctx.accounts.store;
         let request = &mut ctx.accounts.request;
         let offer = &mut ctx.accounts.offer;
-        let to = &mut ctx.accounts.to;
         let authority = &ctx.accounts.authority;
-        let request_payment_counter = &mut ctx.accounts.request_payment_counter;
-        let request_payment_info = &mut ctx.accounts.request_payment_info;
-    
-        if request.authority != authority.key() {
-            return err!(MarketplaceError::InvalidUser);
+        let now = Clock::get().unwrap().unix_timestamp as u64;
+
+        if !user.has_role(ROLE_BUYER) {
+            return err!(MarketplaceError::OnlyBuyersAllowed);
         }
-    
-        if request.lifecycle != RequestLifecycle::AcceptedByBuyer {
-            return err!(MarketplaceError::RequestNotAccepted);
+
+        if quantity == 0 {
+            return err!(MarketplaceError::InvalidQuantity);
         }
 
-        if request.updated_at + TIME_TO_LOCK  > Clock::get().unwrap().unix_timestamp as u64 {
-            return err!(MarketplaceError::RequestNotLocked);
+        if quantity > listing.stock {
+            return err!(MarketplaceError::ListingOutOfStock);
         }
 
-        if !offer.is_accepted {
-            return err!(MarketplaceError::RequestNotAccepted);
+        if store.is_away {
+            return err!(MarketplaceError::StoreAway);
         }
 
+        listing.stock = listing.stock.checked_sub(quantity).unwrap();
+        listing.updated_at = now;
 
-        if request.locked_seller_id != offer.seller_id {
-            return err!(MarketplaceError::InvalidSeller);
-        }
+        let request_counter = &mut ctx.accounts.request_counter;
 
-        if request.paid {
-            return err!(MarketplaceError::RequestAlreadyPaid);
-        }
+        request.id = request_counter.current;
+        request.authority = authority.key();
+        request.name = listing.title.clone();
+        request.buyer_id = user.id;
+        request.description = listing.description.clone();
+        request.images = listing.images.clone();
+        request.seller_ids = vec![seller.id];
+        request.location = store.location.coarsen();
+        request.created_at = now;
+        request.budget_currency = coin.clone();
+        request.quantity = quantity;
+        request.geohash = store.geohash.clone();
+        request.category = listing.category.clone();
+        request.version = ACCOUNT_VERSION;
+        request_counter.current = request_counter.current.checked_add(1).unwrap();
 
-        request.paid = true;
-        request.lifecycle = RequestLifecycle::Paid;
-        request_payment_info.authority = authority.key();
-        request_payment_info.request_id = request.id;
-        request_payment_info.buyer_id = request.buyer_id;
-        request_payment_info.price = offer.price;
-        request_payment_info.seller_authority = offer.authority;
-        request_payment_info.seller_id = offer.seller_id;
-        request_payment_info.created_at = Clock::get().unwrap().unix_timestamp as u64;
-        request_payment_info.updated_at = Clock::get().unwrap().unix_timestamp as u64;
-        request_payment_info.token = coin.clone();
-        request_payment_info.id = request_payment_counter.current;
-        request_payment_counter.current = request_payment_counter.current.checked_add(1).unwrap();
+        let offer_counter = &mut ctx.accounts.offer_counter;
 
-        match coin {
-            CoinPayment::Solana => {
-                let transfer_instruction = system_instruction::transfer(authority.key, to.key, offer.price);
+        offer.id = offer_counter.current;
+        offer.authority = store.authority;
+        offer.request_id = request.id;
+        offer.price = listing.price;
+        offer.images = listing.images.clone();
+        offer.store_name = offer_store_name(store);
+        offer.seller_id = seller.id;
+        offer.created_at = now;
+        offer.is_revealed = true;
+        offer.quantity = quantity;
+        offer.store_id = store.id;
+        offer.version = ACCOUNT_VERSION;
+        offer_counter.current = offer_counter.current.checked_add(1).unwrap();
 
-                request_payment_info.amount = offer.price;
+        push_store_offer(store, offer.id)?;
+        resize_account(store, authority, &ctx.accounts.system_program)?;
 
-                anchor_lang::solana_program::program::invoke_signed(
+        lock_accepted_offer(request, offer, now);
+
+        let total_price = offer.price.checked_mul(quantity).unwrap();
+
+        let amount = match coin {
+            CoinPayment::Solana => {
+                let transfer_instruction = system_instruction::transfer(authority.key, ctx.accounts.to.key, total_price);
+
+                anchor_lang::solana_program::program::invoke(
                     &transfer_instruction,
                     &[
                         authority.to_account_info(),
-                        to.clone(),
+                        ctx.accounts.to.clone(),
                         ctx.accounts.system_program.to_account_info(),
                     ],
-                    &[],
                 )?;
+
+                total_price
             }
-            _ => {
-                return err!(MarketplaceError::InvalidCoinPayment);
-            },
-        }
-        Ok(())
-    }
+            CoinPayment::Pyusdt => {
+                let (Some(from_ata), Some(to_ata), Some(mint), Some(token_program), Some(price_update)) = (
+                    ctx.accounts.from_ata.as_ref(),
+                    ctx.accounts.to_ata.as_ref(),
+                    ctx.accounts.mint.as_ref(),
+                    ctx.accounts.token_program.as_ref(),
+                    ctx.accounts.price_update.as_ref(),
+                ) else {
+                    return err!(MarketplaceError::InvalidCoinPayment);
+                };
 
-    pub fn toggle_location(ctx: Context<ToggleLocation>, enabled: bool) -> Result<()> {
-        let  user  = &mut ctx.accounts.user;
+                let pyusd_amount = lamports_to_pyusd(price_update, total_price)?;
 
-        user.location_enabled = enabled;
+                let accounts = TransferChecked {
+                    from: from_ata.to_account_info(),
+                    to: to_ata.to_account_info(),
+                    authority: authority.to_account_info(),
+                    mint: mint.to_account_info(),
+                };
 
-        emit!(LocationEnabled {
-            location_enabled: enabled,
-            user_id: user.id,
-        });
-        
-        Ok(())
-    }
+                transfer_checked(
+                    CpiContext::new(token_program.to_account_info(), accounts),
+                    pyusd_amount,
+                    mint.decimals,
+                )?;
 
-    pub fn get_location_preference(ctx: Context<GetLocationPreference>) -> Result<bool> {
-        let user = &ctx.accounts.user;
-        
-        Ok(user.location_enabled)
-    }
+                pyusd_amount
+            }
+        };
 
+        let buyer_stats = &mut ctx.accounts.buyer_stats;
+        touch_user_stats(buyer_stats, request.authority, request.buyer_id);
+        buyer_stats.requests_created = buyer_stats.requests_created.checked_add(1).unwrap();
+        buyer_stats.open_requests = buyer_stats.open_requests.checked_add(1).unwrap();
+        buyer_stats.acceptances_given = buyer_stats.acceptances_given.checked_add(1).unwrap();
 
-    pub fn create_offer(
-        ctx: Context<CreateOffer>,
-        price: u64,
-        images: Vec<String>,
-        store_name: String,
-    ) -> Result<()> {
-        let user = &mut ctx.accounts.user;
-        let offer_counter = &mut ctx.accounts.offer_counter;
+        let seller_stats = &mut ctx.accounts.seller_stats;
+        touch_user_stats(seller_stats, offer.authority, offer.seller_id);
+        seller_stats.offers_created = seller_stats.offers_created.checked_add(1).unwrap();
+        seller_stats.offers_accepted = seller_stats.offers_accepted.checked_add(1).unwrap();
 
-        if user.account_type != AccountType::Seller {
-            return err!(MarketplaceError::OnlySellersAllowed);
-        }
+        record_payment_stats(buyer_stats, seller_stats, request, offer, &coin, amount);
+        mark_offer_paid(request, offer, now);
+        record_request_payment(
+            &mut ctx.accounts.request_payment_info,
+            &mut ctx.accounts.request_payment_counter,
+            request,
+            offer,
+            coin,
+            amount,
+        );
 
-        let request = &mut ctx.accounts.request;
+        emit!(ListingPurchased {
+            listing_id: listing.id,
+            buyer_address: *ctx.accounts.user.to_account_info().key,
+            request_id: request.id,
+            offer_id: offer.id,
+            quantity,
+            price: offer.price,
+            stock: listing.stock,
+        });
 
-        if Clock::get().unwrap().unix_timestamp as u64 > request.updated_at + TIME_TO_LOCK
-            && request.lifecycle == RequestLifecycle::AcceptedByBuyer
-        {
-            return err!(MarketplaceError::RequestLocked);
+        Ok(())
+    }
+
+    pub fn set_store_delegate(
+        ctx: Context<SetStoreDelegate>,
+        delegate: Pubkey,
+        permissions: u8,
+    ) -> Result<()> {
+        if permissions == 0 || permissions & !DELEGATE_ALL_PERMISSIONS != 0 {
+            return err!(MarketplaceError::InvalidDelegatePermissions);
         }
 
-        let offer = &mut ctx.accounts.offer;
+        if delegate == ctx.accounts.store.authority {
+            return err!(MarketplaceError::InvalidUser);
+        }
 
-        offer.id = offer_counter.current;
-        offer.price = price;
-        offer.images = images;
-        offer.request_id = request.id;
-        offer.store_name = store_name;
-        offer.seller_id = user.id;
-        offer.is_accepted = false;
-        offer.created_at = Clock::get().unwrap().unix_timestamp as u64;
-        offer.updated_at = Clock::get().unwrap().unix_timestamp as u64;
-        offer.authority = ctx.accounts.authority.key();
+        let store_delegate = &mut ctx.accounts.store_delegate;
+        let now = Clock::get().unwrap().unix_timestamp as u64;
 
-        if request.lifecycle == RequestLifecycle::Pending {
-            request.lifecycle = RequestLifecycle::AcceptedBySeller;
+        if store_delegate.created_at == 0 {
+            store_delegate.created_at = now;
         }
 
-        request.seller_ids.push(offer.seller_id);
+        store_delegate.store_id = ctx.accounts.store.id;
+        store_delegate.delegate = delegate;
+        store_delegate.granted_by = ctx.accounts.authority.key();
+        store_delegate.permissions = permissions;
+        store_delegate.updated_at = now;
 
-        emit!(OfferCreated {
-            offer_id: offer.id,
-            seller_address: *ctx.accounts.user.to_account_info().key,
-            store_name: offer.store_name.clone(),
-            price: offer.price,
-            request_id: offer.request_id,
-            images: offer.images.clone(),
-            seller_id: offer.seller_id,
-            seller_ids: request.seller_ids.clone(),
+        emit!(StoreDelegateSet {
+            store_id: store_delegate.store_id,
+            delegate,
+            permissions,
         });
 
-        offer_counter.current = offer_counter.current.checked_add(1).unwrap();
+        Ok(())
+    }
+
+    pub fn remove_store_delegate(ctx: Context<RemoveStoreDelegate>) -> Result<()> {
+        emit!(StoreDelegateRemoved {
+            store_id: ctx.accounts.store.id,
+            delegate: ctx.accounts.store_delegate.delegate,
+        });
 
         Ok(())
     }
 
-    pub fn accept_offer(ctx: Context<AcceptOffer>) -> Result<()> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_request(
+        ctx: Context<CreateRequest>,
+        name: String,
+        description: String,
+        images: Vec<String>,
+        latitude: i32,
+        longitude: i32,
+        min_price: Option<u64>,
+        max_price: Option<u64>,
+        budget_currency: CoinPayment,
+        mode: RequestMode,
+        bidding_deadline: u64,
+        sealed_bids: bool,
+        reveal_deadline: u64,
+        quantity: u64,
+        pooled: bool,
+        pool_deadline: u64,
+        max_seller_distance: u64,
+        precise_location_commitment: [u8; 32],
+        min_verification_level: u8,
+    ) -> Result<()> {
         let user = &mut ctx.accounts.user;
-        let offer = &mut ctx.accounts.offer;
-        let request = &mut ctx.accounts.request;
+        let request_counter = &mut ctx.accounts.request_counter;
 
-        if user.account_type != AccountType::Buyer {
+        if !user.has_role(ROLE_BUYER) {
             return err!(MarketplaceError::OnlyBuyersAllowed);
         }
 
-        if request.buyer_id != user.id {
-            return err!(MarketplaceError::UnauthorizedBuyer);
+        if !ctx.accounts.category.active {
+            return err!(MarketplaceError::CategoryInactive);
         }
 
-        if offer.is_accepted {
-            return err!(MarketplaceError::OfferAlreadyAccepted);
+        check_name(&name)?;
+        check_description(&description)?;
+        check_images(&images)?;
+
+        if quantity == 0 {
+            return err!(MarketplaceError::InvalidQuantity);
         }
 
-        if Clock::get().unwrap().unix_timestamp as u64 > request.updated_at + TIME_TO_LOCK
-            && request.lifecycle == RequestLifecycle::AcceptedByBuyer
-        {
-            return err!(MarketplaceError::RequestLocked);
+        if let (Some(min_price), Some(max_price)) = (min_price, max_price) {
+            if min_price > max_price {
+                return err!(MarketplaceError::InvalidBudget);
+            }
         }
 
+        if bidding_deadline != 0 && bidding_deadline <= Clock::get().unwrap().unix_timestamp as u64 {
+            return err!(MarketplaceError::InvalidBiddingDeadline);
+        }
 
-        if request.seller_ids.len() != ctx.remaining_accounts.len() {
-            return err!(MarketplaceError::IncorrectNumberOfSellers);
+        if mode == RequestMode::ReverseAuction && bidding_deadline == 0 {
+            return err!(MarketplaceError::InvalidBiddingDeadline);
         }
 
-        for account_info in ctx.remaining_accounts.iter() {
-            let mut data = account_info.try_borrow_mut_data()?;
+        if sealed_bids && bidding_deadline == 0 {
+            return err!(MarketplaceError::InvalidBiddingDeadline);
+        }
 
-            let mut previous_offer = Offer::try_deserialize(&mut data.as_ref()).expect("Error Deserializing Data");
-            
-            if previous_offer.is_accepted  && previous_offer.request_id == request.id {
-                previous_offer.is_accepted = false;
-                previous_offer.try_serialize(&mut data.as_mut())?;
-                emit!(OfferAccepted {
-                    offer_id: previous_offer.id,
-                    buyer_address: *ctx.accounts.user.to_account_info().key,
-                    is_accepted: false,
-                });
-            }
+        if sealed_bids && reveal_deadline <= bidding_deadline {
+            return err!(MarketplaceError::InvalidRevealDeadline);
         }
 
-        offer.is_accepted = true;
-        offer.updated_at = Clock::get().unwrap().unix_timestamp as u64;
-        request.offer_ids.push(offer.id);
-        request.locked_seller_id = offer.seller_id;
-        request.sellers_price_quote = offer.price;
-        request.accepted_offer_id = offer.id;
-        request.lifecycle = RequestLifecycle::AcceptedByBuyer;
+        if pooled && pool_deadline <= Clock::get().unwrap().unix_timestamp as u64 {
+            return err!(MarketplaceError::InvalidPoolDeadline);
+        }
+
+        if min_verification_level > MAX_VERIFICATION_LEVEL {
+            return err!(MarketplaceError::InvalidVerificationLevel);
+        }
+
+        let request = &mut ctx.accounts.request;
+
+        request.id = request_counter.current;
+        request.name = name;
+        request.buyer_id = user.id;
+        request.sellers_price_quote = 0;
+        request.seller_ids = Vec::new();
+        request.offer_ids = Vec::new();
+        request.locked_seller_id = 0;
+        request.description = description;
+        request.images = images;
+        request.created_at = Clock::get().unwrap().unix_timestamp as u64;
+        request.lifecycle = RequestLifecycle::Pending;
+        request.location = Location::new_coarse(latitude, longitude)?;
+        request.precise_location_commitment = precise_location_commitment;
+        request.encrypted_location = Vec::new();
+        request.paid = false;
         request.updated_at = Clock::get().unwrap().unix_timestamp as u64;
+        request.authority = ctx.accounts.authority.key();
+        request.min_price = min_price;
+        request.max_price = max_price;
+        request.budget_currency = budget_currency;
+        request.mode = mode;
+        request.bidding_deadline = bidding_deadline;
+        request.sealed_bids = sealed_bids;
+        request.reveal_deadline = if sealed_bids { reveal_deadline } else { 0 };
+        request.quantity = quantity;
+        request.accepted_quantity = 0;
+        request.paid_quantity = 0;
+        request.fulfilled_quantity = 0;
+        request.pooled = pooled;
+        request.pool_deadline = if pooled { pool_deadline } else { 0 };
+        request.pool_claimed_quantity = 0;
+        request.pool_escrowed = 0;
+        request.pool_participants = 0;
+        request.pool_funded_participants = 0;
+        request.deposit = ctx.accounts.admin.request_deposit;
+        request.category = ctx.accounts.category.name.clone();
+        request.geohash = encode_geohash(&request.location, ctx.accounts.admin.geohash_precision);
+        request.max_seller_distance = max_seller_distance;
+        request.min_verification_level = min_verification_level;
+        request.version = ACCOUNT_VERSION;
 
-        emit!(RequestAccepted {
+        if request.geohash != ctx.accounts.geo_cell.geohash {
+            return err!(MarketplaceError::InvalidGeoCell);
+        }
+
+        request_counter.current = request_counter.current.checked_add(1).unwrap();
+
+        if request.deposit > 0 {
+            let transfer_instruction = system_instruction::transfer(
+                ctx.accounts.authority.key,
+                &request.key(),
+                request.deposit,
+            );
+
+            anchor_lang::solana_program::program::invoke(
+                &transfer_instruction,
+                &[
+                    ctx.accounts.authority.to_account_info(),
+                    request.to_account_info(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+            )?;
+        }
+
+        let user_stats = &mut ctx.accounts.user_stats;
+        touch_user_stats(user_stats, request.authority, request.buyer_id);
+        user_stats.requests_created = user_stats.requests_created.checked_add(1).unwrap();
+        user_stats.open_requests = user_stats.open_requests.checked_add(1).unwrap();
+        user_stats.last_active_at = user_stats.updated_at;
+
+        let category = &mut ctx.accounts.category;
+        category.request_ids.push(request.id);
+        resize_account(category, &ctx.accounts.authority, &ctx.accounts.system_program)?;
+
+        let geo_cell = &mut ctx.accounts.geo_cell;
+        geo_cell.request_ids.push(request.id);
+        resize_account(geo_cell, &ctx.accounts.authority, &ctx.accounts.system_program)?;
+
+        emit!(RequestCreated {
+            request_id: request.id,
+            buyer_address: *ctx.accounts.user.to_account_info().key,
+            request_name: request.name.clone(),
+            latitude: ctx.accounts.user.location_enabled.then_some(request.location.latitude),
+            longitude: ctx.accounts.user.location_enabled.then_some(request.location.longitude),
+            images: request.images.clone(),
+            lifecycle: request.lifecycle.clone() as u8,
+            description: request.description.clone(),
+            buyer_id: request.buyer_id,
+            seller_ids: request.seller_ids.clone(),
+            sellers_price_quote: request.sellers_price_quote,
+            locked_seller_id: request.locked_seller_id,
+            created_at: request.created_at,
+            updated_at: request.updated_at,
+            min_price: request.min_price,
+            max_price: request.max_price,
+            budget_currency: request.budget_currency.clone(),
+            mode: request.mode.clone(),
+            bidding_deadline: request.bidding_deadline,
+            sealed_bids: request.sealed_bids,
+            reveal_deadline: request.reveal_deadline,
+            quantity: request.quantity,
+            pooled: request.pooled,
+            pool_deadline: request.pool_deadline,
+            category: request.category.clone(),
+            geohash: event_geohash(&ctx.accounts.user, &request.geohash),
+            max_seller_distance: request.max_seller_distance,
+            min_verification_level: request.min_verification_level,
+        });
+
+        Ok(())
+    }
+
+    /// Hands the precise request location to the seller of an accepted offer.
+    /// The buyer encrypted it under their own key when creating the request and
+    /// committed to the ciphertext; that key is shared here encrypted off-chain
+    /// to the seller, in an account per offer so every seller keeps theirs.
+    pub fn share_precise_location(
+        ctx: Context<SharePreciseLocation>,
+        encrypted_location: Vec<u8>,
+        encrypted_location_key: Vec<u8>,
+    ) -> Result<()> {
+        let request = &mut ctx.accounts.request;
+        let offer = &ctx.accounts.offer;
+
+        if request.lifecycle != RequestLifecycle::AcceptedByBuyer
+            && request.lifecycle != RequestLifecycle::RequestLocked
+            && request.lifecycle != RequestLifecycle::Paid
+        {
+            return err!(MarketplaceError::RequestNotAccepted);
+        }
+
+        if offer.request_id != request.id || !offer.is_accepted {
+            return err!(MarketplaceError::InvalidOffer);
+        }
+
+        if encrypted_location.is_empty() || encrypted_location.len() > MAX_ENCRYPTED_LOCATION_LEN {
+            return err!(MarketplaceError::InvalidEncryptedLocation);
+        }
+
+        if encrypted_location_key.is_empty()
+            || encrypted_location_key.len() > MAX_ENCRYPTED_LOCATION_KEY_LEN
+        {
+            return err!(MarketplaceError::InvalidEncryptedLocation);
+        }
+
+        if hashv(&[&encrypted_location]).to_bytes() != request.precise_location_commitment {
+            return err!(MarketplaceError::LocationCommitmentMismatch);
+        }
+
+        request.encrypted_location = encrypted_location;
+        request.updated_at = Clock::get().unwrap().unix_timestamp as u64;
+
+        let location_key_share = &mut ctx.accounts.location_key_share;
+        location_key_share.request_id = request.id;
+        location_key_share.offer_id = offer.id;
+        location_key_share.recipient = offer.authority;
+        location_key_share.encrypted_location_key = encrypted_location_key;
+        location_key_share.updated_at = request.updated_at;
+
+        emit!(PreciseLocationShared {
             request_id: request.id,
             offer_id: offer.id,
             seller_id: offer.seller_id,
-            updated_at: request.updated_at,
-            sellers_price_quote: request.sellers_price_quote,
+            encrypted_location: request.encrypted_location.clone(),
+            encrypted_location_key: location_key_share.encrypted_location_key.clone(),
         });
 
-        emit!(OfferAccepted {
+        Ok(())
+    }
+
+    /// Stores contact details encrypted off-chain to the counterparty of an
+    /// accepted offer, either buyer to seller or seller to buyer.
+    pub fn share_contact(ctx: Context<ShareContact>, encrypted_contact: Vec<u8>) -> Result<()> {
+        check_contact_parties(
+            &ctx.accounts.request,
+            &ctx.accounts.offer,
+            &ctx.accounts.sender.key(),
+            &ctx.accounts.recipient.key(),
+        )?;
+
+        if encrypted_contact.is_empty() || encrypted_contact.len() > MAX_ENCRYPTED_CONTACT_LEN {
+            return err!(MarketplaceError::InvalidEncryptedContact);
+        }
+
+        let contact_share = &mut ctx.accounts.contact_share;
+
+        contact_share.request_id = ctx.accounts.request.id;
+        contact_share.sender = ctx.accounts.sender.key();
+        contact_share.recipient = ctx.accounts.recipient.key();
+        contact_share.encrypted_contact = encrypted_contact;
+        contact_share.created_at = Clock::get().unwrap().unix_timestamp as u64;
+        contact_share.updated_at = Clock::get().unwrap().unix_timestamp as u64;
+
+        emit!(ContactShared {
+            request_id: contact_share.request_id,
+            sender: contact_share.sender,
+            recipient: contact_share.recipient,
+            updated_at: contact_share.updated_at,
+        });
+
+        Ok(())
+    }
+
+    pub fn rotate_contact(ctx: Context<RotateContact>, encrypted_contact: Vec<u8>) -> Result<()> {
+        check_contact_parties(
+            &ctx.accounts.request,
+            &ctx.accounts.offer,
+            &ctx.accounts.sender.key(),
+            &ctx.accounts.contact_share.recipient,
+        )?;
+
+        if encrypted_contact.is_empty() || encrypted_contact.len() > MAX_ENCRYPTED_CONTACT_LEN {
+            return err!(MarketplaceError::InvalidEncryptedContact);
+        }
+
+        let contact_share = &mut ctx.accounts.contact_share;
+
+        contact_share.encrypted_contact = encrypted_contact;
+        contact_share.updated_at = Clock::get().unwrap().unix_timestamp as u64;
+
+        emit!(ContactShared {
+            request_id: contact_share.request_id,
+            sender: contact_share.sender,
+            recipient: contact_share.recipient,
+            updated_at: contact_share.updated_at,
+        });
+
+        Ok(())
+    }
+
+    /// Cancels a request that sellers have already offered on. The deposit is
+    /// forfeited to the treasury.
+    pub fn cancel_request(ctx: Context<CancelRequest>) -> Result<()> {
+        let request = &mut ctx.accounts.request;
+
+        if !is_request_abandonable(request) {
+            return err!(MarketplaceError::RequestNotCancellable);
+        }
+
+        abandon_request(request, &ctx.accounts.treasury)?;
+
+        let request_id = request.id;
+        ctx.accounts.category.request_ids.retain(|id| *id != request_id);
+        ctx.accounts.geo_cell.request_ids.retain(|id| *id != request_id);
+
+        let user_stats = &mut ctx.accounts.user_stats;
+        touch_user_stats(user_stats, request.authority, request.buyer_id);
+        user_stats.requests_cancelled = user_stats.requests_cancelled.checked_add(1).unwrap();
+        user_stats.open_requests = user_stats.open_requests.checked_sub(1).unwrap();
+        user_stats.last_active_at = user_stats.updated_at;
+
+        Ok(())
+    }
+
+    /// Permissionless: forfeits the deposit of a request left without
+    /// progress for `abandon_timeout` after sellers offered on it.
+    pub fn claim_abandoned_deposit(ctx: Context<ClaimAbandonedDeposit>) -> Result<()> {
+        let request = &mut ctx.accounts.request;
+
+        if !is_request_abandonable(request) {
+            return err!(MarketplaceError::RequestNotCancellable);
+        }
+
+        if Clock::get().unwrap().unix_timestamp as u64 <= request.updated_at + ctx.accounts.admin.abandon_timeout {
+            return err!(MarketplaceError::RequestNotAbandoned);
+        }
+
+        abandon_request(request, &ctx.accounts.treasury)?;
+
+        let request_id = request.id;
+        ctx.accounts.category.request_ids.retain(|id| *id != request_id);
+        ctx.accounts.geo_cell.request_ids.retain(|id| *id != request_id);
+
+        let user_stats = &mut ctx.accounts.user_stats;
+        touch_user_stats(user_stats, request.authority, request.buyer_id);
+        user_stats.requests_cancelled = user_stats.requests_cancelled.checked_add(1).unwrap();
+        user_stats.open_requests = user_stats.open_requests.checked_sub(1).unwrap();
+        user_stats.last_active_at = user_stats.updated_at;
+
+        Ok(())
+    }
+
+    /// Closes a pending or cancelled request. The live offers on a cancelled
+    /// request are passed writable in `remaining_accounts` and withdrawn, so
+    /// their stores can close without the request.
+    pub fn delete_request(ctx: Context<RemoveRequest>) -> Result<()> {
+        let request = &mut ctx.accounts.request;
+        let authority = &ctx.accounts.authority;
+    
+        if request.authority != authority.key() {
+            return err!(MarketplaceError::InvalidUser);
+        }
+    
+        let cancelled = request.lifecycle == RequestLifecycle::Cancelled;
+
+        if request.lifecycle != RequestLifecycle::Pending && !cancelled {
+            return err!(MarketplaceError::RequestLocked);
+        }
+
+        if request.pool_escrowed > 0 {
+            return err!(MarketplaceError::PoolNotEmpty);
+        }
+
+        if cancelled {
+            withdraw_request_offers(request, ctx.remaining_accounts, ctx.program_id)?;
+        }
+
+        let request_id = request.id;
+        ctx.accounts.category.request_ids.retain(|id| *id != request_id);
+        ctx.accounts.geo_cell.request_ids.retain(|id| *id != request_id);
+
+        // cancelled requests were already counted when they were cancelled
+        if !cancelled {
+            let user_stats = &mut ctx.accounts.user_stats;
+            touch_user_stats(user_stats, request.authority, request.buyer_id);
+            user_stats.requests_cancelled = user_stats.requests_cancelled.checked_add(1).unwrap();
+            user_stats.open_requests = user_stats.open_requests.checked_sub(1).unwrap();
+            user_stats.last_active_at = user_stats.updated_at;
+        }
+
+        // the deposit goes back to the buyer with the rest of the closed account
+        if request.deposit > 0 {
+            emit!(RequestDepositRefunded {
+                request_id: request.id,
+                amount: request.deposit,
+            });
+        }
+    
+        Ok(())
+    }
+
+    /// Completes a paid offer once the seller confirmed delivery, or once
+    /// `DELIVERY_CONFIRMATION_TIMEOUT` has passed since payment without it.
+    pub fn mark_request_as_completed(ctx: Context<MarkAsCompleteRequest>) -> Result<()> {
+        let request = &mut ctx.accounts.request;
+        let offer = &mut ctx.accounts.offer;
+        let authority = &ctx.accounts.authority;
+    
+        if request.authority != authority.key() {
+            return err!(MarketplaceError::InvalidUser);
+        }
+
+        if offer.request_id != request.id || !offer.is_accepted {
+            return err!(MarketplaceError::InvalidOffer);
+        }
+    
+        if !offer.is_paid {
+            return err!(MarketplaceError::RequestNotPaid);
+        }
+
+        // without the seller's confirmation the buyer waits out the timeout,
+        // which also covers paid offers migrated from the legacy layout
+        if !offer.is_delivered
+            && Clock::get().unwrap().unix_timestamp as u64 <= offer.updated_at + DELIVERY_CONFIRMATION_TIMEOUT
+        {
+            return err!(MarketplaceError::DeliveryNotConfirmed);
+        }
+
+        if offer.is_completed {
+            return err!(MarketplaceError::FulfilmentAlreadyCompleted);
+        }
+
+        if request.updated_at + TIME_TO_LOCK  > Clock::get().unwrap().unix_timestamp as u64 {
+            return err!(MarketplaceError::RequestNotLocked);
+        }
+
+        offer.is_completed = true;
+        offer.updated_at = Clock::get().unwrap().unix_timestamp as u64;
+        request.fulfilled_quantity = request.fulfilled_quantity.checked_add(offer.quantity).unwrap();
+
+        let buyer_stats = &mut ctx.accounts.buyer_stats;
+        touch_user_stats(buyer_stats, request.authority, request.buyer_id);
+        buyer_stats.completed_deals = buyer_stats.completed_deals.checked_add(1).unwrap();
+        buyer_stats.last_active_at = buyer_stats.updated_at;
+
+        let seller_stats = &mut ctx.accounts.seller_stats;
+        touch_user_stats(seller_stats, offer.authority, offer.seller_id);
+        seller_stats.completed_deals = seller_stats.completed_deals.checked_add(1).unwrap();
+
+        if request.fulfilled_quantity >= request.quantity {
+            request.lifecycle = RequestLifecycle::Completed;
+            request.updated_at = Clock::get().unwrap().unix_timestamp as u64;
+            buyer_stats.open_requests = buyer_stats.open_requests.checked_sub(1).unwrap();
+
+            if request.deposit > 0 {
+                let amount = request.deposit;
+                request.deposit = 0;
+
+                transfer_lamports(&request.to_account_info(), &authority.to_account_info(), amount)?;
+
+                emit!(RequestDepositRefunded {
+                    request_id: request.id,
+                    amount,
+                });
+            }
+        }
+
+        emit!(FulfilmentCompleted {
+            request_id: request.id,
             offer_id: offer.id,
-            buyer_address: *ctx.accounts.user.to_account_info().key,
-            is_accepted: true,
+            seller_id: offer.seller_id,
+            quantity: offer.quantity,
+            fulfilled_quantity: request.fulfilled_quantity,
+        });
+    
+        Ok(())
+    }
+
+    /// One-time rating of the counterparty on a completed offer. The buyer
+    /// reviews the seller and the seller reviews the buyer, once per offer,
+    /// so a buyer reviews every seller of a request they split.
+    pub fn leave_review(ctx: Context<LeaveReview>, rating: u8, comment_hash: [u8; 32]) -> Result<()> {
+        let request = &ctx.accounts.request;
+        let offer = &ctx.accounts.offer;
+        let reviewer = ctx.accounts.reviewer.key();
+        let reviewee = ctx.accounts.reviewee.key();
+
+        if offer.request_id != request.id || !offer.is_completed {
+            return err!(MarketplaceError::InvalidOffer);
+        }
+
+        if rating == 0 || rating > MAX_RATING {
+            return err!(MarketplaceError::InvalidRating);
+        }
+
+        let reviewee_id = if reviewer == request.authority && reviewee == offer.authority {
+            offer.seller_id
+        } else if reviewer == offer.authority && reviewee == request.authority {
+            request.buyer_id
+        } else {
+            return err!(MarketplaceError::InvalidReviewer);
+        };
+
+        let review = &mut ctx.accounts.review;
+
+        review.request_id = request.id;
+        review.offer_id = offer.id;
+        review.reviewer = reviewer;
+        review.reviewee = reviewee;
+        review.rating = rating;
+        review.comment_hash = comment_hash;
+        review.created_at = Clock::get().unwrap().unix_timestamp as u64;
+
+        let reviewee_stats = &mut ctx.accounts.reviewee_stats;
+        touch_user_stats(reviewee_stats, reviewee, reviewee_id);
+        reviewee_stats.rating_count = reviewee_stats.rating_count.checked_add(1).unwrap();
+        reviewee_stats.rating_sum = reviewee_stats.rating_sum.checked_add(rating as u64).unwrap();
+
+        emit!(ReviewSubmitted {
+            request_id: review.request_id,
+            offer_id: review.offer_id,
+            reviewer,
+            reviewee,
+            rating,
+            comment_hash,
+            rating_count: reviewee_stats.rating_count,
+            rating_sum: reviewee_stats.rating_sum,
         });
 
-        Ok(())
-    }
+        Ok(())
+    }
+
+    pub fn pay_for_request_token(ctx: Context<PayForRequestToken>,coin: CoinPayment) -> Result<()> {
+        let request = &mut ctx.accounts.request;
+        let offer = &mut ctx.accounts.offer;
+        let authority = &ctx.accounts.authority;
+        let mint = &ctx.accounts.mint;
+        let from_ata = &ctx.accounts.from_ata;
+        let to_ata = &ctx.accounts.to_ata;
+        let token_program = &ctx.accounts.token_program;
+        let request_payment_counter = &mut ctx.accounts.request_payment_counter;
+        let request_payment_info = &mut ctx.accounts.request_payment_info;
+    
+        if request.authority != authority.key() {
+            return err!(MarketplaceError::InvalidUser);
+        }
+    
+        if request.lifecycle != RequestLifecycle::AcceptedByBuyer {
+            return err!(MarketplaceError::RequestNotAccepted);
+        }
+
+        if request.pooled {
+            return err!(MarketplaceError::PooledRequest);
+        }
+
+        if request.updated_at + TIME_TO_LOCK  > Clock::get().unwrap().unix_timestamp as u64 {
+            return err!(MarketplaceError::RequestNotLocked);
+        }
+
+        if !offer.is_accepted {
+            return err!(MarketplaceError::RequestNotAccepted);
+        }
+
+        if offer.request_id != request.id {
+            return err!(MarketplaceError::InvalidOffer);
+        }
+
+        if request.quantity == 1 && request.locked_seller_id != offer.seller_id {
+            return err!(MarketplaceError::InvalidSeller);
+        }
+
+        if request.paid {
+            return err!(MarketplaceError::RequestAlreadyPaid);
+        }
+
+        if offer.is_paid {
+            return err!(MarketplaceError::OfferAlreadyPaid);
+        }
+
+        let total_price = offer.price.checked_mul(offer.quantity).unwrap();
+        let pyusd_amount = lamports_to_pyusd(&ctx.accounts.price_update, total_price)?;
+
+        record_payment_stats(
+            &mut ctx.accounts.buyer_stats,
+            &mut ctx.accounts.seller_stats,
+            request,
+            offer,
+            &coin,
+            pyusd_amount,
+        );
+
+        mark_offer_paid(request, offer, Clock::get().unwrap().unix_timestamp as u64);
+        record_request_payment(request_payment_info, request_payment_counter, request, offer, coin.clone(), pyusd_amount);
+
+        match coin {
+            CoinPayment::Pyusdt => {
+                let accounts = TransferChecked {
+                    from: from_ata.to_account_info(),
+                    to: to_ata.to_account_info(),
+                    authority: authority.to_account_info(),
+                    mint: mint.to_account_info(),
+                };
+                
+                let ctx = CpiContext::new(
+                    token_program.to_account_info(),
+                    accounts
+                );
+
+                transfer_checked(ctx, pyusd_amount, mint.decimals)?;
+            },
+            _ => {
+                return err!(MarketplaceError::InvalidCoinPayment);
+            },
+            
+        }
+        
+        Ok(())
+    }
+
+
+    pub fn pay_for_request(ctx: Context<PayForRequest>,coin: CoinPayment) -> Result<()> {
+        let request = &mut ctx.accounts.request;
+        let offer = &mut ctx.accounts.offer;
+        let to = &mut ctx.accounts.to;
+        let authority = &ctx.accounts.authority;
+        let request_payment_counter = &mut ctx.accounts.request_payment_counter;
+        let request_payment_info = &mut ctx.accounts.request_payment_info;
+    
+        if request.authority != authority.key() {
+            return err!(MarketplaceError::InvalidUser);
+        }
+    
+        if request.lifecycle != RequestLifecycle::AcceptedByBuyer {
+            return err!(MarketplaceError::RequestNotAccepted);
+        }
+
+        if request.pooled {
+            return err!(MarketplaceError::PooledRequest);
+        }
+
+        if request.updated_at + TIME_TO_LOCK  > Clock::get().unwrap().unix_timestamp as u64 {
+            return err!(MarketplaceError::RequestNotLocked);
+        }
+
+        if !offer.is_accepted {
+            return err!(MarketplaceError::RequestNotAccepted);
+        }
+
+        if offer.request_id != request.id {
+            return err!(MarketplaceError::InvalidOffer);
+        }
+
+        if request.quantity == 1 && request.locked_seller_id != offer.seller_id {
+            return err!(MarketplaceError::InvalidSeller);
+        }
+
+        if request.paid {
+            return err!(MarketplaceError::RequestAlreadyPaid);
+        }
+
+        if offer.is_paid {
+            return err!(MarketplaceError::OfferAlreadyPaid);
+        }
+
+        let total_price = offer.price.checked_mul(offer.quantity).unwrap();
+
+        record_payment_stats(
+            &mut ctx.accounts.buyer_stats,
+            &mut ctx.accounts.seller_stats,
+            request,
+            offer,
+            &coin,
+            total_price,
+        );
+
+        mark_offer_paid(request, offer, Clock::get().unwrap().unix_timestamp as u64);
+        record_request_payment(request_payment_info, request_payment_counter, request, offer, coin.clone(), total_price);
+
+        match coin {
+            CoinPayment::Solana => {
+                let transfer_instruction = system_instruction::transfer(authority.key, to.key, total_price);
+
+                anchor_lang::solana_program::program::invoke_signed(
+                    &transfer_instruction,
+                    &[
+                        authority.to_account_info(),
+                        to.clone(),
+                        ctx.accounts.system_program.to_account_info(),
+                    ],
+                    &[],
+                )?;
+            }
+            _ => {
+                return err!(MarketplaceError::InvalidCoinPayment);
+            },
+        }
+        Ok(())
+    }
+
+    pub fn stake_bond(ctx: Context<StakeBond>, amount: u64) -> Result<()> {
+        let user = &ctx.accounts.user;
+        let seller_bond = &mut ctx.accounts.seller_bond;
+        let authority = &ctx.accounts.authority;
+
+        if !user.has_role(ROLE_SELLER) {
+            return err!(MarketplaceError::OnlySellersAllowed);
+        }
+
+        if amount == 0 {
+            return err!(MarketplaceError::InvalidBondAmount);
+        }
+
+        init_seller_bond(seller_bond, authority.key(), user.id, None)?;
+
+        let transfer_instruction = system_instruction::transfer(authority.key, &seller_bond.key(), amount);
+
+        anchor_lang::solana_program::program::invoke(
+            &transfer_instruction,
+            &[
+                authority.to_account_info(),
+                seller_bond.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+            ],
+        )?;
+
+        seller_bond.amount = seller_bond.amount.checked_add(amount).unwrap();
+        seller_bond.updated_at = Clock::get().unwrap().unix_timestamp as u64;
+
+        emit!(BondStaked {
+            seller: seller_bond.authority,
+            mint: None,
+            amount,
+            total: seller_bond.amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn stake_bond_token(ctx: Context<StakeBondToken>, amount: u64) -> Result<()> {
+        let user = &ctx.accounts.user;
+        let seller_bond = &mut ctx.accounts.seller_bond;
+        let authority = &ctx.accounts.authority;
+        let mint = &ctx.accounts.mint;
+
+        if !user.has_role(ROLE_SELLER) {
+            return err!(MarketplaceError::OnlySellersAllowed);
+        }
+
+        if amount == 0 {
+            return err!(MarketplaceError::InvalidBondAmount);
+        }
+
+        init_seller_bond(seller_bond, authority.key(), user.id, Some(mint.key()))?;
+
+        let accounts = TransferChecked {
+            from: ctx.accounts.from_ata.to_account_info(),
+            to: ctx.accounts.vault.to_account_info(),
+            authority: authority.to_account_info(),
+            mint: mint.to_account_info(),
+        };
+
+        transfer_checked(
+            CpiContext::new(ctx.accounts.token_program.to_account_info(), accounts),
+            amount,
+            mint.decimals,
+        )?;
+
+        seller_bond.amount = seller_bond.amount.checked_add(amount).unwrap();
+        seller_bond.updated_at = Clock::get().unwrap().unix_timestamp as u64;
+
+        emit!(BondStaked {
+            seller: seller_bond.authority,
+            mint: seller_bond.mint,
+            amount,
+            total: seller_bond.amount,
+        });
+
+        Ok(())
+    }
+
+    /// Starts the cooldown for withdrawing `amount`. The bond can still be
+    /// slashed until it is withdrawn.
+    pub fn request_unstake(ctx: Context<RequestUnstake>, amount: u64) -> Result<()> {
+        let seller_bond = &mut ctx.accounts.seller_bond;
+
+        if amount == 0 || amount > seller_bond.amount {
+            return err!(MarketplaceError::InvalidBondAmount);
+        }
+
+        seller_bond.unstake_amount = amount;
+        seller_bond.unstake_requested_at = Clock::get().unwrap().unix_timestamp as u64;
+        seller_bond.updated_at = seller_bond.unstake_requested_at;
+
+        emit!(BondUnstakeRequested {
+            seller: seller_bond.authority,
+            amount,
+            available_at: seller_bond.unstake_requested_at + ctx.accounts.admin.bond_unstake_cooldown,
+        });
+
+        Ok(())
+    }
+
+    pub fn withdraw_bond(ctx: Context<WithdrawBond>) -> Result<()> {
+        let seller_bond = &mut ctx.accounts.seller_bond;
+
+        if seller_bond.mint.is_some() {
+            return err!(MarketplaceError::InvalidBondMint);
+        }
+
+        let amount = take_unstaked_bond(seller_bond, ctx.accounts.admin.bond_unstake_cooldown)?;
+
+        transfer_lamports(
+            &seller_bond.to_account_info(),
+            &ctx.accounts.authority.to_account_info(),
+            amount,
+        )?;
+
+        emit!(BondWithdrawn {
+            seller: seller_bond.authority,
+            amount,
+            remaining: seller_bond.amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn withdraw_bond_token(ctx: Context<WithdrawBondToken>) -> Result<()> {
+        let seller_bond = &mut ctx.accounts.seller_bond;
+
+        if seller_bond.mint != Some(ctx.accounts.mint.key()) {
+            return err!(MarketplaceError::InvalidBondMint);
+        }
+
+        let amount = take_unstaked_bond(seller_bond, ctx.accounts.admin.bond_unstake_cooldown)?;
+
+        let authority = seller_bond.authority;
+        let signer_seeds: &[&[&[u8]]] = &[&[SELLER_BOND_TAG, authority.as_ref(), &[ctx.bumps.seller_bond]]];
+
+        let accounts = TransferChecked {
+            from: ctx.accounts.vault.to_account_info(),
+            to: ctx.accounts.to_ata.to_account_info(),
+            authority: seller_bond.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+        };
+
+        transfer_checked(
+            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), accounts, signer_seeds),
+            amount,
+            ctx.accounts.mint.decimals,
+        )?;
+
+        emit!(BondWithdrawn {
+            seller: authority,
+            amount,
+            remaining: seller_bond.amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn slash_bond(ctx: Context<SlashBond>, amount: u64) -> Result<()> {
+        let seller_bond = &mut ctx.accounts.seller_bond;
+
+        if seller_bond.mint.is_some() {
+            return err!(MarketplaceError::InvalidBondMint);
+        }
+
+        slash_seller_bond(seller_bond, amount)?;
+
+        transfer_lamports(
+            &seller_bond.to_account_info(),
+            &ctx.accounts.buyer.to_account_info(),
+            amount,
+        )?;
+
+        emit!(BondSlashed {
+            seller: seller_bond.authority,
+            buyer: ctx.accounts.buyer.key(),
+            amount,
+            remaining: seller_bond.amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn slash_bond_token(ctx: Context<SlashBondToken>, amount: u64) -> Result<()> {
+        let seller_bond = &mut ctx.accounts.seller_bond;
+
+        if seller_bond.mint != Some(ctx.accounts.mint.key()) {
+            return err!(MarketplaceError::InvalidBondMint);
+        }
+
+        slash_seller_bond(seller_bond, amount)?;
+
+        let authority = seller_bond.authority;
+        let signer_seeds: &[&[&[u8]]] = &[&[SELLER_BOND_TAG, authority.as_ref(), &[ctx.bumps.seller_bond]]];
+
+        let accounts = TransferChecked {
+            from: ctx.accounts.vault.to_account_info(),
+            to: ctx.accounts.buyer_ata.to_account_info(),
+            authority: seller_bond.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+        };
+
+        transfer_checked(
+            CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), accounts, signer_seeds),
+            amount,
+            ctx.accounts.mint.decimals,
+        )?;
+
+        emit!(BondSlashed {
+            seller: authority,
+            buyer: ctx.accounts.buyer_ata.owner,
+            amount,
+            remaining: seller_bond.amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn toggle_location(ctx: Context<ToggleLocation>, enabled: bool) -> Result<()> {
+        let  user  = &mut ctx.accounts.user;
+
+        user.location_enabled = enabled;
+
+        emit!(LocationEnabled {
+            location_enabled: enabled,
+            user_id: user.id,
+        });
+        
+        Ok(())
+    }
+
+    pub fn get_location_preference(ctx: Context<GetLocationPreference>) -> Result<bool> {
+        let user = &ctx.accounts.user;
+        
+        Ok(user.location_enabled)
+    }
+
+
+    pub fn create_offer(
+        ctx: Context<CreateOffer>,
+        price: u64,
+        images: Vec<String>,
+        quantity: u64,
+    ) -> Result<()> {
+        check_store_signer(
+            &ctx.accounts.store,
+            &ctx.accounts.authority.key(),
+            ctx.accounts.store_delegate.as_deref(),
+            DELEGATE_CREATE_OFFERS,
+        )?;
+
+        check_images(&images)?;
+
+        let user = &mut ctx.accounts.user;
+        let offer_counter = &mut ctx.accounts.offer_counter;
+
+        if !user.has_role(ROLE_SELLER) {
+            return err!(MarketplaceError::OnlySellersAllowed);
+        }
+
+        let request = &mut ctx.accounts.request;
+
+        if is_request_locked(request, Clock::get().unwrap().unix_timestamp as u64) {
+            return err!(MarketplaceError::RequestLocked);
+        }
+
+        if quantity == 0 || quantity > request.quantity {
+            return err!(MarketplaceError::InvalidQuantity);
+        }
+
+        if request.bidding_deadline != 0
+            && Clock::get().unwrap().unix_timestamp as u64 > request.bidding_deadline
+        {
+            return err!(MarketplaceError::BiddingClosed);
+        }
+
+        if request.sealed_bids {
+            return err!(MarketplaceError::SealedBidRequired);
+        }
+
+        if request.seller_ids.len() >= max_request_offers(request) {
+            return err!(MarketplaceError::TooManyOffers);
+        }
+
+        if request.seller_ids.contains(&user.id) {
+            return err!(MarketplaceError::SellerAlreadyOffered);
+        }
+
+        if request.lifecycle == RequestLifecycle::Cancelled {
+            return err!(MarketplaceError::RequestCancelled);
+        }
+
+        check_budget(request, price, ctx.accounts.price_update.as_deref())?;
+        check_seller_distance(request, &ctx.accounts.store)?;
+
+        if ctx.accounts.store.is_away {
+            return err!(MarketplaceError::StoreAway);
+        }
+
+        check_store_verification(request, &ctx.accounts.store)?;
+
+        check_seller_bond(&ctx.accounts.admin, ctx.accounts.seller_bond.as_deref())?;
+
+        let offer = &mut ctx.accounts.offer;
+
+        offer.id = offer_counter.current;
+        offer.price = price;
+        offer.images = images;
+        offer.request_id = request.id;
+        offer.store_name = offer_store_name(&ctx.accounts.store);
+        offer.seller_id = user.id;
+        offer.is_accepted = false;
+        offer.created_at = Clock::get().unwrap().unix_timestamp as u64;
+        offer.updated_at = Clock::get().unwrap().unix_timestamp as u64;
+        offer.authority = ctx.accounts.store.authority;
+        offer.is_revealed = true;
+        offer.quantity = quantity;
+        offer.is_paid = false;
+        offer.is_completed = false;
+        offer.store_id = ctx.accounts.store.id;
+        offer.version = ACCOUNT_VERSION;
+        push_store_offer(&mut ctx.accounts.store, offer.id)?;
+        resize_account(&ctx.accounts.store, &ctx.accounts.authority, &ctx.accounts.system_program)?;
+
+        if request.lifecycle == RequestLifecycle::Pending {
+            request.lifecycle = RequestLifecycle::AcceptedBySeller;
+        }
+
+        request.seller_ids.push(offer.seller_id);
+        resize_account(request, &ctx.accounts.authority, &ctx.accounts.system_program)?;
+
+        emit!(OfferCreated {
+            offer_id: offer.id,
+            seller_address: *ctx.accounts.user.to_account_info().key,
+            store_name: offer.store_name.clone(),
+            price: offer.price,
+            request_id: offer.request_id,
+            images: offer.images.clone(),
+            seller_id: offer.seller_id,
+            seller_ids: request.seller_ids.clone(),
+            quantity: offer.quantity,
+            store_id: offer.store_id,
+        });
+
+        offer_counter.current = offer_counter.current.checked_add(1).unwrap();
+
+        let user_stats = &mut ctx.accounts.user_stats;
+        touch_user_stats(user_stats, offer.authority, offer.seller_id);
+        user_stats.offers_created = user_stats.offers_created.checked_add(1).unwrap();
+        user_stats.last_active_at = user_stats.updated_at;
+
+        Ok(())
+    }
+
+    /// Submits a sealed offer on a sealed-bid request. `price_commitment` is
+    /// `sha256(price.to_le_bytes() || salt)`, opened later with `reveal_offer`.
+    pub fn commit_offer(
+        ctx: Context<CreateOffer>,
+        price_commitment: [u8; 32],
+        images: Vec<String>,
+        quantity: u64,
+    ) -> Result<()> {
+        check_store_signer(
+            &ctx.accounts.store,
+            &ctx.accounts.authority.key(),
+            ctx.accounts.store_delegate.as_deref(),
+            DELEGATE_CREATE_OFFERS,
+        )?;
+
+        check_images(&images)?;
+
+        let user = &mut ctx.accounts.user;
+        let offer_counter = &mut ctx.accounts.offer_counter;
+
+        if !user.has_role(ROLE_SELLER) {
+            return err!(MarketplaceError::OnlySellersAllowed);
+        }
+
+        let request = &mut ctx.accounts.request;
+
+        if !request.sealed_bids {
+            return err!(MarketplaceError::NotSealedBidRequest);
+        }
+
+        if request.seller_ids.len() >= max_request_offers(request) {
+            return err!(MarketplaceError::TooManyOffers);
+        }
+
+        if request.seller_ids.contains(&user.id) {
+            return err!(MarketplaceError::SellerAlreadyOffered);
+        }
+
+        if request.lifecycle == RequestLifecycle::Cancelled {
+            return err!(MarketplaceError::RequestCancelled);
+        }
+
+        if Clock::get().unwrap().unix_timestamp as u64 > request.bidding_deadline {
+            return err!(MarketplaceError::BiddingClosed);
+        }
+
+        if quantity == 0 || quantity > request.quantity {
+            return err!(MarketplaceError::InvalidQuantity);
+        }
+
+        check_seller_distance(request, &ctx.accounts.store)?;
+
+        if ctx.accounts.store.is_away {
+            return err!(MarketplaceError::StoreAway);
+        }
+
+        check_store_verification(request, &ctx.accounts.store)?;
+
+        check_seller_bond(&ctx.accounts.admin, ctx.accounts.seller_bond.as_deref())?;
+
+        let offer = &mut ctx.accounts.offer;
+
+        offer.id = offer_counter.current;
+        offer.price = 0;
+        offer.price_commitment = price_commitment;
+        offer.is_revealed = false;
+        offer.quantity = quantity;
+        offer.is_paid = false;
+        offer.is_completed = false;
+        offer.store_id = ctx.accounts.store.id;
+        offer.version = ACCOUNT_VERSION;
+        push_store_offer(&mut ctx.accounts.store, offer.id)?;
+        resize_account(&ctx.accounts.store, &ctx.accounts.authority, &ctx.accounts.system_program)?;
+        offer.images = images;
+        offer.request_id = request.id;
+        offer.store_name = offer_store_name(&ctx.accounts.store);
+        offer.seller_id = user.id;
+        offer.is_accepted = false;
+        offer.created_at = Clock::get().unwrap().unix_timestamp as u64;
+        offer.updated_at = Clock::get().unwrap().unix_timestamp as u64;
+        offer.authority = ctx.accounts.store.authority;
+
+        if request.lifecycle == RequestLifecycle::Pending {
+            request.lifecycle = RequestLifecycle::AcceptedBySeller;
+        }
+
+        request.seller_ids.push(offer.seller_id);
+        resize_account(request, &ctx.accounts.authority, &ctx.accounts.system_program)?;
+
+        emit!(OfferCommitted {
+            offer_id: offer.id,
+            seller_address: *ctx.accounts.user.to_account_info().key,
+            store_name: offer.store_name.clone(),
+            price_commitment: offer.price_commitment,
+            request_id: offer.request_id,
+            images: offer.images.clone(),
+            seller_id: offer.seller_id,
+            seller_ids: request.seller_ids.clone(),
+            quantity: offer.quantity,
+            store_id: offer.store_id,
+        });
+
+        offer_counter.current = offer_counter.current.checked_add(1).unwrap();
+
+        let user_stats = &mut ctx.accounts.user_stats;
+        touch_user_stats(user_stats, offer.authority, offer.seller_id);
+        user_stats.offers_created = user_stats.offers_created.checked_add(1).unwrap();
+        user_stats.last_active_at = user_stats.updated_at;
+
+        Ok(())
+    }
+
+    pub fn reveal_offer(ctx: Context<RevealOffer>, price: u64, salt: [u8; 32]) -> Result<()> {
+        check_store_signer(
+            &ctx.accounts.store,
+            &ctx.accounts.authority.key(),
+            ctx.accounts.store_delegate.as_deref(),
+            DELEGATE_CREATE_OFFERS,
+        )?;
+
+        let request = &ctx.accounts.request;
+        let offer = &mut ctx.accounts.offer;
+        let now = Clock::get().unwrap().unix_timestamp as u64;
+
+        if !request.sealed_bids {
+            return err!(MarketplaceError::NotSealedBidRequest);
+        }
+
+        if now <= request.bidding_deadline {
+            return err!(MarketplaceError::BiddingNotClosed);
+        }
+
+        if now > request.reveal_deadline {
+            return err!(MarketplaceError::RevealClosed);
+        }
+
+        if offer.is_revealed {
+            return err!(MarketplaceError::OfferAlreadyRevealed);
+        }
+
+        if offer_price_commitment(price, &salt) != offer.price_commitment {
+            return err!(MarketplaceError::CommitmentMismatch);
+        }
+
+        check_budget(request, price, ctx.accounts.price_update.as_deref())?;
+
+        offer.price = price;
+        offer.is_revealed = true;
+        offer.updated_at = now;
+
+        emit!(OfferRevealed {
+            offer_id: offer.id,
+            request_id: offer.request_id,
+            seller_id: offer.seller_id,
+            price: offer.price,
+        });
+
+        Ok(())
+    }
+
+    pub fn update_offer(
+        ctx: Context<UpdateOffer>,
+        price: u64,
+        images: Vec<String>,
+        quantity: u64,
+    ) -> Result<()> {
+        check_store_signer(
+            &ctx.accounts.store,
+            &ctx.accounts.authority.key(),
+            ctx.accounts.store_delegate.as_deref(),
+            DELEGATE_UPDATE_OFFERS,
+        )?;
+
+        check_images(&images)?;
+
+        let request = &ctx.accounts.request;
+        let offer = &mut ctx.accounts.offer;
+        let now = Clock::get().unwrap().unix_timestamp as u64;
+
+        if offer.is_accepted {
+            return err!(MarketplaceError::OfferAlreadyAccepted);
+        }
+
+        // sealed offers are bound to their price commitment
+        if request.sealed_bids {
+            return err!(MarketplaceError::OfferNotUpdatable);
+        }
+
+        if request.lifecycle == RequestLifecycle::Cancelled {
+            return err!(MarketplaceError::RequestCancelled);
+        }
+
+        if is_request_locked(request, now) {
+            return err!(MarketplaceError::RequestLocked);
+        }
+
+        if request.bidding_deadline != 0 && now > request.bidding_deadline {
+            return err!(MarketplaceError::BiddingClosed);
+        }
+
+        if quantity == 0 || quantity > request.quantity {
+            return err!(MarketplaceError::InvalidQuantity);
+        }
+
+        check_budget(request, price, ctx.accounts.price_update.as_deref())?;
+        check_seller_distance(request, &ctx.accounts.store)?;
+        check_store_verification(request, &ctx.accounts.store)?;
+
+        offer.price = price;
+        offer.images = images;
+        offer.quantity = quantity;
+        offer.updated_at = now;
+        resize_account(offer, &ctx.accounts.authority, &ctx.accounts.system_program)?;
+
+        emit!(OfferUpdated {
+            offer_id: offer.id,
+            request_id: offer.request_id,
+            seller_id: offer.seller_id,
+            price: offer.price,
+            images: offer.images.clone(),
+            quantity: offer.quantity,
+            updated_by: ctx.accounts.authority.key(),
+        });
+
+        Ok(())
+    }
+
+    pub fn confirm_delivery(ctx: Context<ConfirmDelivery>) -> Result<()> {
+        check_store_signer(
+            &ctx.accounts.store,
+            &ctx.accounts.authority.key(),
+            ctx.accounts.store_delegate.as_deref(),
+            DELEGATE_CONFIRM_DELIVERY,
+        )?;
+
+        let offer = &mut ctx.accounts.offer;
+
+        if !offer.is_paid {
+            return err!(MarketplaceError::RequestNotPaid);
+        }
+
+        if offer.is_delivered {
+            return err!(MarketplaceError::DeliveryAlreadyConfirmed);
+        }
+
+        offer.is_delivered = true;
+        offer.updated_at = Clock::get().unwrap().unix_timestamp as u64;
+
+        emit!(DeliveryConfirmed {
+            request_id: offer.request_id,
+            offer_id: offer.id,
+            seller_id: offer.seller_id,
+            confirmed_by: ctx.accounts.authority.key(),
+            delivered_at: offer.updated_at,
+        });
+
+        Ok(())
+    }
+
+    pub fn accept_offer<'info>(ctx: Context<'_, '_, 'info, 'info, AcceptOffer<'info>>) -> Result<()> {
+        let user = &mut ctx.accounts.user;
+        let offer = &mut ctx.accounts.offer;
+        let request = &mut ctx.accounts.request;
+
+        if !user.has_role(ROLE_BUYER) {
+            return err!(MarketplaceError::OnlyBuyersAllowed);
+        }
+
+        if request.buyer_id != user.id {
+            return err!(MarketplaceError::UnauthorizedBuyer);
+        }
+
+        if request.mode == RequestMode::ReverseAuction {
+            return err!(MarketplaceError::AuctionRequest);
+        }
+
+        if request.lifecycle == RequestLifecycle::Cancelled {
+            return err!(MarketplaceError::RequestCancelled);
+        }
+
+        if request.lifecycle == RequestLifecycle::Paid
+            || request.lifecycle == RequestLifecycle::Completed
+        {
+            return err!(MarketplaceError::RequestAlreadyPaid);
+        }
+
+        if request.sealed_bids {
+            if Clock::get().unwrap().unix_timestamp as u64 <= request.reveal_deadline {
+                return err!(MarketplaceError::RevealNotClosed);
+            }
+
+            if !offer.is_revealed {
+                return err!(MarketplaceError::OfferNotRevealed);
+            }
+        }
+
+        if offer.is_accepted {
+            return err!(MarketplaceError::OfferAlreadyAccepted);
+        }
+
+        if offer.is_withdrawn {
+            return err!(MarketplaceError::OfferWithdrawn);
+        }
+
+        if offer.request_id != request.id {
+            return err!(MarketplaceError::InvalidOffer);
+        }
+
+        if is_request_locked(request, Clock::get().unwrap().unix_timestamp as u64) {
+            return err!(MarketplaceError::RequestLocked);
+        }
+
+        if request.pooled && offer.quantity != request.quantity {
+            return err!(MarketplaceError::InvalidQuantity);
+        }
+
+        if request.quantity > 1 {
+            if offer.quantity > request.quantity - request.accepted_quantity {
+                return err!(MarketplaceError::QuantityExceeded);
+            }
+        } else {
+            if request.seller_ids.len() != ctx.remaining_accounts.len() {
+                return err!(MarketplaceError::IncorrectNumberOfSellers);
+            }
+
+            for account_info in ctx.remaining_accounts.iter() {
+                let mut previous_offer =
+                    Account::<Offer>::try_from(account_info).map_err(|_| MarketplaceError::InvalidOffer)?;
+
+                if previous_offer.is_accepted && previous_offer.request_id == request.id {
+                    previous_offer.is_accepted = false;
+                    previous_offer.exit(ctx.program_id)?;
+                    emit!(OfferAccepted {
+                        offer_id: previous_offer.id,
+                        buyer_address: *ctx.accounts.user.to_account_info().key,
+                        is_accepted: false,
+                    });
+                }
+            }
+
+            request.accepted_quantity = 0;
+        }
+
+        lock_accepted_offer(request, offer, Clock::get().unwrap().unix_timestamp as u64);
+
+        if request.accepted_quantity >= request.quantity {
+            ctx.accounts.category.request_ids.retain(|id| *id != request.id);
+            ctx.accounts.geo_cell.request_ids.retain(|id| *id != request.id);
+        }
+
+        record_acceptance_stats(&mut ctx.accounts.buyer_stats, &mut ctx.accounts.seller_stats, request, offer);
+
+        emit!(RequestAccepted {
+            request_id: request.id,
+            offer_id: offer.id,
+            seller_id: offer.seller_id,
+            updated_at: request.updated_at,
+            sellers_price_quote: request.sellers_price_quote,
+            quantity: offer.quantity,
+            accepted_quantity: request.accepted_quantity,
+        });
+
+        emit!(OfferAccepted {
+            offer_id: offer.id,
+            buyer_address: *ctx.accounts.user.to_account_info().key,
+            is_accepted: true,
+        });
+
+        Ok(())
+    }
+
+    // Withdraws the buyer's acceptance of an unpaid offer before the request
+    // locks, e.g. a partial offer the buyer no longer wants.
+    pub fn unaccept_offer(ctx: Context<UnacceptOffer>) -> Result<()> {
+        let offer = &mut ctx.accounts.offer;
+        let request = &mut ctx.accounts.request;
+        let now = Clock::get().unwrap().unix_timestamp as u64;
+
+        if request.buyer_id != ctx.accounts.user.id {
+            return err!(MarketplaceError::UnauthorizedBuyer);
+        }
+
+        if offer.request_id != request.id {
+            return err!(MarketplaceError::InvalidOffer);
+        }
+
+        if request.mode == RequestMode::ReverseAuction {
+            return err!(MarketplaceError::AuctionRequest);
+        }
+
+        if request.lifecycle == RequestLifecycle::Cancelled {
+            return err!(MarketplaceError::RequestCancelled);
+        }
+
+        if !offer.is_accepted {
+            return err!(MarketplaceError::OfferNotAccepted);
+        }
+
+        if offer.is_paid {
+            return err!(MarketplaceError::OfferAlreadyPaid);
+        }
+
+        if is_request_locked(request, now) {
+            return err!(MarketplaceError::RequestLocked);
+        }
+
+        if request.pooled && request.pool_funded_participants > 0 {
+            return err!(MarketplaceError::PoolAlreadyFunded);
+        }
+
+        let was_fully_accepted = request.accepted_quantity >= request.quantity;
+
+        offer.is_accepted = false;
+        offer.updated_at = now;
+
+        request.accepted_quantity = request.accepted_quantity.checked_sub(offer.quantity).unwrap();
+        request.offer_ids.retain(|id| *id != offer.id);
+        request.updated_at = now;
+
+        match request.offer_ids.last() {
+            Some(last_offer_id) => {
+                if request.accepted_offer_id == offer.id {
+                    request.accepted_offer_id = *last_offer_id;
+                }
+            }
+            None => {
+                request.accepted_offer_id = 0;
+                request.locked_seller_id = 0;
+                request.sellers_price_quote = 0;
+                request.lifecycle = RequestLifecycle::AcceptedBySeller;
+            }
+        }
+
+        if was_fully_accepted {
+            ctx.accounts.category.request_ids.push(request.id);
+            resize_account(&ctx.accounts.category, &ctx.accounts.authority, &ctx.accounts.system_program)?;
+            ctx.accounts.geo_cell.request_ids.push(request.id);
+            resize_account(&ctx.accounts.geo_cell, &ctx.accounts.authority, &ctx.accounts.system_program)?;
+        }
+
+        emit!(OfferAccepted {
+            offer_id: offer.id,
+            buyer_address: *ctx.accounts.user.to_account_info().key,
+            is_accepted: false,
+        });
+
+        Ok(())
+    }
+
+    /// Remaining accounts are every offer on the request followed by the
+    /// seller stats of each of those offers, in the same order. An auction no
+    /// offer can win is cancelled, and the buyer gets the deposit back with
+    /// `delete_request`.
+    pub fn settle_auction(ctx: Context<SettleAuction>) -> Result<()> {
+        let request = &mut ctx.accounts.request;
+        let now = Clock::get().unwrap().unix_timestamp as u64;
+
+        if request.mode != RequestMode::ReverseAuction {
+            return err!(MarketplaceError::NotAuctionRequest);
+        }
+
+        if now <= request.bidding_deadline {
+            return err!(MarketplaceError::BiddingNotClosed);
+        }
+
+        if request.sealed_bids && now <= request.reveal_deadline {
+            return err!(MarketplaceError::RevealNotClosed);
+        }
+
+        if request.lifecycle != RequestLifecycle::Pending
+            && request.lifecycle != RequestLifecycle::AcceptedBySeller
+        {
+            return err!(MarketplaceError::AuctionAlreadySettled);
+        }
+
+        if request.seller_ids.is_empty() {
+            return err!(MarketplaceError::NoOffers);
+        }
+
+        let offers_count = request.seller_ids.len();
+
+        if offers_count * 2 != ctx.remaining_accounts.len() {
+            return err!(MarketplaceError::IncorrectNumberOfSellers);
+        }
+
+        let (offer_accounts, seller_stats_accounts) = ctx.remaining_accounts.split_at(offers_count);
+        let mut offer_ids: Vec<u64> = Vec::with_capacity(offers_count);
+        let mut candidates: Vec<(usize, Offer)> = Vec::with_capacity(offers_count);
+
+        for (index, account_info) in offer_accounts.iter().enumerate() {
+            if account_info.owner != ctx.program_id {
+                return err!(MarketplaceError::InvalidOffer);
+            }
+
+            let data = account_info.try_borrow_data()?;
+            let candidate = Offer::try_deserialize(&mut data.as_ref())?;
+
+            if candidate.request_id != request.id || offer_ids.contains(&candidate.id) {
+                return err!(MarketplaceError::InvalidOffer);
+            }
+
+            offer_ids.push(candidate.id);
+
+            if candidate.is_revealed && !candidate.is_withdrawn {
+                candidates.push((index, candidate));
+            }
+        }
+
+        candidates.sort_by_key(|(_, offer)| (offer.price, offer.created_at, offer.id));
+
+        let mut settled = false;
+
+        for (index, mut offer) in candidates {
+            if offer.quantity > request.quantity - request.accepted_quantity {
+                continue;
+            }
+
+            if request.pooled && offer.quantity != request.quantity {
+                continue;
+            }
+
+            lock_accepted_offer(request, &mut offer, now);
+
+            let mut data = offer_accounts[index].try_borrow_mut_data()?;
+            offer.try_serialize(&mut data.as_mut())?;
+            settled = true;
+
+            let seller_stats_info = &seller_stats_accounts[index];
+
+            if seller_stats_info.owner != ctx.program_id {
+                return err!(MarketplaceError::InvalidUser);
+            }
+
+            let mut seller_stats_data = seller_stats_info.try_borrow_mut_data()?;
+            let mut seller_stats = UserStats::try_deserialize(&mut seller_stats_data.as_ref())?;
+
+            if seller_stats.authority != offer.authority {
+                return err!(MarketplaceError::InvalidUser);
+            }
+
+            record_acceptance_stats(&mut ctx.accounts.buyer_stats, &mut seller_stats, request, &offer);
+            seller_stats.try_serialize(&mut seller_stats_data.as_mut())?;
+
+            emit!(RequestAccepted {
+                request_id: request.id,
+                offer_id: offer.id,
+                seller_id: offer.seller_id,
+                updated_at: request.updated_at,
+                sellers_price_quote: request.sellers_price_quote,
+                quantity: offer.quantity,
+                accepted_quantity: request.accepted_quantity,
+            });
+
+            emit!(OfferAccepted {
+                offer_id: offer.id,
+                buyer_address: request.authority,
+                is_accepted: true,
+            });
+
+            emit!(AuctionSettled {
+                request_id: request.id,
+                offer_id: offer.id,
+                seller_id: offer.seller_id,
+                price: offer.price,
+                offers_count: offer_ids.len() as u64,
+                settled_at: now,
+            });
+
+            if request.accepted_quantity >= request.quantity {
+                break;
+            }
+        }
+
+        // Nothing could win, so the auction is cancelled without forfeiting
+        // the deposit, which the buyer gets back with delete_request.
+        if !settled {
+            request.lifecycle = RequestLifecycle::Cancelled;
+            request.updated_at = now;
+
+            let buyer_stats = &mut ctx.accounts.buyer_stats;
+            touch_user_stats(buyer_stats, request.authority, request.buyer_id);
+            buyer_stats.open_requests = buyer_stats.open_requests.checked_sub(1).unwrap();
+
+            emit!(AuctionFailed {
+                request_id: request.id,
+                offers_count: offer_ids.len() as u64,
+                failed_at: now,
+            });
+
+            emit!(RequestCancelled {
+                request_id: request.id,
+                cancelled_at: now,
+            });
+        }
+
+        ctx.accounts.category.request_ids.retain(|id| *id != request.id);
+        ctx.accounts.geo_cell.request_ids.retain(|id| *id != request.id);
+
+        Ok(())
+    }
+
+    pub fn join_pool(ctx: Context<JoinPool>, quantity: u64, deposit: u64) -> Result<()> {
+        let user = &ctx.accounts.user;
+        let request = &mut ctx.accounts.request;
+        let participant = &mut ctx.accounts.participant;
+        let authority = &ctx.accounts.authority;
+        let now = Clock::get().unwrap().unix_timestamp as u64;
+
+        if !user.has_role(ROLE_BUYER) {
+            return err!(MarketplaceError::OnlyBuyersAllowed);
+        }
+
+        if !request.pooled {
+            return err!(MarketplaceError::NotPooledRequest);
+        }
+
+        if request.lifecycle == RequestLifecycle::Cancelled {
+            return err!(MarketplaceError::RequestCancelled);
+        }
+
+        if now > request.pool_deadline || request.paid {
+            return err!(MarketplaceError::PoolClosed);
+        }
+
+        if quantity == 0 {
+            return err!(MarketplaceError::InvalidQuantity);
+        }
+
+        if quantity > request.quantity - request.pool_claimed_quantity {
+            return err!(MarketplaceError::QuantityExceeded);
+        }
+
+        if deposit > 0 {
+            let transfer_instruction = system_instruction::transfer(authority.key, &request.key(), deposit);
+
+            anchor_lang::solana_program::program::invoke(
+                &transfer_instruction,
+                &[
+                    authority.to_account_info(),
+                    request.to_account_info(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+            )?;
+        }
+
+        participant.authority = authority.key();
+        participant.request_id = request.id;
+        participant.user_id = user.id;
+        participant.quantity = quantity;
+        participant.escrowed = deposit;
+        participant.is_funded = false;
+        participant.created_at = now;
+        participant.updated_at = now;
+
+        request.pool_claimed_quantity = request.pool_claimed_quantity.checked_add(quantity).unwrap();
+        request.pool_escrowed = request.pool_escrowed.checked_add(deposit).unwrap();
+        request.pool_participants = request.pool_participants.checked_add(1).unwrap();
+
+        emit!(PoolJoined {
+            request_id: request.id,
+            participant: participant.authority,
+            user_id: participant.user_id,
+            quantity: participant.quantity,
+            deposit,
+            pool_claimed_quantity: request.pool_claimed_quantity,
+        });
+
+        Ok(())
+    }
+
+    pub fn fund_pool(ctx: Context<FundPool>) -> Result<()> {
+        let request = &mut ctx.accounts.request;
+        let offer = &ctx.accounts.offer;
+        let participant = &mut ctx.accounts.participant;
+        let authority = &ctx.accounts.authority;
+        let now = Clock::get().unwrap().unix_timestamp as u64;
+
+        if !request.pooled {
+            return err!(MarketplaceError::NotPooledRequest);
+        }
+
+        if request.lifecycle != RequestLifecycle::AcceptedByBuyer || !offer.is_accepted {
+            return err!(MarketplaceError::RequestNotAccepted);
+        }
+
+        if request.updated_at + TIME_TO_LOCK > now {
+            return err!(MarketplaceError::RequestNotLocked);
+        }
+
+        if now > request.pool_deadline {
+            return err!(MarketplaceError::PoolClosed);
+        }
+
+        if participant.is_funded {
+            return err!(MarketplaceError::ParticipantAlreadyFunded);
+        }
+
+        let total_price = offer.price.checked_mul(offer.quantity).unwrap();
+
+        // The last participant to fund pays whatever the rounded down shares
+        // of everyone else left over, so the pool escrows exactly the price.
+        let is_last_funder = request.pool_claimed_quantity == request.quantity
+            && request.pool_funded_participants.checked_add(1).unwrap() == request.pool_participants;
+
+        let share = if is_last_funder {
+            total_price
+                .checked_sub(request.pool_escrowed - participant.escrowed)
+                .ok_or(MarketplaceError::PoolAlreadyFunded)?
+        } else {
+            pool_share(total_price, participant.quantity, request.quantity)
+        };
+
+        if share > participant.escrowed {
+            let transfer_instruction = system_instruction::transfer(
+                authority.key,
+                &request.key(),
+                share - participant.escrowed,
+            );
+
+            anchor_lang::solana_program::program::invoke(
+                &transfer_instruction,
+                &[
+                    authority.to_account_info(),
+                    request.to_account_info(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+            )?;
+        } else if participant.escrowed > share {
+            transfer_lamports(
+                &request.to_account_info(),
+                &authority.to_account_info(),
+                participant.escrowed - share,
+            )?;
+        }
+
+        request.pool_escrowed = request.pool_escrowed - participant.escrowed + share;
+        request.pool_funded_participants = request.pool_funded_participants.checked_add(1).unwrap();
+        participant.escrowed = share;
+        participant.is_funded = true;
+        participant.updated_at = now;
+
+        emit!(PoolFunded {
+            request_id: request.id,
+            participant: participant.authority,
+            share,
+            pool_funded_participants: request.pool_funded_participants,
+        });
+
+        Ok(())
+    }
+
+    pub fn execute_pool(ctx: Context<ExecutePool>) -> Result<()> {
+        let request = &mut ctx.accounts.request;
+        let offer = &mut ctx.accounts.offer;
+        let request_payment_counter = &mut ctx.accounts.request_payment_counter;
+        let request_payment_info = &mut ctx.accounts.request_payment_info;
+        let now = Clock::get().unwrap().unix_timestamp as u64;
+
+        if !request.pooled {
+            return err!(MarketplaceError::NotPooledRequest);
+        }
+
+        if request.lifecycle != RequestLifecycle::AcceptedByBuyer || !offer.is_accepted {
+            return err!(MarketplaceError::RequestNotAccepted);
+        }
+
+        if request.paid || offer.is_paid {
+            return err!(MarketplaceError::RequestAlreadyPaid);
+        }
+
+        if now > request.pool_deadline {
+            return err!(MarketplaceError::PoolClosed);
+        }
+
+        let amount = offer.price.checked_mul(offer.quantity).unwrap();
+
+        if !is_pool_funded(request) || request.pool_escrowed != amount {
+            return err!(MarketplaceError::PoolNotFunded);
+        }
+
+        transfer_lamports(&request.to_account_info(), &ctx.accounts.to.to_account_info(), amount)?;
+
+        record_payment_stats(
+            &mut ctx.accounts.buyer_stats,
+            &mut ctx.accounts.seller_stats,
+            request,
+            offer,
+            &CoinPayment::Solana,
+            amount,
+        );
+
+        request.pool_escrowed = 0;
+        mark_offer_paid(request, offer, now);
+        record_request_payment(request_payment_info, request_payment_counter, request, offer, CoinPayment::Solana, amount);
+
+        emit!(PoolExecuted {
+            request_id: request.id,
+            offer_id: offer.id,
+            amount,
+        });
+
+        Ok(())
+    }
+
+    pub fn refund_pool(ctx: Context<RefundPool>) -> Result<()> {
+        let request = &mut ctx.accounts.request;
+        let participant = &ctx.accounts.participant;
+
+        if !request.pooled {
+            return err!(MarketplaceError::NotPooledRequest);
+        }
+
+        if Clock::get().unwrap().unix_timestamp as u64 <= request.pool_deadline {
+            return err!(MarketplaceError::PoolStillOpen);
+        }
+
+        // A pool that was funded but not executed by the deadline can no
+        // longer be executed, so it is refunded like an unfunded one.
+        if request.paid {
+            return err!(MarketplaceError::RequestAlreadyPaid);
+        }
+
+        transfer_lamports(
+            &request.to_account_info(),
+            &ctx.accounts.participant_authority.to_account_info(),
+            participant.escrowed,
+        )?;
+
+        request.pool_escrowed = request.pool_escrowed.checked_sub(participant.escrowed).unwrap();
+        request.pool_claimed_quantity = request.pool_claimed_quantity.checked_sub(participant.quantity).unwrap();
+        request.pool_participants = request.pool_participants.checked_sub(1).unwrap();
+
+        if participant.is_funded {
+            request.pool_funded_participants = request.pool_funded_participants.checked_sub(1).unwrap();
+        }
+
+        emit!(PoolRefunded {
+            request_id: request.id,
+            participant: participant.authority,
+            amount: participant.escrowed,
+        });
+
+        Ok(())
+    }
+}
+
+pub fn offer_price_commitment(price: u64, salt: &[u8; 32]) -> [u8; 32] {
+    hashv(&[&price.to_le_bytes(), salt]).to_bytes()
+}
+
+fn max_request_offers(request: &Request) -> usize {
+    if request.mode == RequestMode::ReverseAuction {
+        MAX_AUCTION_OFFERS
+    } else {
+        MAX_OFFERS_PER_REQUEST
+    }
+}
+
+fn lock_accepted_offer(request: &mut Request, offer: &mut Offer, now: u64) {
+    offer.is_accepted = true;
+    offer.updated_at = now;
+    request.offer_ids.push(offer.id);
+    request.locked_seller_id = offer.seller_id;
+    request.sellers_price_quote = offer.price;
+    request.accepted_offer_id = offer.id;
+    request.accepted_quantity = request.accepted_quantity.checked_add(offer.quantity).unwrap();
+    request.lifecycle = RequestLifecycle::AcceptedByBuyer;
+    request.updated_at = now;
+}
+
+fn record_request_payment(
+    payment: &mut RequestPaymentTransaction,
+    counter: &mut Counter,
+    request: &Request,
+    offer: &Offer,
+    coin: CoinPayment,
+    amount: u64,
+) {
+    let now = Clock::get().unwrap().unix_timestamp as u64;
+
+    payment.authority = request.authority;
+    payment.request_id = request.id;
+    payment.offer_id = offer.id;
+    payment.quantity = offer.quantity;
+    payment.buyer_id = request.buyer_id;
+    payment.price = offer.price;
+    payment.seller_authority = offer.authority;
+    payment.seller_id = offer.seller_id;
+    payment.created_at = now;
+    payment.updated_at = now;
+    payment.token = coin;
+    payment.amount = amount;
+    payment.id = counter.current;
+    counter.current = counter.current.checked_add(1).unwrap();
+}
+
+fn mark_offer_paid(request: &mut Request, offer: &mut Offer, now: u64) {
+    offer.is_paid = true;
+    offer.updated_at = now;
+    request.paid_quantity = request.paid_quantity.checked_add(offer.quantity).unwrap();
+
+    if request.paid_quantity >= request.quantity {
+        request.paid = true;
+        request.lifecycle = RequestLifecycle::Paid;
+    }
+}
+
+fn resize_account<'info, T>(
+    account: &Account<'info, T>,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+) -> Result<()>
+where
+    T: AccountSerialize + AccountDeserialize + Owner + Clone,
+{
+    let mut data = Vec::new();
+    account.try_serialize(&mut data)?;
+
+    grow_account(&account.to_account_info(), data.len(), payer, system_program)
+}
+
+// Reallocates to at least `len` bytes, topping up rent from the payer.
+fn grow_account<'info>(
+    account_info: &AccountInfo<'info>,
+    len: usize,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+) -> Result<()> {
+    if len <= account_info.data_len() {
+        return Ok(());
+    }
+
+    let minimum_balance = Rent::get()?.minimum_balance(len);
+
+    if minimum_balance > account_info.lamports() {
+        let transfer_instruction = system_instruction::transfer(
+            payer.key,
+            account_info.key,
+            minimum_balance - account_info.lamports(),
+        );
+
+        anchor_lang::solana_program::program::invoke(
+            &transfer_instruction,
+            &[
+                payer.to_account_info(),
+                account_info.clone(),
+                system_program.to_account_info(),
+            ],
+        )?;
+    }
+
+    account_info.realloc(len, false)?;
+
+    Ok(())
+}
+
+fn is_pool_funded(request: &Request) -> bool {
+    request.pool_claimed_quantity == request.quantity
+        && request.pool_participants > 0
+        && request.pool_funded_participants == request.pool_participants
+}
+
+fn pool_share(total_price: u64, quantity: u64, pool_quantity: u64) -> u64 {
+    let share = total_price as u128 * quantity as u128 / pool_quantity as u128;
+
+    share as u64
+}
+
+// Migrated stores may be over MAX_OFFERS_PER_STORE, they take no new offers
+// until pruned below it.
+fn push_store_offer(store: &mut Store, offer_id: u64) -> Result<()> {
+    if store.offer_ids.len() >= MAX_OFFERS_PER_STORE {
+        return err!(MarketplaceError::StoreOfferLimit);
+    }
+
+    store.offer_ids.push(offer_id);
+
+    Ok(())
+}
+
+// Offers only reserve MAX_NAME_LEN bytes for the store name, and names of
+// stores created before check_name may be longer.
+fn offer_store_name(store: &Store) -> String {
+    let mut end = store.name.len().min(MAX_NAME_LEN);
+
+    while !store.name.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    store.name[..end].to_string()
+}
+
+fn check_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.len() > MAX_NAME_LEN {
+        return err!(MarketplaceError::InvalidName);
+    }
+
+    Ok(())
+}
+
+fn check_description(description: &str) -> Result<()> {
+    if description.len() > MAX_DESCRIPTION_LEN {
+        return err!(MarketplaceError::DescriptionTooLong);
+    }
+
+    Ok(())
+}
+
+fn check_images(images: &[String]) -> Result<()> {
+    if images.len() > MAX_IMAGES {
+        return err!(MarketplaceError::TooManyImages);
+    }
+
+    if images
+        .iter()
+        .any(|image| image.is_empty() || image.len() > MAX_IMAGE_URL_LEN)
+    {
+        return err!(MarketplaceError::InvalidImageUrl);
+    }
+
+    Ok(())
+}
+
+fn is_valid_username(username: &str) -> bool {
+    (MIN_USERNAME_LEN..=MAX_USERNAME_LEN).contains(&username.len())
+        && username
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'_')
+}
+
+// Seed of a username record, the lowercase name. The name is validated while
+// the accounts are checked, as one over the 32-byte seed limit would otherwise
+// fail with an opaque seeds error.
+fn username_seed(username: &str) -> Result<String> {
+    if !is_valid_username(username) {
+        return err!(MarketplaceError::InvalidUsername);
+    }
+
+    Ok(username.to_ascii_lowercase())
+}
+
+// Seed of the record a user holds. Names of users created before usernames
+// were validated may not fit in a seed, those map to an address no record is
+// ever created at.
+fn username_record_seed(username: &str) -> Vec<u8> {
+    if is_valid_username(username) {
+        username.to_ascii_lowercase().into_bytes()
+    } else {
+        hashv(&[username.as_bytes()]).to_bytes().to_vec()
+    }
+}
+
+// Closes the record of `username`, whose address is checked by the seeds of
+// the accounts struct. A valid name must be claimed before it can be
+// released. A record held by another authority is left alone, as it was
+// registered before this user claimed the name.
+fn release_username_record<'info>(
+    record: &UncheckedAccount<'info>,
+    username: &str,
+    authority: &Signer<'info>,
+    program_id: &Pubkey,
+) -> Result<()> {
+    if record.owner != program_id {
+        if is_valid_username(username) {
+            return err!(MarketplaceError::UsernameNotClaimed);
+        }
+        return Ok(());
+    }
+
+    let username_record = {
+        let data = record.try_borrow_data()?;
+        UsernameRecord::try_deserialize(&mut data.as_ref())?
+    };
+
+    if username_record.authority != authority.key() {
+        return Ok(());
+    }
+
+    transfer_lamports(&record.to_account_info(), &authority.to_account_info(), record.lamports())?;
+    record.assign(&System::id());
+    record.realloc(0, false)?;
+
+    Ok(())
+}
+
+// `account_type` is kept for older clients; sellers that also buy report as sellers.
+fn primary_account_type(roles: u8) -> AccountType {
+    if roles & ROLE_SELLER != 0 {
+        AccountType::Seller
+    } else {
+        AccountType::Buyer
+    }
+}
+
+fn check_store_verification(request: &Request, store: &Store) -> Result<()> {
+    if request.min_verification_level == 0 {
+        return Ok(());
+    }
+
+    let expired = store.verified_until != 0
+        && store.verified_until <= Clock::get().unwrap().unix_timestamp as u64;
+
+    if store.verification_level < request.min_verification_level || expired {
+        return err!(MarketplaceError::StoreNotVerified);
+    }
+
+    Ok(())
+}
+
+// Events leave the geohash empty for users who disabled their location, like
+// the coordinates. The account still holds it, as it keys the geo cell.
+fn event_geohash(user: &User, geohash: &str) -> String {
+    if user.location_enabled {
+        geohash.to_string()
+    } else {
+        String::new()
+    }
+}
+
+fn store_updated_event(store: &Store, user: &Account<User>) -> StoreUpdated {
+    StoreUpdated {
+        seller_address: user.key(),
+        store_id: store.id,
+        store_name: store.name.clone(),
+        latitude: user.location_enabled.then_some(store.location.latitude),
+        longitude: user.location_enabled.then_some(store.location.longitude),
+        geohash: event_geohash(user, &store.geohash),
+        service_radius: store.service_radius,
+        opening_hours: store.opening_hours,
+        utc_offset_minutes: store.utc_offset_minutes,
+        is_away: store.is_away,
+    }
+}
+
+// The store authority can always act for the store; anyone else needs a
+// delegate account granted by the current authority with `permission` set.
+fn check_store_signer(
+    store: &Store,
+    signer: &Pubkey,
+    store_delegate: Option<&StoreDelegate>,
+    permission: u8,
+) -> Result<()> {
+    if *signer == store.authority {
+        return Ok(());
+    }
+
+    match store_delegate {
+        Some(store_delegate)
+            if store_delegate.delegate == *signer
+                && store_delegate.store_id == store.id
+                && store_delegate.granted_by == store.authority
+                && store_delegate.permissions & permission != 0 =>
+        {
+            Ok(())
+        }
+        _ => err!(MarketplaceError::UnauthorizedDelegate),
+    }
+}
+
+// Sorts the store's offers passed in `accounts` into finished ones (completed,
+// withdrawn, or on a cancelled request) and idle ones (never or no longer
+// accepted). Accepted offers still in progress are in neither list. The
+// request of an offer that is not accepted must be passed too, so that
+// withdrawing it frees its slot on the request.
+fn verify_store_offers(
+    store: &Store,
+    accounts: &[AccountInfo],
+    program_id: &Pubkey,
+) -> Result<(Vec<u64>, Vec<u64>)> {
+    let mut offers: Vec<Offer> = Vec::with_capacity(accounts.len());
+    let mut request_ids: Vec<u64> = Vec::new();
+    let mut cancelled_request_ids: Vec<u64> = Vec::new();
+
+    for account_info in accounts.iter() {
+        if account_info.owner != program_id {
+            return err!(MarketplaceError::InvalidOffer);
+        }
+
+        let data = account_info.try_borrow_data()?;
+
+        if let Ok(offer) = Offer::try_deserialize(&mut data.as_ref()) {
+            if offer.store_id != store.id || !store.offer_ids.contains(&offer.id) {
+                return err!(MarketplaceError::InvalidOffer);
+            }
+            offers.push(offer);
+        } else {
+            let request = Request::try_deserialize(&mut data.as_ref())?;
+            request_ids.push(request.id);
+            if request.lifecycle == RequestLifecycle::Cancelled {
+                cancelled_request_ids.push(request.id);
+            }
+        }
+    }
+
+    let mut finished: Vec<u64> = Vec::new();
+    let mut idle: Vec<u64> = Vec::new();
+
+    for offer in offers {
+        if offer.is_completed || offer.is_withdrawn || cancelled_request_ids.contains(&offer.request_id) {
+            finished.push(offer.id);
+        } else if !offer.is_accepted {
+            if !request_ids.contains(&offer.request_id) {
+                return err!(MarketplaceError::OfferRequestMissing);
+            }
+            idle.push(offer.id);
+        }
+    }
+
+    Ok((finished, idle))
+}
+
+// Withdraws every live offer on a request that is about to be closed.
+// `accounts` must hold exactly the offers whose sellers are still listed.
+fn withdraw_request_offers(request: &Request, accounts: &[AccountInfo], program_id: &Pubkey) -> Result<()> {
+    if request.seller_ids.len() != accounts.len() {
+        return err!(MarketplaceError::IncorrectNumberOfSellers);
+    }
+
+    let mut offer_ids: Vec<u64> = Vec::with_capacity(accounts.len());
+
+    for account_info in accounts.iter() {
+        if account_info.owner != program_id {
+            return err!(MarketplaceError::InvalidOffer);
+        }
+
+        let mut data = account_info.try_borrow_mut_data()?;
+        let mut offer = Offer::try_deserialize(&mut data.as_ref())?;
+
+        if offer.request_id != request.id || offer.is_withdrawn || offer_ids.contains(&offer.id) {
+            return err!(MarketplaceError::InvalidOffer);
+        }
+
+        offer_ids.push(offer.id);
+        offer.is_withdrawn = true;
+        offer.updated_at = Clock::get().unwrap().unix_timestamp as u64;
+        offer.try_serialize(&mut data.as_mut())?;
+
+        emit!(OfferWithdrawn {
+            offer_id: offer.id,
+            request_id: offer.request_id,
+            store_id: offer.store_id,
+        });
+    }
+
+    Ok(())
+}
+
+// Marks the idle offers among `accounts` withdrawn, so buyers can no longer
+// accept offers from a store that closed or changed hands, and drops their
+// sellers from the requests passed with them. `accounts` must have been
+// checked by verify_store_offers.
+fn withdraw_idle_offers(store: &Store, accounts: &[AccountInfo], idle: &[u64]) -> Result<()> {
+    let mut withdrawn: Vec<(u64, u64)> = Vec::with_capacity(idle.len());
+
+    for account_info in accounts.iter() {
+        let mut data = account_info.try_borrow_mut_data()?;
+
+        let mut offer = match Offer::try_deserialize(&mut data.as_ref()) {
+            Ok(offer) => offer,
+            Err(_) => continue,
+        };
+
+        if offer.is_withdrawn || !idle.contains(&offer.id) {
+            continue;
+        }
+
+        offer.is_withdrawn = true;
+        offer.updated_at = Clock::get().unwrap().unix_timestamp as u64;
+        offer.try_serialize(&mut data.as_mut())?;
+        withdrawn.push((offer.request_id, offer.seller_id));
+
+        emit!(OfferWithdrawn {
+            offer_id: offer.id,
+            request_id: offer.request_id,
+            store_id: store.id,
+        });
+    }
+
+    for account_info in accounts.iter() {
+        let mut data = account_info.try_borrow_mut_data()?;
+
+        let mut request = match Request::try_deserialize(&mut data.as_ref()) {
+            Ok(request) => request,
+            Err(_) => continue,
+        };
+
+        let mut changed = false;
+
+        for (request_id, seller_id) in withdrawn.iter() {
+            if *request_id != request.id {
+                continue;
+            }
+
+            if let Some(position) = request.seller_ids.iter().position(|id| id == seller_id) {
+                request.seller_ids.remove(position);
+                changed = true;
+            }
+        }
+
+        if changed {
+            request.try_serialize(&mut data.as_mut())?;
+        }
+    }
+
+    Ok(())
+}
+
+// Sellers have offered but nothing has been paid for yet.
+fn is_request_abandonable(request: &Request) -> bool {
+    (request.lifecycle == RequestLifecycle::AcceptedBySeller
+        || request.lifecycle == RequestLifecycle::AcceptedByBuyer)
+        && request.paid_quantity == 0
+        && request.pool_escrowed == 0
+}
+
+fn abandon_request(request: &mut Account<Request>, treasury: &AccountInfo) -> Result<()> {
+    let now = Clock::get().unwrap().unix_timestamp as u64;
+    let amount = request.deposit;
+
+    request.lifecycle = RequestLifecycle::Cancelled;
+    request.deposit = 0;
+    request.updated_at = now;
+
+    if amount > 0 {
+        transfer_lamports(&request.to_account_info(), treasury, amount)?;
+
+        emit!(RequestDepositForfeited {
+            request_id: request.id,
+            amount,
+            treasury: treasury.key(),
+        });
+    }
+
+    emit!(RequestCancelled {
+        request_id: request.id,
+        cancelled_at: now,
+    });
+
+    Ok(())
+}
+
+fn check_seller_bond(admin: &Admin, seller_bond: Option<&SellerBond>) -> Result<()> {
+    if admin.min_bond_lamports == 0 && admin.min_bond_tokens == 0 {
+        return Ok(());
+    }
+
+    let seller_bond = seller_bond.ok_or(MarketplaceError::BondRequired)?;
+
+    let minimum = match seller_bond.mint {
+        None => admin.min_bond_lamports,
+        Some(mint) if mint == admin.bond_mint => admin.min_bond_tokens,
+        Some(_) => return err!(MarketplaceError::InvalidBondMint),
+    };
+
+    let staked = seller_bond.amount.checked_sub(seller_bond.unstake_amount).unwrap();
+
+    if minimum == 0 || staked < minimum {
+        return err!(MarketplaceError::InsufficientBond);
+    }
+
+    Ok(())
+}
+
+fn init_seller_bond(
+    seller_bond: &mut SellerBond,
+    authority: Pubkey,
+    seller_id: u64,
+    mint: Option<Pubkey>,
+) -> Result<()> {
+    if seller_bond.authority == Pubkey::default() {
+        seller_bond.authority = authority;
+        seller_bond.seller_id = seller_id;
+        seller_bond.mint = mint;
+        seller_bond.created_at = Clock::get().unwrap().unix_timestamp as u64;
+    } else if seller_bond.mint != mint {
+        return err!(MarketplaceError::InvalidBondMint);
+    }
+
+    Ok(())
+}
+
+fn take_unstaked_bond(seller_bond: &mut SellerBond, cooldown: u64) -> Result<u64> {
+    if seller_bond.unstake_amount == 0 {
+        return err!(MarketplaceError::NoUnstakeRequested);
+    }
+
+    let now = Clock::get().unwrap().unix_timestamp as u64;
+
+    if now < seller_bond.unstake_requested_at + cooldown {
+        return err!(MarketplaceError::UnstakeCooldown);
+    }
+
+    let amount = seller_bond.unstake_amount;
+
+    seller_bond.amount = seller_bond.amount.checked_sub(amount).unwrap();
+    seller_bond.unstake_amount = 0;
+    seller_bond.unstake_requested_at = 0;
+    seller_bond.updated_at = now;
+
+    Ok(amount)
+}
+
+fn slash_seller_bond(seller_bond: &mut SellerBond, amount: u64) -> Result<()> {
+    if amount == 0 || amount > seller_bond.amount {
+        return err!(MarketplaceError::InvalidBondAmount);
+    }
+
+    seller_bond.amount -= amount;
+    seller_bond.unstake_amount = seller_bond.unstake_amount.min(seller_bond.amount);
+    seller_bond.updated_at = Clock::get().unwrap().unix_timestamp as u64;
+
+    Ok(())
+}
+
+fn transfer_lamports(from: &AccountInfo, to: &AccountInfo, amount: u64) -> Result<()> {
+    **from.try_borrow_mut_lamports()? = from.lamports().checked_sub(amount).unwrap();
+    **to.try_borrow_mut_lamports()? = to.lamports().checked_add(amount).unwrap();
+
+    Ok(())
+}
+
+fn is_request_locked(request: &Request, now: u64) -> bool {
+    request.lifecycle == RequestLifecycle::AcceptedByBuyer
+        && request.accepted_quantity >= request.quantity
+        && now > request.updated_at + TIME_TO_LOCK
+}
+
+fn lamports_to_pyusd(price_update: &PriceUpdateV2, lamports: u64) -> Result<u64> {
+    let current_price = price_update.get_price_no_older_than(
+        &Clock::get()?,
+        MAXIMUM_AGE,
+        &get_feed_id_from_hex(SOL_USD_PRICE_FEED)?,
+    )?;
+
+    let sol_price_in_usd = u128::try_from(current_price.price)
+        .map_err(|_| error!(MarketplaceError::PriceOutOfRange))?;
+
+    let amount = (lamports as u128)
+        .checked_mul(sol_price_in_usd)
+        .ok_or(MarketplaceError::PriceOutOfRange)?
+        / 100000000000;
+
+    u64::try_from(amount).map_err(|_| error!(MarketplaceError::PriceOutOfRange))
+}
+
+// The budget bounds the per-unit price, like `Offer::price`; the buyer pays
+// `price * quantity` for an offer.
+fn check_budget(request: &Request, price: u64, price_update: Option<&PriceUpdateV2>) -> Result<()> {
+    if request.min_price.is_none() && request.max_price.is_none() {
+        return Ok(());
+    }
+
+    let quoted_price = match request.budget_currency {
+        CoinPayment::Solana => price,
+        CoinPayment::Pyusdt => {
+            let price_update = price_update.ok_or(MarketplaceError::PriceFeedRequired)?;
+            lamports_to_pyusd(price_update, price)?
+        }
+    };
+
+    if let Some(min_price) = request.min_price {
+        if quoted_price < min_price {
+            return err!(MarketplaceError::OfferBelowBudget);
+        }
+    }
+
+    if let Some(max_price) = request.max_price {
+        if quoted_price > max_price {
+            return err!(MarketplaceError::OfferAboveBudget);
+        }
+    }
+
+    Ok(())
+}
+
+// Rewrites an account still in the deployed legacy layout in the current one.
+// Current accounts carry ACCOUNT_VERSION, so they are never migrated twice.
+fn migrate_legacy_account<'info, L, T>(
+    account: &AccountInfo<'info>,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+    migrate: impl FnOnce(L) -> Result<T>,
+) -> Result<T>
+where
+    L: AnchorDeserialize,
+    T: AccountSerialize + AccountDeserialize + anchor_lang::Discriminator + Versioned,
+{
+    if account.owner != &crate::ID {
+        return err!(MarketplaceError::InvalidLegacyAccount);
+    }
+
+    let migrated = {
+        let data = account.try_borrow_data()?;
+
+        if data.len() < 8 || data[..8] != T::DISCRIMINATOR {
+            return err!(MarketplaceError::InvalidLegacyAccount);
+        }
+
+        if let Ok(current) = T::try_deserialize(&mut &data[..]) {
+            if current.version() == ACCOUNT_VERSION {
+                return err!(MarketplaceError::AccountAlreadyMigrated);
+            }
+        }
+
+        let legacy = L::deserialize(&mut &data[8..])
+            .map_err(|_| MarketplaceError::InvalidLegacyAccount)?;
+
+        migrate(legacy)?
+    };
+
+    let mut serialized = Vec::new();
+    migrated.try_serialize(&mut serialized)?;
+
+    grow_account(account, serialized.len(), payer, system_program)?;
+
+    let mut data = account.try_borrow_mut_data()?;
+    data.fill(0);
+    data[..serialized.len()].copy_from_slice(&serialized);
+
+    Ok(migrated)
+}
+
+// Stats accounts are created lazily for users that predate them.
+fn touch_user_stats(stats: &mut UserStats, authority: Pubkey, user_id: u64) {
+    let now = Clock::get().unwrap().unix_timestamp as u64;
+
+    if stats.authority == Pubkey::default() {
+        stats.authority = authority;
+        stats.user_id = user_id;
+        stats.created_at = now;
+    }
+
+    stats.updated_at = now;
+}
+
+fn record_acceptance_stats(buyer_stats: &mut UserStats, seller_stats: &mut UserStats, request: &Request, offer: &Offer) {
+    touch_user_stats(buyer_stats, request.authority, request.buyer_id);
+    buyer_stats.acceptances_given = buyer_stats.acceptances_given.checked_add(1).unwrap();
+    buyer_stats.last_active_at = buyer_stats.updated_at;
+
+    touch_user_stats(seller_stats, offer.authority, offer.seller_id);
+    seller_stats.offers_accepted = seller_stats.offers_accepted.checked_add(1).unwrap();
+}
+
+// Called before mark_offer_paid, while offer.updated_at is still the time the
+// offer was accepted.
+fn record_payment_stats(
+    buyer_stats: &mut UserStats,
+    seller_stats: &mut UserStats,
+    request: &Request,
+    offer: &Offer,
+    coin: &CoinPayment,
+    amount: u64,
+) {
+    let now = Clock::get().unwrap().unix_timestamp as u64;
+
+    touch_user_stats(buyer_stats, request.authority, request.buyer_id);
+    touch_user_stats(seller_stats, offer.authority, offer.seller_id);
+
+    buyer_stats.payments_made = buyer_stats.payments_made.checked_add(1).unwrap();
+    buyer_stats.last_active_at = now;
+
+    if now <= offer.updated_at + ON_TIME_PAYMENT_WINDOW {
+        buyer_stats.payments_on_time = buyer_stats.payments_on_time.checked_add(1).unwrap();
+    }
+
+    for stats in [buyer_stats, seller_stats] {
+        match coin {
+            CoinPayment::Solana => stats.sol_volume = stats.sol_volume.checked_add(amount).unwrap(),
+            CoinPayment::Pyusdt => stats.pyusd_volume = stats.pyusd_volume.checked_add(amount).unwrap(),
+        }
+    }
+}
+
+fn check_contact_parties(
+    request: &Request,
+    offer: &Offer,
+    sender: &Pubkey,
+    recipient: &Pubkey,
+) -> Result<()> {
+    if offer.request_id != request.id || !offer.is_accepted {
+        return err!(MarketplaceError::InvalidOffer);
+    }
+
+    if request.lifecycle == RequestLifecycle::Pending
+        || request.lifecycle == RequestLifecycle::AcceptedBySeller
+    {
+        return err!(MarketplaceError::RequestNotAccepted);
+    }
+
+    let buyer_to_seller = *sender == request.authority && *recipient == offer.authority;
+    let seller_to_buyer = *sender == offer.authority && *recipient == request.authority;
+
+    if !buyer_to_seller && !seller_to_buyer {
+        return err!(MarketplaceError::InvalidContactParties);
+    }
+
+    Ok(())
+}
+
+// Only the coarse request location is on chain, the south-west corner of its
+// LOCATION_GRID_SIZE cell, so the buyer may be up to one cell diagonal (about
+// 1.6 km at the equator, less towards the poles) further from the store than
+// measured here. Buyers and sellers should leave that margin in their radius.
+fn check_seller_distance(request: &Request, store: &Store) -> Result<()> {
+    if request.max_seller_distance == 0 && store.service_radius == 0 {
+        return Ok(());
+    }
+
+    let distance = distance_in_meters(&store.location, &request.location);
+
+    if request.max_seller_distance != 0 && distance > request.max_seller_distance {
+        return err!(MarketplaceError::SellerOutOfRange);
+    }
+
+    if store.service_radius != 0 && distance > store.service_radius {
+        return err!(MarketplaceError::SellerOutOfRange);
+    }
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(username: String)]
+pub struct CreateUser<'info> {
+    #[account(
+        init,
+        seeds = [USER_TAG,authority.key.as_ref()],
+        bump,
+        payer = authority,
+        space = 8 + size_of::<User>() + User::MAX_DATA_LEN)]
+    pub user: Box<Account<'info, User>>,
+    #[account(
+        init_if_needed,
+        seeds = [USER_STATS_TAG, authority.key.as_ref()],
+        bump,
+        payer = authority,
+        space = 8 + size_of::<UserStats>())]
+    pub user_stats: Box<Account<'info, UserStats>>,
+    #[account(
+        init,
+        seeds = [USERNAME_TAG, username_seed(&username)?.as_bytes()],
+        bump,
+        payer = authority,
+        space = 8 + size_of::<UsernameRecord>() + UsernameRecord::MAX_DATA_LEN)]
+    pub username_record: Box<Account<'info, UsernameRecord>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [USER_COUNTER],
+        bump,
+    )]
+    pub user_counter: Box<Account<'info, Counter>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction()]
+pub struct UpdateUser<'info> {
+    #[account(
+        mut,
+        seeds = [USER_TAG,authority.key().as_ref()],
+        bump,
+        has_one = authority
+    )]
+    pub user: Box<Account<'info, User>>,
+    #[account(
+        init_if_needed,
+        seeds = [USER_STATS_TAG, authority.key().as_ref()],
+        bump,
+        payer = authority,
+        space = 8 + size_of::<UserStats>()
+    )]
+    pub user_stats: Box<Account<'info, UserStats>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimUsername<'info> {
+    #[account(
+        seeds = [USER_TAG, user.authority.as_ref()],
+        bump,
+    )]
+    pub user: Box<Account<'info, User>>,
+    #[account(
+        init,
+        seeds = [USERNAME_TAG, username_seed(&user.username)?.as_bytes()],
+        bump,
+        payer = payer,
+        space = 8 + size_of::<UsernameRecord>() + UsernameRecord::MAX_DATA_LEN
+    )]
+    pub username_record: Box<Account<'info, UsernameRecord>>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(username: String)]
+pub struct RenameUser<'info> {
+    #[account(
+        mut,
+        seeds = [USER_TAG, authority.key().as_ref()],
+        bump,
+        has_one = authority
+    )]
+    pub user: Box<Account<'info, User>>,
+    /// CHECK: checked against the user in release_username_record, may not
+    /// exist for users created before usernames were validated
+    #[account(
+        mut,
+        seeds = [USERNAME_TAG, username_record_seed(&user.username).as_ref()],
+        bump
+    )]
+    pub old_username_record: UncheckedAccount<'info>,
+    // for case-only renames this is the existing record
+    #[account(
+        init_if_needed,
+        seeds = [USERNAME_TAG, username_seed(&username)?.as_bytes()],
+        bump,
+        payer = authority,
+        space = 8 + size_of::<UsernameRecord>() + UsernameRecord::MAX_DATA_LEN
+    )]
+    pub new_username_record: Box<Account<'info, UsernameRecord>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseUser<'info> {
+    #[account(
+        mut,
+        seeds = [USER_TAG, authority.key().as_ref()],
+        bump,
+        has_one = authority,
+        close = authority
+    )]
+    pub user: Box<Account<'info, User>>,
+    // kept on close, so closing cannot reset a user's history
+    #[account(
+        init_if_needed,
+        seeds = [USER_STATS_TAG, authority.key().as_ref()],
+        bump,
+        payer = authority,
+        space = 8 + size_of::<UserStats>()
+    )]
+    pub user_stats: Box<Account<'info, UserStats>>,
+    /// CHECK: checked against the user in release_username_record, may not
+    /// exist for users created before usernames were validated
+    #[account(
+        mut,
+        seeds = [USERNAME_TAG, username_record_seed(&user.username).as_ref()],
+        bump
+    )]
+    pub username_record: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction()]
+pub struct CreateStore<'info> {
+    #[account(
+        mut,
+        seeds = [USER_TAG,authority.key().as_ref()],
+        bump,
+        has_one = authority
+    )]
+    pub user: Box<Account<'info, User>>,
+    #[account(init, payer = authority ,space = 8 + size_of::<Store>() + Store::MAX_DATA_LEN,        
+    seeds = [STORE_TAG, authority.key().as_ref(),&store_counter.current.to_le_bytes()],
+    bump,)]
+    pub store: Box<Account<'info, Store>>,
+    #[account(seeds = [ADMIN_TAG], bump)]
+    pub admin: Box<Account<'info, Admin>>,
+    #[account(
+        mut,
+        seeds = [GEO_CELL_TAG, geo_cell.geohash.as_bytes()],
+        bump,
+    )]
+    pub geo_cell: Box<Account<'info, GeoCell>>,
+    #[account(
+        init_if_needed,
+        seeds = [USER_STATS_TAG, authority.key().as_ref()],
+        bump,
+        payer = authority,
+        space = 8 + size_of::<UserStats>()
+    )]
+    pub user_stats: Box<Account<'info, UserStats>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [STORE_COUNTER],
+        bump,
+    )]
+    pub store_counter: Box<Account<'info, Counter>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction()]
+pub struct UpdateStore<'info> {
+    #[account(
+        seeds = [USER_TAG,authority.key().as_ref()],
+        bump,
+        has_one = authority
+    )]
+    pub user: Box<Account<'info, User>>,
+    #[account(
+        mut,
+        seeds = [STORE_TAG, store.creator.as_ref(), &store.id.to_le_bytes()],
+        bump,
+        has_one = authority
+    )]
+    pub store: Box<Account<'info, Store>>,
+    #[account(seeds = [ADMIN_TAG], bump)]
+    pub admin: Box<Account<'info, Admin>>,
+    #[account(
+        mut,
+        seeds = [GEO_CELL_TAG, store.geohash.as_bytes()],
+        bump,
+    )]
+    pub geo_cell: Box<Account<'info, GeoCell>>,
+    // only needed when the store moves to another geohash cell
+    #[account(
+        mut,
+        seeds = [GEO_CELL_TAG, new_geo_cell.geohash.as_bytes()],
+        bump,
+    )]
+    pub new_geo_cell: Option<Box<Account<'info, GeoCell>>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction()]
+pub struct SetStoreAvailability<'info> {
+    #[account(
+        seeds = [USER_TAG,authority.key().as_ref()],
+        bump,
+        has_one = authority
+    )]
+    pub user: Box<Account<'info, User>>,
+    #[account(
+        mut,
+        seeds = [STORE_TAG, store.creator.as_ref(), &store.id.to_le_bytes()],
+        bump,
+        has_one = authority
+    )]
+    pub store: Box<Account<'info, Store>>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction()]
+pub struct CloseStore<'info> {
+    #[account(
+        seeds = [USER_TAG,authority.key().as_ref()],
+        bump,
+        has_one = authority
+    )]
+    pub user: Box<Account<'info, User>>,
+    #[account(
+        mut,
+        seeds = [STORE_TAG, store.creator.as_ref(), &store.id.to_le_bytes()],
+        bump,
+        has_one = authority,
+        close = authority
+    )]
+    pub store: Box<Account<'info, Store>>,
+    #[account(
+        mut,
+        seeds = [GEO_CELL_TAG, store.geohash.as_bytes()],
+        bump,
+    )]
+    pub geo_cell: Box<Account<'info, GeoCell>>,
+    #[account(
+        mut,
+        seeds = [USER_STATS_TAG, authority.key().as_ref()],
+        bump,
+    )]
+    pub user_stats: Box<Account<'info, UserStats>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction()]
+pub struct PruneStoreOffers<'info> {
+    #[account(
+        mut,
+        seeds = [STORE_TAG, store.creator.as_ref(), &store.id.to_le_bytes()],
+        bump,
+        has_one = authority
+    )]
+    pub store: Box<Account<'info, Store>>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction()]
+pub struct TransferStore<'info> {
+    #[account(
+        seeds = [USER_TAG,authority.key().as_ref()],
+        bump,
+        has_one = authority
+    )]
+    pub user: Box<Account<'info, User>>,
+    #[account(
+        mut,
+        seeds = [STORE_TAG, store.creator.as_ref(), &store.id.to_le_bytes()],
+        bump,
+        has_one = authority
+    )]
+    pub store: Box<Account<'info, Store>>,
+    #[account(
+        seeds = [USER_TAG, new_authority.key().as_ref()],
+        bump,
+        constraint = new_user.authority == new_authority.key() @ MarketplaceError::InvalidUser,
+    )]
+    pub new_user: Box<Account<'info, User>>,
+    #[account(
+        mut,
+        seeds = [USER_STATS_TAG, authority.key().as_ref()],
+        bump,
+    )]
+    pub user_stats: Box<Account<'info, UserStats>>,
+    #[account(
+        init_if_needed,
+        seeds = [USER_STATS_TAG, new_authority.key().as_ref()],
+        bump,
+        payer = authority,
+        space = 8 + size_of::<UserStats>()
+    )]
+    pub new_user_stats: Box<Account<'info, UserStats>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub new_authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(delegate: Pubkey)]
+pub struct SetStoreDelegate<'info> {
+    #[account(
+        seeds = [STORE_TAG, store.creator.as_ref(), &store.id.to_le_bytes()],
+        bump,
+        has_one = authority
+    )]
+    pub store: Box<Account<'info, Store>>,
+    #[account(
+        init_if_needed,
+        seeds = [STORE_DELEGATE_TAG, store.key().as_ref(), delegate.as_ref()],
+        bump,
+        payer = authority,
+        space = 8 + size_of::<StoreDelegate>()
+    )]
+    pub store_delegate: Box<Account<'info, StoreDelegate>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction()]
+pub struct RemoveStoreDelegate<'info> {
+    #[account(
+        seeds = [STORE_TAG, store.creator.as_ref(), &store.id.to_le_bytes()],
+        bump,
+        has_one = authority
+    )]
+    pub store: Box<Account<'info, Store>>,
+    #[account(
+        mut,
+        seeds = [STORE_DELEGATE_TAG, store.key().as_ref(), store_delegate.delegate.as_ref()],
+        bump,
+        close = authority
+    )]
+    pub store_delegate: Box<Account<'info, StoreDelegate>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction()]
+pub struct CreateListing<'info> {
+    #[account(
+        seeds = [STORE_TAG, store.creator.as_ref(), &store.id.to_le_bytes()],
+        bump,
+        has_one = authority
+    )]
+    pub store: Box<Account<'info, Store>>,
+    #[account(init, payer = authority, space = 8 + size_of::<Listing>() + Listing::MAX_DATA_LEN,
+    seeds = [LISTING_TAG, store.key().as_ref(), &listing_counter.current.to_le_bytes()],
+    bump,)]
+    pub listing: Box<Account<'info, Listing>>,
+    #[account(
+        mut,
+        seeds = [LISTING_COUNTER],
+        bump,
+    )]
+    pub listing_counter: Box<Account<'info, Counter>>,
+    #[account(
+        seeds = [CATEGORY_TAG, category.name.as_bytes()],
+        bump,
+    )]
+    pub category: Box<Account<'info, Category>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction()]
+pub struct UpdateListing<'info> {
+    #[account(
+        seeds = [STORE_TAG, store.creator.as_ref(), &store.id.to_le_bytes()],
+        bump,
+        has_one = authority
+    )]
+    pub store: Box<Account<'info, Store>>,
+    #[account(
+        mut,
+        seeds = [LISTING_TAG, store.key().as_ref(), &listing.id.to_le_bytes()],
+        bump,
+    )]
+    pub listing: Box<Account<'info, Listing>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction()]
+pub struct CloseListing<'info> {
+    #[account(
+        seeds = [STORE_TAG, store.creator.as_ref(), &store.id.to_le_bytes()],
+        bump,
+        has_one = authority
+    )]
+    pub store: Box<Account<'info, Store>>,
+    #[account(
+        mut,
+        seeds = [LISTING_TAG, store.key().as_ref(), &listing.id.to_le_bytes()],
+        bump,
+        close = authority
+    )]
+    pub listing: Box<Account<'info, Listing>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction()]
+pub struct BuyListing<'info> {
+    #[account(
+        seeds = [USER_TAG, authority.key().as_ref()],
+        bump,
+        has_one = authority
+    )]
+    pub user: Box<Account<'info, User>>,
+    #[account(
+        seeds = [USER_TAG, store.authority.as_ref()],
+        bump,
+    )]
+    pub seller: Box<Account<'info, User>>,
+    #[account(
+        mut,
+        seeds = [STORE_TAG, store.creator.as_ref(), &store.id.to_le_bytes()],
+        bump,
+    )]
+    pub store: Box<Account<'info, Store>>,
+    #[account(
+        mut,
+        seeds = [LISTING_TAG, store.key().as_ref(), &listing.id.to_le_bytes()],
+        bump,
+    )]
+    pub listing: Box<Account<'info, Listing>>,
+    #[account(init, payer = authority, space = 8 + size_of::<Request>() + Request::MAX_DATA_LEN,
+    seeds = [REQUEST_TAG, authority.key().as_ref(), &request_counter.current.to_le_bytes()],
+    bump,)]
+    pub request: Box<Account<'info, Request>>,
+    #[account(
+        mut,
+        seeds = [REQUEST_COUNTER],
+        bump,
+    )]
+    pub request_counter: Box<Account<'info, Counter>>,
+    #[account(init, payer = authority, space = 8 + size_of::<Offer>() + Offer::MAX_DATA_LEN,
+    seeds = [OFFER_TAG, store.authority.as_ref(), &offer_counter.current.to_le_bytes()],
+    bump,)]
+    pub offer: Box<Account<'info, Offer>>,
+    #[account(
+        mut,
+        seeds = [OFFER_COUNTER],
+        bump,
+    )]
+    pub offer_counter: Box<Account<'info, Counter>>,
+    #[account(init, payer = authority, space = 8 + size_of::<RequestPaymentTransaction>() + 1024,
+    seeds = [REQUEST_PAYMENT_TAG, authority.key().as_ref(), &request_payment_counter.current.to_le_bytes()],
+    bump,)]
+    pub request_payment_info: Box<Account<'info, RequestPaymentTransaction>>,
+    #[account(
+        mut,
+        seeds = [REQUEST_PAYMENT_COUNTER],
+        bump,
+    )]
+    pub request_payment_counter: Box<Account<'info, Counter>>,
+    #[account(
+        init_if_needed,
+        seeds = [USER_STATS_TAG, authority.key().as_ref()],
+        bump,
+        payer = authority,
+        space = 8 + size_of::<UserStats>()
+    )]
+    pub buyer_stats: Box<Account<'info, UserStats>>,
+    #[account(
+        init_if_needed,
+        seeds = [USER_STATS_TAG, store.authority.as_ref()],
+        bump,
+        payer = authority,
+        space = 8 + size_of::<UserStats>()
+    )]
+    pub seller_stats: Box<Account<'info, UserStats>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    /// CHECK: This is the account to which SOL payments are made
+    #[account(mut, address = PORTAL_CLIENT_PUBKEY)]
+    pub to: AccountInfo<'info>,
+    // token accounts are only needed for PYUSD payments
+    #[account(mut)]
+    pub from_ata: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, address = PORTAL_PYUSD_TOKEN_ACCOUNT_PUBKEY)]
+    pub to_ata: Option<InterfaceAccount<'info, TokenAccount>>,
+    pub mint: Option<InterfaceAccount<'info, Mint>>,
+    pub token_program: Option<Interface<'info, TokenInterface>>,
+    #[account(address = PYTH_USDC_FEED)]
+    pub price_update: Option<Account<'info, PriceUpdateV2>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction()]
+pub struct CreateRequest<'info> {
+    #[account(
+        mut,
+        seeds = [USER_TAG,authority.key().as_ref()],
+        bump,
+        has_one = authority
+    )]
+    pub user: Box<Account<'info, User>>,
+    #[account(init, payer = authority ,space = 8 + size_of::<Request>() + Request::MAX_DATA_LEN,        
+    seeds = [REQUEST_TAG, authority.key().as_ref(),&request_counter.current.to_le_bytes()],
+    bump,)]
+    pub request: Box<Account<'info, Request>>,
+    #[account(
+        mut,
+        seeds = [CATEGORY_TAG, category.name.as_bytes()],
+        bump,
+    )]
+    pub category: Box<Account<'info, Category>>,
+    #[account(seeds = [ADMIN_TAG], bump)]
+    pub admin: Box<Account<'info, Admin>>,
+    #[account(
+        mut,
+        seeds = [GEO_CELL_TAG, geo_cell.geohash.as_bytes()],
+        bump,
+    )]
+    pub geo_cell: Box<Account<'info, GeoCell>>,
+    #[account(
+        mut,
+        seeds = [REQUEST_COUNTER],
+        bump,
+    )]
+    pub request_counter: Box<Account<'info, Counter>>,
+    #[account(
+        init_if_needed,
+        seeds = [USER_STATS_TAG, authority.key().as_ref()],
+        bump,
+        payer = authority,
+        space = 8 + size_of::<UserStats>()
+    )]
+    pub user_stats: Box<Account<'info, UserStats>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveRequest<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [REQUEST_TAG, authority.key().as_ref(), &request.id.to_le_bytes()],
+        bump,
+        close = authority
+    )]
+    pub request: Box<Account<'info, Request>>,
+
+    #[account(
+        mut,
+        seeds = [CATEGORY_TAG, request.category.as_bytes()],
+        bump,
+    )]
+    pub category: Box<Account<'info, Category>>,
+    #[account(
+        mut,
+        seeds = [GEO_CELL_TAG, request.geohash.as_bytes()],
+        bump,
+    )]
+    pub geo_cell: Box<Account<'info, GeoCell>>,
+    #[account(
+        init_if_needed,
+        seeds = [USER_STATS_TAG, authority.key().as_ref()],
+        bump,
+        payer = authority,
+        space = 8 + size_of::<UserStats>()
+    )]
+    pub user_stats: Box<Account<'info, UserStats>>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ToggleLocation<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [USER_TAG,authority.key().as_ref()],
+        bump,
+        has_one = authority
+    )]
+    pub user: Box<Account<'info, User>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct GetLocationPreference<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [USER_TAG,authority.key().as_ref()],
+        bump,
+        has_one = authority
+    )]
+    pub user: Box<Account<'info, User>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MarkAsCompleteRequest<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [REQUEST_TAG, authority.key().as_ref(), &request.id.to_le_bytes()],
+        bump,
+    )]
+    pub request: Box<Account<'info, Request>>,
+
+    #[account(mut)]
+    pub offer: Box<Account<'info, Offer>>,
+
+    #[account(
+        init_if_needed,
+        seeds = [USER_STATS_TAG, authority.key().as_ref()],
+        bump,
+        payer = authority,
+        space = 8 + size_of::<UserStats>()
+    )]
+    pub buyer_stats: Box<Account<'info, UserStats>>,
+
+    #[account(
+        init_if_needed,
+        seeds = [USER_STATS_TAG, offer.authority.as_ref()],
+        bump,
+        payer = authority,
+        space = 8 + size_of::<UserStats>()
+    )]
+    pub seller_stats: Box<Account<'info, UserStats>>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct LeaveReview<'info> {
+    pub request: Box<Account<'info, Request>>,
+    pub offer: Box<Account<'info, Offer>>,
+    #[account(
+        init,
+        seeds = [REVIEW_TAG, offer.key().as_ref(), reviewer.key().as_ref()],
+        bump,
+        payer = reviewer,
+        space = 8 + size_of::<Review>()
+    )]
+    pub review: Box<Account<'info, Review>>,
+    #[account(
+        init_if_needed,
+        seeds = [USER_STATS_TAG, reviewee.key().as_ref()],
+        bump,
+        payer = reviewer,
+        space = 8 + size_of::<UserStats>()
+    )]
+    pub reviewee_stats: Box<Account<'info, UserStats>>,
+    /// CHECK: counterparty wallet, checked against the request and offer authorities
+    pub reviewee: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub reviewer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+#[derive(Accounts)]
+pub struct PayForRequest<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [REQUEST_TAG, authority.key().as_ref(), &request.id.to_le_bytes()],
+        bump,
+    )]
+    pub request: Box<Account<'info, Request>>,
+
+    #[account(
+        mut,
+    )]
+    pub offer: Box<Account<'info, Offer>>,
+
+    #[account(init, payer = authority ,space = 8 + size_of::<RequestPaymentTransaction>() + 1024,   
+    seeds = [REQUEST_PAYMENT_TAG, authority.key().as_ref(),&request_payment_counter.current.to_le_bytes()],
+    bump,)]
+    pub request_payment_info: Box<Account<'info, RequestPaymentTransaction>>,
+
+    #[account(
+        mut,
+        seeds = [REQUEST_PAYMENT_COUNTER],
+        bump,
+    )]
+    pub request_payment_counter: Box<Account<'info, Counter>>,
+
+    #[account(
+        init_if_needed,
+        seeds = [USER_STATS_TAG, authority.key().as_ref()],
+        bump,
+        payer = authority,
+        space = 8 + size_of::<UserStats>()
+    )]
+    pub buyer_stats: Box<Account<'info, UserStats>>,
+    #[account(
+        init_if_needed,
+        seeds = [USER_STATS_TAG, offer.authority.as_ref()],
+        bump,
+        payer = authority,
+        space = 8 + size_of::<UserStats>()
+    )]
+    pub seller_stats: Box<Account<'info, UserStats>>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    /// CHECK: This is the account to which the payment is made
+    #[account(mut, address = PORTAL_CLIENT_PUBKEY)]
+    pub to: AccountInfo<'info>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+
+#[derive(Accounts)]
+pub struct PayForRequestToken<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [REQUEST_TAG, authority.key().as_ref(), &request.id.to_le_bytes()],
+        bump,
+    )]
+    pub request: Box<Account<'info, Request>>,
+
+    #[account(init, payer = authority ,space = 8 + size_of::<RequestPaymentTransaction>() + 1024,   
+    seeds = [REQUEST_PAYMENT_TAG, authority.key().as_ref(),&request_payment_counter.current.to_le_bytes()],
+    bump,)]
+    pub request_payment_info: Box<Account<'info, RequestPaymentTransaction>>,
+
+    #[account(
+        mut,
+        seeds = [REQUEST_PAYMENT_COUNTER],
+        bump,
+    )]
+    pub request_payment_counter: Box<Account<'info, Counter>>,
+
+    #[account(
+        mut,
+    )]
+    pub offer: Box<Account<'info, Offer>>,
+
+    #[account(
+        init_if_needed,
+        seeds = [USER_STATS_TAG, authority.key().as_ref()],
+        bump,
+        payer = authority,
+        space = 8 + size_of::<UserStats>()
+    )]
+    pub buyer_stats: Box<Account<'info, UserStats>>,
+    #[account(
+        init_if_needed,
+        seeds = [USER_STATS_TAG, offer.authority.as_ref()],
+        bump,
+        payer = authority,
+        space = 8 + size_of::<UserStats>()
+    )]
+    pub seller_stats: Box<Account<'info, UserStats>>,
+    
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    
+    pub system_program: Program<'info, System>,
+
+    #[account(mut)]
+    pub from_ata: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut,address = PORTAL_PYUSD_TOKEN_ACCOUNT_PUBKEY)]
+    pub to_ata: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// CHECK: this is the price feed
+    #[account(address = PYTH_USDC_FEED)]
+    pub price_update: Account<'info, PriceUpdateV2>,
+
+
+    pub mint: InterfaceAccount<'info, Mint>,
+}
+
+#[derive(Accounts)]
+#[instruction()]
+pub struct CreateOffer<'info> {
+    // offers are always made as the store owner, even when a delegate signs
+    #[account(
+        mut,
+        seeds = [USER_TAG, store.authority.as_ref()],
+        bump,
+    )]
+    pub user: Box<Account<'info, User>>,
+    #[account(mut)]
+    pub request: Box<Account<'info, Request>>,
+    #[account(init, payer = authority ,space = 8 + size_of::<Offer>() + Offer::MAX_DATA_LEN,     
+    seeds = [OFFER_TAG, store.authority.as_ref(),&offer_counter.current.to_le_bytes()],
+    bump,)]
+    pub offer: Box<Account<'info, Offer>>,
+    #[account(
+        mut,
+        seeds = [STORE_TAG, store.creator.as_ref(), &store.id.to_le_bytes()],
+        bump,
+    )]
+    pub store: Box<Account<'info, Store>>,
+    #[account(
+        seeds = [STORE_DELEGATE_TAG, store.key().as_ref(), authority.key().as_ref()],
+        bump,
+    )]
+    pub store_delegate: Option<Account<'info, StoreDelegate>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [OFFER_COUNTER],
+        bump,
+    )]
+    pub offer_counter: Box<Account<'info, Counter>>,
+    #[account(seeds = [ADMIN_TAG], bump)]
+    pub admin: Box<Account<'info, Admin>>,
+    #[account(
+        seeds = [SELLER_BOND_TAG, store.authority.as_ref()],
+        bump,
+    )]
+    pub seller_bond: Option<Account<'info, SellerBond>>,
+    #[account(
+        init_if_needed,
+        seeds = [USER_STATS_TAG, store.authority.as_ref()],
+        bump,
+        payer = authority,
+        space = 8 + size_of::<UserStats>()
+    )]
+    pub user_stats: Box<Account<'info, UserStats>>,
+    /// CHECK: this is the price feed, only needed when the request budget is in PYUSD
+    #[account(address = PYTH_USDC_FEED)]
+    pub price_update: Option<Account<'info, PriceUpdateV2>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevealOffer<'info> {
+    #[account(
+        mut,
+        seeds = [OFFER_TAG, offer.authority.as_ref(), &offer.id.to_le_bytes()],
+        bump,
+    )]
+    pub offer: Box<Account<'info, Offer>>,
+    #[account(constraint = request.id == offer.request_id @ MarketplaceError::InvalidOffer)]
+    pub request: Box<Account<'info, Request>>,
+    #[account(
+        seeds = [STORE_TAG, store.creator.as_ref(), &store.id.to_le_bytes()],
+        bump,
+        constraint = store.id == offer.store_id @ MarketplaceError::InvalidOffer,
+        constraint = store.authority == offer.authority @ MarketplaceError::InvalidUser,
+    )]
+    pub store: Box<Account<'info, Store>>,
+    #[account(
+        seeds = [STORE_DELEGATE_TAG, store.key().as_ref(), authority.key().as_ref()],
+        bump,
+    )]
+    pub store_delegate: Option<Account<'info, StoreDelegate>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    /// CHECK: this is the price feed, only needed when the request budget is in PYUSD
+    #[account(address = PYTH_USDC_FEED)]
+    pub price_update: Option<Account<'info, PriceUpdateV2>>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateOffer<'info> {
+    #[account(
+        mut,
+        seeds = [OFFER_TAG, offer.authority.as_ref(), &offer.id.to_le_bytes()],
+        bump,
+    )]
+    pub offer: Box<Account<'info, Offer>>,
+    #[account(constraint = request.id == offer.request_id @ MarketplaceError::InvalidOffer)]
+    pub request: Box<Account<'info, Request>>,
+    #[account(
+        seeds = [STORE_TAG, store.creator.as_ref(), &store.id.to_le_bytes()],
+        bump,
+        constraint = store.id == offer.store_id @ MarketplaceError::InvalidOffer,
+        constraint = store.authority == offer.authority @ MarketplaceError::InvalidUser,
+    )]
+    pub store: Box<Account<'info, Store>>,
+    #[account(
+        seeds = [STORE_DELEGATE_TAG, store.key().as_ref(), authority.key().as_ref()],
+        bump,
+    )]
+    pub store_delegate: Option<Account<'info, StoreDelegate>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    /// CHECK: this is the price feed, only needed when the request budget is in PYUSD
+    #[account(address = PYTH_USDC_FEED)]
+    pub price_update: Option<Account<'info, PriceUpdateV2>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ConfirmDelivery<'info> {
+    #[account(
+        mut,
+        seeds = [OFFER_TAG, offer.authority.as_ref(), &offer.id.to_le_bytes()],
+        bump,
+    )]
+    pub offer: Box<Account<'info, Offer>>,
+    #[account(
+        seeds = [STORE_TAG, store.creator.as_ref(), &store.id.to_le_bytes()],
+        bump,
+        constraint = store.id == offer.store_id @ MarketplaceError::InvalidOffer,
+        constraint = store.authority == offer.authority @ MarketplaceError::InvalidUser,
+    )]
+    pub store: Box<Account<'info, Store>>,
+    #[account(
+        seeds = [STORE_DELEGATE_TAG, store.key().as_ref(), authority.key().as_ref()],
+        bump,
+    )]
+    pub store_delegate: Option<Account<'info, StoreDelegate>>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction()]
+pub struct AcceptOffer<'info> {
+    #[account(
+        mut,
+        seeds = [USER_TAG,authority.key().as_ref()],
+        bump,
+        has_one = authority
+    )]
+    pub user: Box<Account<'info, User>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(mut)]
+    pub offer: Box<Account<'info, Offer>>,
+    #[account(mut)]
+    pub request: Box<Account<'info, Request>>,
+    #[account(
+        mut,
+        seeds = [CATEGORY_TAG, request.category.as_bytes()],
+        bump,
+    )]
+    pub category: Box<Account<'info, Category>>,
+    #[account(
+        mut,
+        seeds = [GEO_CELL_TAG, request.geohash.as_bytes()],
+        bump,
+    )]
+    pub geo_cell: Box<Account<'info, GeoCell>>,
+    #[account(
+        init_if_needed,
+        seeds = [USER_STATS_TAG, authority.key().as_ref()],
+        bump,
+        payer = authority,
+        space = 8 + size_of::<UserStats>()
+    )]
+    pub buyer_stats: Box<Account<'info, UserStats>>,
+    #[account(
+        init_if_needed,
+        seeds = [USER_STATS_TAG, offer.authority.as_ref()],
+        bump,
+        payer = authority,
+        space = 8 + size_of::<UserStats>()
+    )]
+    pub seller_stats: Box<Account<'info, UserStats>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UnacceptOffer<'info> {
+    #[account(
+        seeds = [USER_TAG, authority.key().as_ref()],
+        bump,
+        has_one = authority
+    )]
+    pub user: Box<Account<'info, User>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(mut)]
+    pub offer: Box<Account<'info, Offer>>,
+    #[account(mut)]
+    pub request: Box<Account<'info, Request>>,
+    #[account(
+        mut,
+        seeds = [CATEGORY_TAG, request.category.as_bytes()],
+        bump,
+    )]
+    pub category: Box<Account<'info, Category>>,
+    #[account(
+        mut,
+        seeds = [GEO_CELL_TAG, request.geohash.as_bytes()],
+        bump,
+    )]
+    pub geo_cell: Box<Account<'info, GeoCell>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SettleAuction<'info> {
+    #[account(mut)]
+    pub request: Box<Account<'info, Request>>,
+    #[account(
+        mut,
+        seeds = [CATEGORY_TAG, request.category.as_bytes()],
+        bump,
+    )]
+    pub category: Box<Account<'info, Category>>,
+    #[account(
+        mut,
+        seeds = [GEO_CELL_TAG, request.geohash.as_bytes()],
+        bump,
+    )]
+    pub geo_cell: Box<Account<'info, GeoCell>>,
+    #[account(
+        init_if_needed,
+        seeds = [USER_STATS_TAG, request.authority.as_ref()],
+        bump,
+        payer = authority,
+        space = 8 + size_of::<UserStats>()
+    )]
+    pub buyer_stats: Box<Account<'info, UserStats>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct JoinPool<'info> {
+    #[account(
+        seeds = [USER_TAG, authority.key().as_ref()],
+        bump,
+        has_one = authority
+    )]
+    pub user: Box<Account<'info, User>>,
+    #[account(mut)]
+    pub request: Box<Account<'info, Request>>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + size_of::<PoolParticipant>(),
+        seeds = [POOL_PARTICIPANT_TAG, request.key().as_ref(), authority.key().as_ref()],
+        bump,
+    )]
+    pub participant: Box<Account<'info, PoolParticipant>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FundPool<'info> {
+    #[account(mut)]
+    pub request: Box<Account<'info, Request>>,
+    #[account(constraint = offer.request_id == request.id @ MarketplaceError::InvalidOffer)]
+    pub offer: Box<Account<'info, Offer>>,
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [POOL_PARTICIPANT_TAG, request.key().as_ref(), authority.key().as_ref()],
+        bump,
+    )]
+    pub participant: Box<Account<'info, PoolParticipant>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExecutePool<'info> {
+    #[account(mut)]
+    pub request: Box<Account<'info, Request>>,
+
+    #[account(
+        mut,
+        constraint = offer.request_id == request.id @ MarketplaceError::InvalidOffer
+    )]
+    pub offer: Box<Account<'info, Offer>>,
+
+    #[account(init, payer = authority ,space = 8 + size_of::<RequestPaymentTransaction>() + 1024,
+    seeds = [REQUEST_PAYMENT_TAG, authority.key().as_ref(),&request_payment_counter.current.to_le_bytes()],
+    bump,)]
+    pub request_payment_info: Box<Account<'info, RequestPaymentTransaction>>,
+
+    #[account(
+        mut,
+        seeds = [REQUEST_PAYMENT_COUNTER],
+        bump,
+    )]
+    pub request_payment_counter: Box<Account<'info, Counter>>,
+
+    #[account(
+        init_if_needed,
+        seeds = [USER_STATS_TAG, request.authority.as_ref()],
+        bump,
+        payer = authority,
+        space = 8 + size_of::<UserStats>()
+    )]
+    pub buyer_stats: Box<Account<'info, UserStats>>,
+    #[account(
+        init_if_needed,
+        seeds = [USER_STATS_TAG, offer.authority.as_ref()],
+        bump,
+        payer = authority,
+        space = 8 + size_of::<UserStats>()
+    )]
+    pub seller_stats: Box<Account<'info, UserStats>>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    /// CHECK: This is the account to which the payment is made
+    #[account(mut, address = PORTAL_CLIENT_PUBKEY)]
+    pub to: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RefundPool<'info> {
+    #[account(mut)]
+    pub request: Box<Account<'info, Request>>,
+    #[account(
+        mut,
+        seeds = [POOL_PARTICIPANT_TAG, request.key().as_ref(), participant.authority.as_ref()],
+        bump,
+        close = participant_authority
+    )]
+    pub participant: Box<Account<'info, PoolParticipant>>,
+    /// CHECK: This is the participant receiving the refund
+    #[account(mut, address = participant.authority)]
+    pub participant_authority: AccountInfo<'info>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeAdmin<'info> {
+    #[account(
+        init,
+        seeds = [ADMIN_TAG],
+        bump,
+        payer = authority,
+        space = 8 + size_of::<Admin>() + 1024
+    )]
+    pub admin: Box<Account<'info, Admin>>,
+
+    // Only the upgrade authority of the deployed program can claim the admin
+    // account, so it cannot be front-run after deployment.
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::Marketplace>,
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(authority.key())
+            @ MarketplaceError::UnauthorizedAdmin
+    )]
+    pub program_data: Account<'info, ProgramData>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateAdmin<'info> {
+    #[account(
+        mut,
+        seeds = [ADMIN_TAG],
+        bump,
+        has_one = authority
+    )]
+    pub admin: Box<Account<'info, Admin>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct VerifyStore<'info> {
+    #[account(seeds = [ADMIN_TAG], bump)]
+    pub admin: Box<Account<'info, Admin>>,
+    #[account(
+        mut,
+        seeds = [STORE_TAG, store.creator.as_ref(), &store.id.to_le_bytes()],
+        bump,
+    )]
+    pub store: Box<Account<'info, Store>>,
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ShareContact<'info> {
+    pub request: Box<Account<'info, Request>>,
+    pub offer: Box<Account<'info, Offer>>,
+    #[account(
+        init,
+        payer = sender,
+        space = 8 + size_of::<ContactShare>() + MAX_ENCRYPTED_CONTACT_LEN,
+        seeds = [CONTACT_TAG, request.key().as_ref(), sender.key().as_ref(), recipient.key().as_ref()],
+        bump,
+    )]
+    pub contact_share: Box<Account<'info, ContactShare>>,
+    /// CHECK: counterparty wallet, checked against the request and offer authorities
+    pub recipient: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub sender: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RotateContact<'info> {
+    pub request: Box<Account<'info, Request>>,
+    pub offer: Box<Account<'info, Offer>>,
+    #[account(
+        mut,
+        has_one = sender,
+        seeds = [CONTACT_TAG, request.key().as_ref(), sender.key().as_ref(), contact_share.recipient.as_ref()],
+        bump,
+    )]
+    pub contact_share: Box<Account<'info, ContactShare>>,
+    pub sender: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SharePreciseLocation<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [REQUEST_TAG, authority.key().as_ref(), &request.id.to_le_bytes()],
+        bump,
+    )]
+    pub request: Box<Account<'info, Request>>,
+    pub offer: Box<Account<'info, Offer>>,
+    // sharing again with the same seller replaces their key
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + size_of::<LocationKeyShare>() + MAX_ENCRYPTED_LOCATION_KEY_LEN,
+        seeds = [LOCATION_KEY_TAG, offer.key().as_ref()],
+        bump,
+    )]
+    pub location_key_share: Box<Account<'info, LocationKeyShare>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelRequest<'info> {
+    #[account(
+        mut,
+        has_one = authority,
+        seeds = [REQUEST_TAG, authority.key().as_ref(), &request.id.to_le_bytes()],
+        bump,
+    )]
+    pub request: Box<Account<'info, Request>>,
+    #[account(seeds = [ADMIN_TAG], bump)]
+    pub admin: Box<Account<'info, Admin>>,
+    /// CHECK: receives forfeited deposits
+    #[account(mut, address = admin.treasury)]
+    pub treasury: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [CATEGORY_TAG, request.category.as_bytes()],
+        bump,
+    )]
+    pub category: Box<Account<'info, Category>>,
+    #[account(
+        mut,
+        seeds = [GEO_CELL_TAG, request.geohash.as_bytes()],
+        bump,
+    )]
+    pub geo_cell: Box<Account<'info, GeoCell>>,
+    #[account(
+        init_if_needed,
+        seeds = [USER_STATS_TAG, authority.key().as_ref()],
+        bump,
+        payer = authority,
+        space = 8 + size_of::<UserStats>()
+    )]
+    pub user_stats: Box<Account<'info, UserStats>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimAbandonedDeposit<'info> {
+    #[account(mut)]
+    pub request: Box<Account<'info, Request>>,
+    #[account(seeds = [ADMIN_TAG], bump)]
+    pub admin: Box<Account<'info, Admin>>,
+    /// CHECK: receives forfeited deposits
+    #[account(mut, address = admin.treasury)]
+    pub treasury: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [CATEGORY_TAG, request.category.as_bytes()],
+        bump,
+    )]
+    pub category: Box<Account<'info, Category>>,
+    #[account(
+        mut,
+        seeds = [GEO_CELL_TAG, request.geohash.as_bytes()],
+        bump,
+    )]
+    pub geo_cell: Box<Account<'info, GeoCell>>,
+    #[account(
+        mut,
+        seeds = [USER_STATS_TAG, request.authority.as_ref()],
+        bump,
+    )]
+    pub user_stats: Box<Account<'info, UserStats>>,
 }
 
 #[derive(Accounts)]
-#[instruction()]
-pub struct CreateUser<'info> {
+pub struct StakeBond<'info> {
     #[account(
-        init,
-        seeds = [USER_TAG,authority.key.as_ref()],
+        seeds = [USER_TAG, authority.key().as_ref()],
         bump,
-        payer = authority,
-        space = 8 + size_of::<User>() + 1024)]
+        has_one = authority
+    )]
     pub user: Box<Account<'info, User>>,
-    #[account(mut)]
-    pub authority: Signer<'info>,
     #[account(
-        mut,
-        seeds = [USER_COUNTER],
+        init_if_needed,
+        seeds = [SELLER_BOND_TAG, authority.key().as_ref()],
         bump,
+        payer = authority,
+        space = 8 + size_of::<SellerBond>()
     )]
-    pub user_counter: Box<Account<'info, Counter>>,
+    pub seller_bond: Box<Account<'info, SellerBond>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-#[instruction()]
-pub struct UpdateUser<'info> {
+pub struct StakeBondToken<'info> {
     #[account(
-        mut,
-        seeds = [USER_TAG,authority.key().as_ref()],
+        seeds = [USER_TAG, authority.key().as_ref()],
         bump,
         has_one = authority
     )]
     pub user: Box<Account<'info, User>>,
+    #[account(seeds = [ADMIN_TAG], bump)]
+    pub admin: Box<Account<'info, Admin>>,
+    #[account(
+        init_if_needed,
+        seeds = [SELLER_BOND_TAG, authority.key().as_ref()],
+        bump,
+        payer = authority,
+        space = 8 + size_of::<SellerBond>()
+    )]
+    pub seller_bond: Box<Account<'info, SellerBond>>,
+    #[account(
+        init_if_needed,
+        seeds = [BOND_VAULT_TAG, seller_bond.key().as_ref()],
+        bump,
+        payer = authority,
+        token::mint = mint,
+        token::authority = seller_bond,
+        token::token_program = token_program,
+    )]
+    pub vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, token::mint = mint)]
+    pub from_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(address = admin.bond_mint @ MarketplaceError::InvalidBondMint)]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
     #[account(mut)]
     pub authority: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-#[instruction()]
-pub struct CreateStore<'info> {
+pub struct RequestUnstake<'info> {
+    #[account(seeds = [ADMIN_TAG], bump)]
+    pub admin: Box<Account<'info, Admin>>,
     #[account(
         mut,
-        seeds = [USER_TAG,authority.key().as_ref()],
+        seeds = [SELLER_BOND_TAG, authority.key().as_ref()],
         bump,
         has_one = authority
     )]
-    pub user: Box<Account<'info, User>>,
-    #[account(init, payer = authority ,space = 8 + size_of::<Store>() + 1024,        
-    seeds = [STORE_TAG, authority.key().as_ref(),&store_counter.current.to_le_bytes()],
-    bump,)]
-    pub store: Box<Account<'info, Store>>,
-    #[account(mut)]
+    pub seller_bond: Box<Account<'info, SellerBond>>,
     pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawBond<'info> {
+    #[account(seeds = [ADMIN_TAG], bump)]
+    pub admin: Box<Account<'info, Admin>>,
     #[account(
         mut,
-        seeds = [STORE_COUNTER],
+        seeds = [SELLER_BOND_TAG, authority.key().as_ref()],
         bump,
+        has_one = authority
     )]
-    pub store_counter: Box<Account<'info, Counter>>,
-    pub system_program: Program<'info, System>,
+    pub seller_bond: Box<Account<'info, SellerBond>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
 }
 
 #[derive(Accounts)]
-#[instruction()]
-pub struct CreateRequest<'info> {
+pub struct WithdrawBondToken<'info> {
+    #[account(seeds = [ADMIN_TAG], bump)]
+    pub admin: Box<Account<'info, Admin>>,
     #[account(
         mut,
-        seeds = [USER_TAG,authority.key().as_ref()],
+        seeds = [SELLER_BOND_TAG, authority.key().as_ref()],
         bump,
         has_one = authority
     )]
-    pub user: Box<Account<'info, User>>,
-    #[account(init, payer = authority ,space = 8 + size_of::<Request>() + 1024,        
-    seeds = [REQUEST_TAG, authority.key().as_ref(),&request_counter.current.to_le_bytes()],
-    bump,)]
-    pub request: Box<Account<'info, Request>>,
+    pub seller_bond: Box<Account<'info, SellerBond>>,
     #[account(
         mut,
-        seeds = [REQUEST_COUNTER],
+        seeds = [BOND_VAULT_TAG, seller_bond.key().as_ref()],
         bump,
     )]
-    pub request_counter: Box<Account<'info, Counter>>,
-    #[account(mut)]
+    pub vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, token::mint = mint)]
+    pub to_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
     pub authority: Signer<'info>,
-    pub system_program: Program<'info, System>,
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
 #[derive(Accounts)]
-pub struct RemoveRequest<'info> {
+pub struct SlashBond<'info> {
+    #[account(
+        seeds = [ADMIN_TAG],
+        bump,
+        has_one = authority
+    )]
+    pub admin: Box<Account<'info, Admin>>,
     #[account(
         mut,
-        has_one = authority,
-        seeds = [REQUEST_TAG, authority.key().as_ref(), &request.id.to_le_bytes()],
+        seeds = [SELLER_BOND_TAG, seller_bond.authority.as_ref()],
         bump,
-        close = authority
     )]
-    pub request: Box<Account<'info, Request>>,
-    
+    pub seller_bond: Box<Account<'info, SellerBond>>,
+    /// CHECK: harmed buyer's wallet, chosen by the admin
     #[account(mut)]
+    pub buyer: UncheckedAccount<'info>,
     pub authority: Signer<'info>,
-    
-    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct ToggleLocation<'info> {
-    #[account(mut)]
-    pub authority: Signer<'info>,
+pub struct SlashBondToken<'info> {
     #[account(
-        mut,
-        seeds = [USER_TAG,authority.key().as_ref()],
+        seeds = [ADMIN_TAG],
         bump,
         has_one = authority
     )]
-    pub user: Box<Account<'info, User>>,
-    pub system_program: Program<'info, System>,
+    pub admin: Box<Account<'info, Admin>>,
+    #[account(
+        mut,
+        seeds = [SELLER_BOND_TAG, seller_bond.authority.as_ref()],
+        bump,
+    )]
+    pub seller_bond: Box<Account<'info, SellerBond>>,
+    #[account(
+        mut,
+        seeds = [BOND_VAULT_TAG, seller_bond.key().as_ref()],
+        bump,
+    )]
+    pub vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut, token::mint = mint)]
+    pub buyer_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    pub authority: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
 #[derive(Accounts)]
-pub struct GetLocationPreference<'info> {
-    #[account(mut)]
-    pub authority: Signer<'info>,
+pub struct MigrateAccount<'info> {
     #[account(
-        mut,
-        seeds = [USER_TAG,authority.key().as_ref()],
+        seeds = [ADMIN_TAG],
         bump,
         has_one = authority
     )]
-    pub user: Box<Account<'info, User>>,
+    pub admin: Box<Account<'info, Admin>>,
+    /// CHECK: owner, discriminator and legacy layout are checked in migrate_legacy_account
+    #[account(mut)]
+    pub account: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct MarkAsCompleteRequest<'info> {
+pub struct MigrateStore<'info> {
+    #[account(
+        seeds = [ADMIN_TAG],
+        bump,
+        has_one = authority
+    )]
+    pub admin: Box<Account<'info, Admin>>,
+    /// CHECK: owner, discriminator and legacy layout are checked in migrate_legacy_account
+    #[account(mut)]
+    pub account: UncheckedAccount<'info>,
     #[account(
         mut,
-        has_one = authority,
-        seeds = [REQUEST_TAG, authority.key().as_ref(), &request.id.to_le_bytes()],
+        seeds = [GEO_CELL_TAG, geo_cell.geohash.as_bytes()],
         bump,
     )]
-    pub request: Box<Account<'info, Request>>,
-    
+    pub geo_cell: Box<Account<'info, GeoCell>>,
+    #[account(
+        seeds = [USER_TAG, user.authority.as_ref()],
+        bump,
+    )]
+    pub user: Box<Account<'info, User>>,
+    #[account(
+        init_if_needed,
+        seeds = [USER_STATS_TAG, user.authority.as_ref()],
+        bump,
+        payer = authority,
+        space = 8 + size_of::<UserStats>()
+    )]
+    pub user_stats: Box<Account<'info, UserStats>>,
     #[account(mut)]
     pub authority: Signer<'info>,
-    
     pub system_program: Program<'info, System>,
 }
+
 #[derive(Accounts)]
-pub struct PayForRequest<'info> {
+pub struct MigrateRequest<'info> {
     #[account(
-        mut,
-        has_one = authority,
-        seeds = [REQUEST_TAG, authority.key().as_ref(), &request.id.to_le_bytes()],
+        seeds = [ADMIN_TAG],
         bump,
+        has_one = authority
     )]
-    pub request: Box<Account<'info, Request>>,
-
+    pub admin: Box<Account<'info, Admin>>,
+    /// CHECK: owner, discriminator and legacy layout are checked in migrate_legacy_account
+    #[account(mut)]
+    pub account: UncheckedAccount<'info>,
     #[account(
         mut,
+        seeds = [CATEGORY_TAG, category.name.as_bytes()],
+        bump,
     )]
-    pub offer: Box<Account<'info, Offer>>,
-
-    #[account(init, payer = authority ,space = 8 + size_of::<RequestPaymentTransaction>() + 1024,   
-    seeds = [REQUEST_PAYMENT_TAG, authority.key().as_ref(),&request_payment_counter.current.to_le_bytes()],
-    bump,)]
-    pub request_payment_info: Box<Account<'info, RequestPaymentTransaction>>,
-
+    pub category: Box<Account<'info, Category>>,
     #[account(
         mut,
-        seeds = [REQUEST_PAYMENT_COUNTER],
+        seeds = [GEO_CELL_TAG, geo_cell.geohash.as_bytes()],
         bump,
     )]
-    pub request_payment_counter: Box<Account<'info, Counter>>,
-    
+    pub geo_cell: Box<Account<'info, GeoCell>>,
+    #[account(
+        seeds = [USER_TAG, user.authority.as_ref()],
+        bump,
+    )]
+    pub user: Box<Account<'info, User>>,
+    #[account(
+        init_if_needed,
+        seeds = [USER_STATS_TAG, user.authority.as_ref()],
+        bump,
+        payer = authority,
+        space = 8 + size_of::<UserStats>()
+    )]
+    pub user_stats: Box<Account<'info, UserStats>>,
     #[account(mut)]
     pub authority: Signer<'info>,
-
-    /// CHECK: This is the account to which the payment is made
-    #[account(mut, address = PORTAL_CLIENT_PUBKEY)]
-    pub to: AccountInfo<'info>,
-    
     pub system_program: Program<'info, System>,
 }
 
-
 #[derive(Accounts)]
-pub struct PayForRequestToken<'info> {
+pub struct MigrateOffer<'info> {
     #[account(
-        mut,
-        has_one = authority,
-        seeds = [REQUEST_TAG, authority.key().as_ref(), &request.id.to_le_bytes()],
+        seeds = [ADMIN_TAG],
         bump,
+        has_one = authority
     )]
-    pub request: Box<Account<'info, Request>>,
-
-    #[account(init, payer = authority ,space = 8 + size_of::<RequestPaymentTransaction>() + 1024,   
-    seeds = [REQUEST_PAYMENT_TAG, authority.key().as_ref(),&request_payment_counter.current.to_le_bytes()],
-    bump,)]
-    pub request_payment_info: Box<Account<'info, RequestPaymentTransaction>>,
-
+    pub admin: Box<Account<'info, Admin>>,
+    /// CHECK: owner, discriminator and legacy layout are checked in migrate_legacy_account
+    #[account(mut)]
+    pub account: UncheckedAccount<'info>,
     #[account(
-        mut,
-        seeds = [REQUEST_PAYMENT_COUNTER],
+        seeds = [REQUEST_TAG, request.authority.as_ref(), &request.id.to_le_bytes()],
         bump,
     )]
-    pub request_payment_counter: Box<Account<'info, Counter>>,
-
+    pub request: Box<Account<'info, Request>>,
     #[account(
         mut,
+        seeds = [STORE_TAG, store.creator.as_ref(), &store.id.to_le_bytes()],
+        bump,
     )]
-    pub offer: Box<Account<'info, Offer>>,
-    
+    pub store: Box<Account<'info, Store>>,
     #[account(mut)]
     pub authority: Signer<'info>,
-    
     pub system_program: Program<'info, System>,
+}
 
+#[derive(Accounts)]
+#[instruction(geohash: String)]
+pub struct CreateGeoCell<'info> {
+    #[account(
+        init,
+        seeds = [GEO_CELL_TAG, geohash.as_bytes()],
+        bump,
+        payer = authority,
+        space = 8 + size_of::<GeoCell>() + GeoCell::MAX_DATA_LEN
+    )]
+    pub geo_cell: Box<Account<'info, GeoCell>>,
     #[account(mut)]
-    pub from_ata: InterfaceAccount<'info, TokenAccount>,
-
-    #[account(mut,address = PORTAL_PYUSD_TOKEN_ACCOUNT_PUBKEY)]
-    pub to_ata: InterfaceAccount<'info, TokenAccount>,
-
-    pub token_program: Interface<'info, TokenInterface>,
-
-    /// CHECK: this is the price feed
-    #[account(address = PYTH_USDC_FEED)]
-    pub price_update: Account<'info, PriceUpdateV2>,
-
-
-    pub mint: InterfaceAccount<'info, Mint>,
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-#[instruction()]
-pub struct CreateOffer<'info> {
+#[instruction(name: String)]
+pub struct CreateCategory<'info> {
     #[account(
-        mut,
-        seeds = [USER_TAG,authority.key().as_ref()],
+        seeds = [ADMIN_TAG],
         bump,
         has_one = authority
     )]
-    pub user: Box<Account<'info, User>>,
-    #[account(mut)]
-    pub request: Box<Account<'info, Request>>,
-    #[account(init, payer = authority ,space = 8 + size_of::<Offer>() + 1024,     
-    seeds = [OFFER_TAG, authority.key().as_ref(),&offer_counter.current.to_le_bytes()],
-    bump,)]
-    pub offer: Box<Account<'info, Offer>>,
-    #[account(mut)]
-    pub authority: Signer<'info>,
+    pub admin: Box<Account<'info, Admin>>,
     #[account(
-        mut,
-        seeds = [OFFER_COUNTER],
+        init,
+        seeds = [CATEGORY_TAG, name.as_bytes()],
         bump,
+        payer = authority,
+        space = 8 + size_of::<Category>() + Category::MAX_DATA_LEN
     )]
-    pub offer_counter: Box<Account<'info, Counter>>,
+    pub category: Box<Account<'info, Category>>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-#[instruction()]
-pub struct AcceptOffer<'info> {
+pub struct UpdateCategory<'info> {
     #[account(
-        mut,
-        seeds = [USER_TAG,authority.key().as_ref()],
+        seeds = [ADMIN_TAG],
         bump,
         has_one = authority
     )]
-    pub user: Box<Account<'info, User>>,
+    pub admin: Box<Account<'info, Admin>>,
+    #[account(
+        mut,
+        seeds = [CATEGORY_TAG, category.name.as_bytes()],
+        bump,
+    )]
+    pub category: Box<Account<'info, Category>>,
     #[account(mut)]
     pub authority: Signer<'info>,
-    #[account(mut)]
-    pub offer: Box<Account<'info, Offer>>,
-    #[account(mut)]
-    pub request: Box<Account<'info, Request>>,
-    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
@@ -861,6 +5528,21 @@ pub struct InitializePaymentIncrement<'info> {
     pub system_program: Program<'info, System>,
 }
 #[derive(Accounts)]
+pub struct InitializeListingCounter<'info> {
+    #[account(
+        init,
+        seeds = [LISTING_COUNTER],
+        bump,
+        payer = authority,
+        space = 8 + size_of::<Counter>()
+    )]
+    pub listing_counter: Box<Account<'info, Counter>>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+#[derive(Accounts)]
 pub struct InitializeCounters<'info> {
     #[account(
         init,
@@ -1,16 +1,51 @@
 use anchor_lang::prelude::*;
+
+use crate::constants::{
+    MAX_CATEGORY_NAME_LEN, MAX_DESCRIPTION_LEN, MAX_ENCRYPTED_LOCATION_LEN,
+    MAX_GEOHASH_PRECISION, MAX_IMAGES, MAX_IMAGE_URL_LEN, MAX_NAME_LEN, MAX_OFFERS_PER_REQUEST,
+    MAX_OFFERS_PER_STORE, MAX_USERNAME_LEN, ROLE_BUYER, ROLE_SELLER,
+};
+
+// Bytes needed by a full `images` vector on top of its `size_of` header.
+pub const MAX_IMAGES_LEN: usize = MAX_IMAGES * (4 + MAX_IMAGE_URL_LEN);
 #[account]
 #[derive(Debug)]
 pub struct User {
     pub id: u64,
     pub username: String,
-    pub phone: String,
+    // sha256(phone || salt), computed off-chain with a random 32 byte salt that
+    // is secret to the user and never reused across users. Phone numbers are
+    // few enough that an unsalted or shared-salt hash is brute-forced offline;
+    // the salt is only revealed inside the encrypted contact of `share_contact`.
+    pub phone_hash: [u8; 32],
     pub location: Location,
     pub created_at: i64,
     pub updated_at: i64,
     pub account_type: AccountType,
     pub authority: Pubkey,
     pub location_enabled: bool,
+    pub roles: u8,
+    pub version: u8,
+}
+
+impl User {
+    pub const MAX_DATA_LEN: usize = MAX_USERNAME_LEN;
+
+    // Accounts from before roles existed only have `account_type`.
+    pub fn roles(&self) -> u8 {
+        if self.roles != 0 {
+            return self.roles;
+        }
+
+        match self.account_type {
+            AccountType::Buyer => ROLE_BUYER,
+            AccountType::Seller => ROLE_SELLER,
+        }
+    }
+
+    pub fn has_role(&self, role: u8) -> bool {
+        self.roles() & role != 0
+    }
 }
 
 #[account]
@@ -19,8 +54,57 @@ pub struct Store {
     pub id: u64,
     pub name: String,
     pub description: String,
-    pub phone: String,
     pub location: Location,
+    pub geohash: String,
+    pub service_radius: u64,
+    pub creator: Pubkey,
+    pub offer_ids: Vec<u64>,
+    pub opening_hours: [OpeningHours; 7],
+    pub utc_offset_minutes: i16,
+    pub is_away: bool,
+    pub verification_level: u8,
+    pub verified_until: u64,
+    pub version: u8,
+}
+
+// `offer_ids` holds at most MAX_OFFERS_PER_STORE ids. Migrated stores may
+// hold more until pruned, and are resized to fit.
+impl Store {
+    pub const MAX_DATA_LEN: usize = MAX_NAME_LEN
+        + MAX_DESCRIPTION_LEN
+        + MAX_GEOHASH_PRECISION as usize
+        + MAX_OFFERS_PER_STORE * 8;
+}
+
+// Permissions are a bitmask of the DELEGATE_* constants. A delegate only
+// counts while `granted_by` is still the store authority.
+#[account]
+pub struct StoreDelegate {
+    pub store_id: u64,
+    pub delegate: Pubkey,
+    pub granted_by: Pubkey,
+    pub permissions: u8,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+#[account]
+pub struct Listing {
+    pub id: u64,
+    pub store_id: u64,
+    pub title: String,
+    pub description: String,
+    pub images: Vec<String>,
+    pub price: u64,
+    pub stock: u64,
+    pub category: String,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl Listing {
+    pub const MAX_DATA_LEN: usize =
+        MAX_NAME_LEN + MAX_DESCRIPTION_LEN + MAX_IMAGES_LEN + MAX_CATEGORY_NAME_LEN;
 }
 
 #[account]
@@ -41,6 +125,43 @@ pub struct Request {
     pub lifecycle: RequestLifecycle,
     pub paid: bool,
     pub accepted_offer_id: u64,
+    pub min_price: Option<u64>,
+    pub max_price: Option<u64>,
+    pub budget_currency: CoinPayment,
+    pub mode: RequestMode,
+    pub bidding_deadline: u64,
+    pub sealed_bids: bool,
+    pub reveal_deadline: u64,
+    pub quantity: u64,
+    pub accepted_quantity: u64,
+    pub paid_quantity: u64,
+    pub fulfilled_quantity: u64,
+    pub pooled: bool,
+    pub pool_deadline: u64,
+    pub pool_claimed_quantity: u64,
+    pub pool_escrowed: u64,
+    pub pool_participants: u64,
+    pub pool_funded_participants: u64,
+    pub category: String,
+    pub geohash: String,
+    pub max_seller_distance: u64,
+    pub precise_location_commitment: [u8; 32],
+    pub encrypted_location: Vec<u8>,
+    pub deposit: u64,
+    pub min_verification_level: u8,
+    pub version: u8,
+}
+
+// `seller_ids` and `offer_ids` each hold at most one id per offer.
+// `min_price`, `max_price` and `sellers_price_quote` are per unit of `quantity`.
+impl Request {
+    pub const MAX_DATA_LEN: usize = MAX_NAME_LEN
+        + MAX_DESCRIPTION_LEN
+        + MAX_IMAGES_LEN
+        + 2 * MAX_OFFERS_PER_REQUEST * 8
+        + MAX_CATEGORY_NAME_LEN
+        + MAX_GEOHASH_PRECISION as usize
+        + MAX_ENCRYPTED_LOCATION_LEN;
 }
 
 #[account]
@@ -55,6 +176,20 @@ pub struct Offer {
     pub is_accepted: bool,
     pub created_at: u64,
     pub updated_at: u64,
+    pub price_commitment: [u8; 32],
+    pub is_revealed: bool,
+    pub quantity: u64,
+    pub is_paid: bool,
+    pub is_completed: bool,
+    pub store_id: u64,
+    pub is_delivered: bool,
+    pub is_withdrawn: bool,
+    pub version: u8,
+}
+
+// `price` is per unit, so an offer costs `price * quantity`.
+impl Offer {
+    pub const MAX_DATA_LEN: usize = MAX_NAME_LEN + MAX_IMAGES_LEN;
 }
 
 #[account]
@@ -70,48 +205,287 @@ pub struct RequestPaymentTransaction {
     pub token: CoinPayment,
     pub amount: u64,
     pub id: u64,
+    pub offer_id: u64,
+    pub quantity: u64,
+}
+
+// `mint` is None for bonds staked in SOL, which are held as lamports on the
+// bond account itself. SPL bonds sit in the BOND_VAULT_TAG token account.
+#[account]
+pub struct SellerBond {
+    pub authority: Pubkey,
+    pub seller_id: u64,
+    pub mint: Option<Pubkey>,
+    pub amount: u64,
+    pub unstake_amount: u64,
+    pub unstake_requested_at: u64,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+// Reserves a username, seeded by its lowercase form.
+#[account]
+pub struct UsernameRecord {
+    pub authority: Pubkey,
+    pub user_id: u64,
+    pub username: String,
+    pub created_at: u64,
+}
+
+impl UsernameRecord {
+    pub const MAX_DATA_LEN: usize = MAX_USERNAME_LEN;
+}
+
+#[account]
+pub struct UserStats {
+    pub authority: Pubkey,
+    pub user_id: u64,
+    pub rating_count: u64,
+    pub rating_sum: u64,
+    pub completed_deals: u64,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub requests_created: u64,
+    pub requests_cancelled: u64,
+    pub offers_created: u64,
+    pub offers_accepted: u64,
+    pub acceptances_given: u64,
+    pub payments_made: u64,
+    pub payments_on_time: u64,
+    pub sol_volume: u64,
+    pub pyusd_volume: u64,
+    pub last_active_at: u64,
+    pub open_requests: u64,
+    pub open_stores: u64,
+}
+
+#[account]
+pub struct Review {
+    pub request_id: u64,
+    pub offer_id: u64,
+    pub reviewer: Pubkey,
+    pub reviewee: Pubkey,
+    pub rating: u8,
+    pub comment_hash: [u8; 32],
+    pub created_at: u64,
+}
+
+#[account]
+pub struct ContactShare {
+    pub request_id: u64,
+    pub sender: Pubkey,
+    pub recipient: Pubkey,
+    pub encrypted_contact: Vec<u8>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+// The key of the request's encrypted location, encrypted to the seller of one
+// accepted offer.
+#[account]
+pub struct LocationKeyShare {
+    pub request_id: u64,
+    pub offer_id: u64,
+    pub recipient: Pubkey,
+    pub encrypted_location_key: Vec<u8>,
+    pub updated_at: u64,
+}
+
+#[account]
+pub struct PoolParticipant {
+    pub authority: Pubkey,
+    pub request_id: u64,
+    pub user_id: u64,
+    pub quantity: u64,
+    pub escrowed: u64,
+    pub is_funded: bool,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+#[account]
+pub struct Admin {
+    pub authority: Pubkey,
+    pub geohash_precision: u8,
+    pub min_bond_lamports: u64,
+    pub bond_mint: Pubkey,
+    pub min_bond_tokens: u64,
+    pub bond_unstake_cooldown: u64,
+    pub request_deposit: u64,
+    pub treasury: Pubkey,
+    pub abandon_timeout: u64,
+    pub store_verifier: Pubkey,
+}
+
+#[account]
+pub struct Category {
+    pub name: String,
+    pub active: bool,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub request_ids: Vec<u64>,
+}
+
+// `request_ids` is not bounded here, the category is resized as requests are added.
+impl Category {
+    pub const MAX_DATA_LEN: usize = MAX_CATEGORY_NAME_LEN;
+}
+
+#[account]
+pub struct GeoCell {
+    pub geohash: String,
+    pub request_ids: Vec<u64>,
+    pub store_ids: Vec<u64>,
+}
+
+// `request_ids` and `store_ids` are not bounded here, the cell is resized as
+// requests and stores are added.
+impl GeoCell {
+    pub const MAX_DATA_LEN: usize = MAX_GEOHASH_PRECISION as usize;
 }
 
 #[account]
 pub struct Counter {
     pub current: u64,
 }
+// Minutes since local midnight, Monday first. A day with `open == close` is
+// closed, so an all-zero schedule means the store has not published hours.
+// A `close` before `open` runs past midnight into the next day, e.g. 22:00 to
+// 02:00 is `open: 1320, close: 120`.
+#[derive(Debug, Default, AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct OpeningHours {
+    pub open: u16,
+    pub close: u16,
+}
+
+// Degrees scaled by 10^LOCATION_DECIMALS, i.e. 1e-7 degree (~1cm) resolution.
 #[derive(Debug, AnchorSerialize, AnchorDeserialize, Clone)]
 pub struct Location {
+    pub latitude: i32,
+    pub longitude: i32,
+}
+
+pub trait Versioned {
+    fn version(&self) -> u8;
+}
+
+impl Versioned for User {
+    fn version(&self) -> u8 {
+        self.version
+    }
+}
+
+impl Versioned for Store {
+    fn version(&self) -> u8 {
+        self.version
+    }
+}
+
+impl Versioned for Request {
+    fn version(&self) -> u8 {
+        self.version
+    }
+}
+
+impl Versioned for Offer {
+    fn version(&self) -> u8 {
+        self.version
+    }
+}
+
+// The account layouts deployed before versioning, only used by the migrate_*
+// instructions. Fields added since then are defaulted during migration.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct LegacyLocation {
     pub latitude: i128,
     pub longitude: i128,
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LegacyUser {
+    pub id: u64,
+    pub username: String,
+    pub phone: String,
+    pub location: LegacyLocation,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub account_type: AccountType,
+    pub authority: Pubkey,
+    pub location_enabled: bool,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LegacyStore {
+    pub authority: Pubkey,
+    pub id: u64,
+    pub name: String,
+    pub description: String,
+    pub phone: String,
+    pub location: LegacyLocation,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LegacyRequest {
+    pub authority: Pubkey,
+    pub id: u64,
+    pub name: String,
+    pub buyer_id: u64,
+    pub description: String,
+    pub images: Vec<String>,
+    pub sellers_price_quote: u64,
+    pub seller_ids: Vec<u64>,
+    pub offer_ids: Vec<u64>,
+    pub locked_seller_id: u64,
+    pub location: LegacyLocation,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub lifecycle: RequestLifecycle,
+    pub paid: bool,
+    pub accepted_offer_id: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LegacyOffer {
+    pub authority: Pubkey,
+    pub id: u64,
+    pub request_id: u64,
+    pub price: u64,
+    pub images: Vec<String>,
+    pub store_name: String,
+    pub seller_id: u64,
+    pub is_accepted: bool,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
 #[derive(Debug, AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
 pub enum AccountType {
     Buyer,
     Seller,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Default)]
 pub enum RequestLifecycle {
+    #[default]
     Pending = 0,
     AcceptedBySeller = 1,
     AcceptedByBuyer = 2,
     RequestLocked = 3,
     Paid = 4,
     Completed = 5,
+    Cancelled = 6,
 }
 
-impl Default for RequestLifecycle {
-    fn default() -> Self {
-        RequestLifecycle::Pending
-    }
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Default)]
+pub enum RequestMode {
+    #[default]
+    Standard,
+    ReverseAuction,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Default)]
 pub enum CoinPayment {
+    #[default]
     Solana,
     Pyusdt,
 }
-
-impl Default for CoinPayment {
-    fn default() -> Self {
-        CoinPayment::Solana
-    }
-}
@@ -2,16 +2,44 @@ import * as anchor from "@coral-xyz/anchor";
 import { Program } from "@coral-xyz/anchor";
 import { Marketplace } from "../target/types/marketplace";
 import {
+  ADMIN_TAG,
+  BPF_UPGRADEABLE_LOADER_ID,
+  CATEGORY_TAG,
+  coarsenCoordinate,
+  CONTACT_TAG,
+  LOCATION_KEY_TAG,
+  contactHash,
+  newContactSalt,
+  DELEGATE_CONFIRM_DELIVERY,
+  DELEGATE_CREATE_OFFERS,
+  encodeGeohash,
+  GEO_CELL_TAG,
+  LISTING_COUNTER,
+  LISTING_TAG,
   LOCATION_DECIMALS,
+  MAX_IMAGES,
   ntobs58,
+  offerPriceCommitment,
+  preciseLocationCommitment,
   OFFER_COUNTER,
   OFFER_TAG,
+  POOL_PARTICIPANT_TAG,
+  PORTAL_CLIENT,
   REQUEST_COUNTER,
+  REQUEST_PAYMENT_COUNTER,
+  REQUEST_PAYMENT_TAG,
+  ROLE_BUYER,
+  ROLE_SELLER,
   REQUEST_TAG,
+  REVIEW_TAG,
+  SELLER_BOND_TAG,
   STORE_COUNTER,
+  STORE_DELEGATE_TAG,
   STORE_TAG,
   USER_COUNTER,
+  USER_STATS_TAG,
   USER_TAG,
+  USERNAME_TAG,
 } from "../utils";
 import { PublicKey, SystemProgram } from "@solana/web3.js";
 import { utf8 } from "@coral-xyz/anchor/dist/cjs/utils/bytes";
@@ -30,42 +58,417 @@ describe("match-solana-contract", function () {
   let OFFER_COUNTER_PUBKEY: PublicKey;
   let profilePda: PublicKey;
   let buyerPda: PublicKey;
+  let categoryPda: PublicKey;
+  let adminPda: PublicKey;
+  let geoCellPda: PublicKey;
+  let requestPaymentCounterPda: PublicKey;
+
+  const categoryName = "electronics";
+
+  const buyerContactSalt = newContactSalt();
+  const sellerContactSalt = newContactSalt();
 
   const buyerPayload = {
     username: "test",
     phone: "1234567890",
-    latitude: new BN(Math.trunc(3.4 * 10 ** LOCATION_DECIMALS).toString()),
-    longitude: new BN(Math.trunc(6.2 * 10 ** LOCATION_DECIMALS).toString()),
-    account_type: { buyer: {} },
+    latitude: Math.trunc(3.4 * 10 ** LOCATION_DECIMALS),
+    longitude: Math.trunc(6.2 * 10 ** LOCATION_DECIMALS),
+    roles: ROLE_BUYER,
   };
 
+  const buyerUsername = "buyer";
+
   const sellerPayload = {
     username: "test2",
     phone: "0987654321",
-    latitude: new BN(Math.trunc(4.5 * 10 ** LOCATION_DECIMALS).toString()),
-    longitude: new BN(Math.trunc(7.8 * 10 ** LOCATION_DECIMALS).toString()),
-    account_type: { seller: {} },
+    latitude: Math.trunc(4.5 * 10 ** LOCATION_DECIMALS),
+    longitude: Math.trunc(7.8 * 10 ** LOCATION_DECIMALS),
+    roles: ROLE_SELLER,
   };
 
   const requestPayload = {
     name: "test store",
     description: "test description",
     phone: "1234567890",
-    long: Math.trunc(3.38 * 10 ** LOCATION_DECIMALS),
-    lat: Math.trunc(4.383 * 10 ** LOCATION_DECIMALS),
+    long: coarsenCoordinate(Math.trunc(3.38 * 10 ** LOCATION_DECIMALS)),
+    lat: coarsenCoordinate(Math.trunc(4.383 * 10 ** LOCATION_DECIMALS)),
     images: ["image1", "image2"],
   };
 
   const storePayload = {
     name: "test store",
     description: "test description",
-    phone: "1234567890",
     long: Math.trunc(3.38 * 10 ** LOCATION_DECIMALS),
     lat: Math.trunc(4.383 * 10 ** LOCATION_DECIMALS),
   };
 
+  const encryptedLocation = Buffer.from("encrypted precise location");
+
+  const locationCommitment = preciseLocationCommitment(encryptedLocation);
+
   const buyer = anchor.web3.Keypair.generate();
 
+  const findUsernameRecordPda = (username: string) =>
+    PublicKey.findProgramAddressSync(
+      [utf8.encode(USERNAME_TAG), utf8.encode(username.toLowerCase())],
+      program.programId
+    )[0];
+
+  const findUserStatsPda = (authority: PublicKey) =>
+    PublicKey.findProgramAddressSync(
+      [utf8.encode(USER_STATS_TAG), authority.toBuffer()],
+      program.programId
+    )[0];
+
+  type KeypairUser = { keypair: anchor.web3.Keypair; userPda: PublicKey };
+
+  const createSellerStore = async (
+    lat = storePayload.lat,
+    long = storePayload.long,
+    serviceRadius = 0,
+    seller: KeypairUser | null = null
+  ) => {
+    const authority = seller ? seller.keypair.publicKey : provider.publicKey;
+    const geohash = encodeGeohash(
+      lat / 10 ** LOCATION_DECIMALS,
+      long / 10 ** LOCATION_DECIMALS
+    );
+
+    const [storeGeoCellPda] = PublicKey.findProgramAddressSync(
+      [utf8.encode(GEO_CELL_TAG), utf8.encode(geohash)],
+      program.programId
+    );
+
+    if (!(await provider.connection.getAccountInfo(storeGeoCellPda))) {
+      await program.methods
+        .createGeoCell(geohash)
+        .accounts({
+          geoCell: storeGeoCellPda,
+          authority: provider.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+    }
+
+    const storeCounter = await program.account.counter.fetch(
+      STORE_COUNTER_PUBKEY
+    );
+
+    const [storePda] = PublicKey.findProgramAddressSync(
+      [
+        utf8.encode(STORE_TAG),
+        authority.toBuffer(),
+        Buffer.from(storeCounter.current.toArray("le", 8)),
+      ],
+      program.programId
+    );
+
+    await program.methods
+      .createStore(
+        storePayload.name,
+        storePayload.description,
+        lat,
+        long,
+        new BN(serviceRadius)
+      )
+      .accounts({
+        user: seller ? seller.userPda : profilePda,
+        systemProgram: SystemProgram.programId,
+        storeCounter: STORE_COUNTER_PUBKEY,
+        authority,
+        store: storePda,
+        admin: adminPda,
+        geoCell: storeGeoCellPda,
+        userStats: findUserStatsPda(authority),
+      })
+      .signers(seller ? [seller.keypair] : [])
+      .rpc();
+
+    return storePda;
+  };
+
+  const chainNow = async () =>
+    (await provider.connection.getBlockTime(
+      await provider.connection.getSlot()
+    )) as number;
+
+  const sleep = (seconds: number) =>
+    new Promise((resolve) => setTimeout(resolve, seconds * 1000));
+
+  const createBuyerRequest = async ({
+    minPrice = null,
+    maxPrice = null,
+    mode = { standard: {} },
+    biddingDeadline = 0,
+    sealedBids = false,
+    revealDeadline = 0,
+    quantity = 1,
+    pooled = false,
+    poolDeadline = 0,
+  }: {
+    minPrice?: number | null;
+    maxPrice?: number | null;
+    mode?: object;
+    biddingDeadline?: number;
+    sealedBids?: boolean;
+    revealDeadline?: number;
+    quantity?: number;
+    pooled?: boolean;
+    poolDeadline?: number;
+  } = {}) => {
+    const requestCounter = await program.account.counter.fetch(
+      REQUEST_COUNTER_PUBKEY
+    );
+
+    const [requestPda] = PublicKey.findProgramAddressSync(
+      [
+        utf8.encode(REQUEST_TAG),
+        buyer.publicKey.toBuffer(),
+        Buffer.from(requestCounter.current.toArray("le", 8)),
+      ],
+      program.programId
+    );
+
+    await program.methods
+      .createRequest(
+        requestPayload.name,
+        requestPayload.description,
+        requestPayload.images,
+        requestPayload.lat,
+        requestPayload.long,
+        minPrice === null ? null : new BN(minPrice),
+        maxPrice === null ? null : new BN(maxPrice),
+        { solana: {} },
+        mode as any,
+        new BN(biddingDeadline),
+        sealedBids,
+        new BN(revealDeadline),
+        new BN(quantity),
+        pooled,
+        new BN(poolDeadline),
+        new BN(0),
+        locationCommitment,
+        0
+      )
+      .accounts({
+        user: buyerPda,
+        systemProgram: SystemProgram.programId,
+        requestCounter: REQUEST_COUNTER_PUBKEY,
+        authority: buyer.publicKey,
+        request: requestPda,
+        category: categoryPda,
+        admin: adminPda,
+        geoCell: geoCellPda,
+        userStats: findUserStatsPda(buyer.publicKey),
+      })
+      .signers([buyer])
+      .rpc();
+
+    return requestPda;
+  };
+
+  const nextOfferPda = async (authority = provider.publicKey) => {
+    const offerCounter = await program.account.counter.fetch(
+      OFFER_COUNTER_PUBKEY
+    );
+
+    return PublicKey.findProgramAddressSync(
+      [
+        utf8.encode(OFFER_TAG),
+        authority.toBuffer(),
+        Buffer.from(offerCounter.current.toArray("le", 8)),
+      ],
+      program.programId
+    )[0];
+  };
+
+  const createSellerOffer = async (
+    requestPda: PublicKey,
+    storePda: PublicKey,
+    price: number,
+    quantity = 1,
+    seller: KeypairUser | null = null
+  ) => {
+    const authority = seller ? seller.keypair.publicKey : provider.publicKey;
+    const offerPda = await nextOfferPda(authority);
+
+    await program.methods
+      .createOffer(new BN(price), ["image1"], new BN(quantity))
+      .accounts({
+        user: seller ? seller.userPda : profilePda,
+        systemProgram: SystemProgram.programId,
+        offerCounter: OFFER_COUNTER_PUBKEY,
+        authority,
+        request: requestPda,
+        offer: offerPda,
+        store: storePda,
+        userStats: findUserStatsPda(authority),
+        admin: adminPda,
+        sellerBond: null,
+        storeDelegate: null,
+        priceUpdate: null,
+      })
+      .signers(seller ? [seller.keypair] : [])
+      .rpc();
+
+    return offerPda;
+  };
+
+  const commitSealedOffer = async (
+    requestPda: PublicKey,
+    storePda: PublicKey,
+    price: number,
+    salt: Buffer
+  ) => {
+    const offerPda = await nextOfferPda();
+
+    await program.methods
+      .commitOffer(offerPriceCommitment(price, salt), ["image1"], new BN(1))
+      .accounts({
+        user: profilePda,
+        systemProgram: SystemProgram.programId,
+        offerCounter: OFFER_COUNTER_PUBKEY,
+        authority: provider.publicKey,
+        request: requestPda,
+        offer: offerPda,
+        store: storePda,
+        userStats: findUserStatsPda(provider.publicKey),
+        admin: adminPda,
+        sellerBond: null,
+        storeDelegate: null,
+        priceUpdate: null,
+      })
+      .rpc();
+
+    return offerPda;
+  };
+
+  const revealSealedOffer = (
+    requestPda: PublicKey,
+    storePda: PublicKey,
+    offerPda: PublicKey,
+    price: number,
+    salt: Buffer
+  ) =>
+    program.methods
+      .revealOffer(new BN(price), Array.from(salt))
+      .accounts({
+        offer: offerPda,
+        request: requestPda,
+        store: storePda,
+        storeDelegate: null,
+        authority: provider.publicKey,
+        priceUpdate: null,
+      })
+      .rpc();
+
+  const createKeypairUser = async (
+    username: string,
+    roles = ROLE_BUYER,
+    keypair = anchor.web3.Keypair.generate()
+  ) => {
+    const [userPda] = PublicKey.findProgramAddressSync(
+      [utf8.encode(USER_TAG), keypair.publicKey.toBuffer()],
+      program.programId
+    );
+
+    await provider.connection.confirmTransaction(
+      await provider.connection.requestAirdrop(
+        keypair.publicKey,
+        anchor.web3.LAMPORTS_PER_SOL
+      )
+    );
+
+    await program.methods
+      .createUser(
+        username,
+        contactHash(buyerPayload.phone, newContactSalt()),
+        buyerPayload.latitude,
+        buyerPayload.longitude,
+        roles
+      )
+      .accounts({
+        user: userPda,
+        userStats: findUserStatsPda(keypair.publicKey),
+        usernameRecord: findUsernameRecordPda(username),
+        systemProgram: SystemProgram.programId,
+        userCounter: USER_COUNTER_PUBKEY,
+        authority: keypair.publicKey,
+      })
+      .signers([keypair])
+      .rpc();
+
+    return { keypair, userPda };
+  };
+
+  const findPoolParticipantPda = (
+    requestPda: PublicKey,
+    authority: PublicKey
+  ) =>
+    PublicKey.findProgramAddressSync(
+      [
+        utf8.encode(POOL_PARTICIPANT_TAG),
+        requestPda.toBuffer(),
+        authority.toBuffer(),
+      ],
+      program.programId
+    )[0];
+
+  const joinPool = (
+    requestPda: PublicKey,
+    participant: anchor.web3.Keypair,
+    userPda: PublicKey,
+    quantity: number,
+    deposit: number
+  ) =>
+    program.methods
+      .joinPool(new BN(quantity), new BN(deposit))
+      .accounts({
+        user: userPda,
+        request: requestPda,
+        participant: findPoolParticipantPda(requestPda, participant.publicKey),
+        authority: participant.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([participant])
+      .rpc();
+
+  const requestOfferAccounts = async (requestPda: PublicKey) => {
+    const request = await program.account.request.fetch(requestPda);
+    const offerAccounts = await program.account.offer.all([
+      {
+        memcmp: {
+          offset: 8 + 32 + 8,
+          bytes: ntobs58(request.id),
+        },
+      },
+    ]);
+
+    // withdrawn offers no longer hold a slot on the request
+    return offerAccounts
+      .filter((offerAccount) => !offerAccount.account.isWithdrawn)
+      .map((offerAccount) => ({
+        pubkey: offerAccount.publicKey,
+        isWritable: true,
+        isSigner: false,
+      }));
+  };
+
+  const auctionSettlementAccounts = async (requestPda: PublicKey) => {
+    const offerAccounts = await requestOfferAccounts(requestPda);
+    const sellerStatsAccounts = await Promise.all(
+      offerAccounts.map(async ({ pubkey }) => ({
+        pubkey: findUserStatsPda(
+          (await program.account.offer.fetch(pubkey)).authority
+        ),
+        isWritable: true,
+        isSigner: false,
+      }))
+    );
+
+    return [...offerAccounts, ...sellerStatsAccounts];
+  };
+
   beforeEach(async function () {
     if (profilePda) return;
     await provider.connection.requestAirdrop(
@@ -101,143 +504,2579 @@ describe("match-solana-contract", function () {
       })
       .rpc();
 
+    const [requestPaymentCounterPda_] = PublicKey.findProgramAddressSync(
+      [utf8.encode(REQUEST_PAYMENT_COUNTER)],
+      program.programId
+    );
+
+    requestPaymentCounterPda = requestPaymentCounterPda_;
+
+    await program.methods
+      .initializeCountersPay()
+      .accounts({
+        requestPaymentCounter: requestPaymentCounterPda,
+        authority: provider.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
     USER_COUNTER_PUBKEY = userCounterPDA;
     STORE_COUNTER_PUBKEY = storeCounterPDA;
     REQUEST_COUNTER_PUBKEY = requestCounterPDA;
     OFFER_COUNTER_PUBKEY = offerCounterPDA;
 
-    const [profilePda_] = PublicKey.findProgramAddressSync(
-      [utf8.encode(USER_TAG), provider.publicKey.toBuffer()],
-      program.programId
+    const [adminPda_] = PublicKey.findProgramAddressSync(
+      [utf8.encode(ADMIN_TAG)],
+      program.programId
+    );
+
+    adminPda = adminPda_;
+
+    const [categoryPda_] = PublicKey.findProgramAddressSync(
+      [utf8.encode(CATEGORY_TAG), utf8.encode(categoryName)],
+      program.programId
+    );
+
+    categoryPda = categoryPda_;
+
+    await program.methods
+      .initializeAdmin()
+      .accounts({
+        admin: adminPda,
+        program: program.programId,
+        programData: PublicKey.findProgramAddressSync(
+          [program.programId.toBuffer()],
+          BPF_UPGRADEABLE_LOADER_ID
+        )[0],
+        authority: provider.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    await program.methods
+      .createCategory(categoryName)
+      .accounts({
+        admin: adminPda,
+        category: categoryPda,
+        authority: provider.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    const geohash = encodeGeohash(
+      storePayload.lat / 10 ** LOCATION_DECIMALS,
+      storePayload.long / 10 ** LOCATION_DECIMALS
+    );
+
+    const [geoCellPda_] = PublicKey.findProgramAddressSync(
+      [utf8.encode(GEO_CELL_TAG), utf8.encode(geohash)],
+      program.programId
+    );
+
+    geoCellPda = geoCellPda_;
+
+    await program.methods
+      .createGeoCell(geohash)
+      .accounts({
+        geoCell: geoCellPda,
+        authority: provider.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    const [profilePda_] = PublicKey.findProgramAddressSync(
+      [utf8.encode(USER_TAG), provider.publicKey.toBuffer()],
+      program.programId
+    );
+
+    const [buyerPda_] = PublicKey.findProgramAddressSync(
+      [utf8.encode(USER_TAG), buyer.publicKey.toBuffer()],
+      program.programId
+    );
+
+    profilePda = profilePda_;
+    buyerPda = buyerPda_;
+
+    await program.methods
+      .createUser(
+        buyerPayload.username,
+        contactHash(buyerPayload.phone, sellerContactSalt),
+        buyerPayload.latitude,
+        buyerPayload.longitude,
+        buyerPayload.roles
+      )
+      .accounts({
+        user: profilePda,
+        userStats: findUserStatsPda(provider.publicKey),
+        usernameRecord: findUsernameRecordPda(buyerPayload.username),
+        systemProgram: SystemProgram.programId,
+        userCounter: USER_COUNTER_PUBKEY,
+        authority: provider.publicKey,
+      })
+
+      .rpc();
+
+    await program.methods
+      .createUser(
+        buyerUsername,
+        contactHash(buyerPayload.phone, buyerContactSalt),
+        buyerPayload.latitude,
+        buyerPayload.longitude,
+        buyerPayload.roles
+      )
+      .accounts({
+        user: buyerPda,
+        userStats: findUserStatsPda(buyer.publicKey),
+        usernameRecord: findUsernameRecordPda(buyerUsername),
+        systemProgram: SystemProgram.programId,
+        userCounter: USER_COUNTER_PUBKEY,
+        authority: buyer.publicKey,
+      })
+      .signers([buyer])
+      .rpc();
+  });
+
+  it("Can create a new user and return true values", async function () {
+    const user = await program.account.user.fetch(profilePda);
+    expect(user.username).to.be.equal(buyerPayload.username);
+    expect(user.phoneHash).to.be.deep.equal(
+      contactHash(buyerPayload.phone, sellerContactSalt)
+    );
+    // the same phone under another user's salt hashes differently
+    const buyerUser = await program.account.user.fetch(buyerPda);
+    expect(buyerUser.phoneHash).to.not.be.deep.equal(user.phoneHash);
+    expect(Number(user.location.latitude)).to.be.equal(
+      Number(buyerPayload.latitude)
+    );
+    expect(Number(user.location.longitude)).to.be.equal(
+      Number(buyerPayload.longitude)
+    );
+    expect(user.roles).to.be.equal(buyerPayload.roles);
+
+    const userStats = await program.account.userStats.fetch(
+      findUserStatsPda(provider.publicKey)
+    );
+    expect(Number(userStats.userId)).to.be.equal(Number(user.id));
+    expect(Number(userStats.ratingCount)).to.be.equal(0);
+  });
+
+  it("Can update a user and return true values", async function () {
+    await program.methods
+      .renameUser(sellerPayload.username)
+      .accounts({
+        user: profilePda,
+        oldUsernameRecord: findUsernameRecordPda(buyerPayload.username),
+        newUsernameRecord: findUsernameRecordPda(sellerPayload.username),
+        authority: provider.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    expect(
+      await provider.connection.getAccountInfo(
+        findUsernameRecordPda(buyerPayload.username)
+      )
+    ).to.be.null;
+
+    await program.methods
+      .updateUser(
+        contactHash(sellerPayload.phone, sellerContactSalt),
+        sellerPayload.latitude,
+        sellerPayload.longitude,
+        sellerPayload.roles
+      )
+      .accounts({
+        user: profilePda,
+        userStats: findUserStatsPda(provider.publicKey),
+        authority: provider.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    const user = await program.account.user.fetch(profilePda);
+    expect(user.username).to.be.equal(sellerPayload.username);
+    expect(user.phoneHash).to.be.deep.equal(
+      contactHash(sellerPayload.phone, sellerContactSalt)
+    );
+    expect(Number(user.location.latitude)).to.be.equal(
+      Number(sellerPayload.latitude)
+    );
+    expect(Number(user.location.longitude)).to.be.equal(
+      Number(sellerPayload.longitude)
+    );
+    expect(user.roles).to.be.equal(sellerPayload.roles);
+    expect(user.accountType).to.be.deep.equal({ seller: {} });
+  });
+
+  it("Should keep usernames unique and validate them before use", async function () {
+    const renameUser = (username: string, newUsernameRecord: PublicKey) =>
+      program.methods
+        .renameUser(username)
+        .accounts({
+          user: profilePda,
+          oldUsernameRecord: findUsernameRecordPda(sellerPayload.username),
+          newUsernameRecord,
+          authority: provider.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+    try {
+      // too long to be a seed, so no record address can be derived for it
+      await renameUser("a".repeat(40), anchor.web3.Keypair.generate().publicKey);
+      expect.fail("a username over 32 bytes should be rejected");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("InvalidUsername");
+    }
+
+    try {
+      await renameUser(
+        buyerUsername.toUpperCase(),
+        findUsernameRecordPda(buyerUsername)
+      );
+      expect.fail("a username taken in another letter case should be rejected");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("UsernameTaken");
+    }
+
+    try {
+      await program.methods
+        .renameUser("unrelated_record")
+        .accounts({
+          user: profilePda,
+          // a record the user does not hold, which would leave theirs reserved
+          oldUsernameRecord: findUsernameRecordPda(buyerUsername),
+          newUsernameRecord: findUsernameRecordPda("unrelated_record"),
+          authority: provider.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+      expect.fail("the old record must be the one of the current username");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("ConstraintSeeds");
+    }
+
+    try {
+      await program.methods
+        .claimUsername()
+        .accounts({
+          user: buyerPda,
+          usernameRecord: findUsernameRecordPda(buyerUsername),
+          payer: provider.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+      expect.fail("a registered username cannot be claimed again");
+    } catch (error) {
+      expect(error.logs.join("\n")).to.include("already in use");
+    }
+  });
+
+  it("Should let a user without open activity close their profile", async function () {
+    const user = anchor.web3.Keypair.generate();
+    const [userPda] = PublicKey.findProgramAddressSync(
+      [utf8.encode(USER_TAG), user.publicKey.toBuffer()],
+      program.programId
+    );
+
+    await provider.connection.confirmTransaction(
+      await provider.connection.requestAirdrop(
+        user.publicKey,
+        anchor.web3.LAMPORTS_PER_SOL
+      )
+    );
+
+    const createUser = () =>
+      program.methods
+        .createUser(
+          "Closing_User",
+          contactHash(buyerPayload.phone, newContactSalt()),
+          buyerPayload.latitude,
+          buyerPayload.longitude,
+          buyerPayload.roles
+        )
+        .accounts({
+          user: userPda,
+          userStats: findUserStatsPda(user.publicKey),
+          usernameRecord: findUsernameRecordPda("Closing_User"),
+          systemProgram: SystemProgram.programId,
+          userCounter: USER_COUNTER_PUBKEY,
+          authority: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+
+    await createUser();
+
+    await program.methods
+      .closeUser()
+      .accounts({
+        user: userPda,
+        userStats: findUserStatsPda(user.publicKey),
+        usernameRecord: findUsernameRecordPda("closing_user"),
+        authority: user.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user])
+      .rpc();
+
+    expect(await provider.connection.getAccountInfo(userPda)).to.be.null;
+    expect(
+      await provider.connection.getAccountInfo(
+        findUsernameRecordPda("closing_user")
+      )
+    ).to.be.null;
+    // the stats are kept, so closing does not reset the user's history
+    const closedStats = await program.account.userStats.fetch(
+      findUserStatsPda(user.publicKey)
+    );
+
+    // the wallet can come back with a fresh profile
+    await createUser();
+
+    const recreatedUser = await program.account.user.fetch(userPda);
+    const recreatedStats = await program.account.userStats.fetch(
+      findUserStatsPda(user.publicKey)
+    );
+    expect(Number(recreatedStats.userId)).to.be.equal(
+      Number(recreatedUser.id)
+    );
+    expect(Number(recreatedStats.createdAt)).to.be.equal(
+      Number(closedStats.createdAt)
+    );
+  });
+
+  it("Should reject coordinates outside the valid range", async function () {
+    try {
+      await program.methods
+        .updateUser(
+          contactHash(sellerPayload.phone, sellerContactSalt),
+          91 * 10 ** LOCATION_DECIMALS,
+          sellerPayload.longitude,
+          sellerPayload.roles
+        )
+        .accounts({
+          user: profilePda,
+          userStats: findUserStatsPda(provider.publicKey),
+          authority: provider.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+      expect.fail("latitude above 90 degrees should be rejected");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("InvalidLocation");
+    }
+  });
+
+  it("Should allow a seller to create a store", async function () {
+    const [profilePda, _] = PublicKey.findProgramAddressSync(
+      [utf8.encode(USER_TAG), provider.publicKey.toBuffer()],
+      program.programId
+    );
+
+    const storeCounter = await program.account.counter.fetch(
+      STORE_COUNTER_PUBKEY
+    );
+
+    const [storePda] = PublicKey.findProgramAddressSync(
+      [
+        utf8.encode(STORE_TAG),
+        provider.publicKey.toBuffer(),
+        Buffer.from(storeCounter.current.toArray("le", 8)),
+      ],
+      program.programId
+    );
+
+    const receipt = await program.methods
+      .createStore(
+        storePayload.name,
+        storePayload.description,
+        storePayload.lat,
+        storePayload.long,
+        new BN(0)
+      )
+      .accounts({
+        user: profilePda,
+        systemProgram: SystemProgram.programId,
+        storeCounter: STORE_COUNTER_PUBKEY,
+        authority: provider.publicKey,
+        store: storePda,
+        admin: adminPda,
+        geoCell: geoCellPda,
+        userStats: findUserStatsPda(provider.publicKey),
+      })
+      .rpc();
+
+    const store = await program.account.store.fetch(storePda);
+    expect(store.name).to.be.equal(storePayload.name);
+    expect(store.description).to.be.equal(storePayload.description);
+    expect(Number(store.location.latitude)).to.be.equal(storePayload.lat);
+  });
+
+  it("Should keep the seller role while the user has stores", async function () {
+    const [profilePda] = PublicKey.findProgramAddressSync(
+      [utf8.encode(USER_TAG), provider.publicKey.toBuffer()],
+      program.programId
+    );
+    const accounts = {
+      user: profilePda,
+      userStats: findUserStatsPda(provider.publicKey),
+      authority: provider.publicKey,
+      systemProgram: SystemProgram.programId,
+    };
+    const updateRoles = (roles: number) =>
+      program.methods
+        .updateUser(
+          contactHash(sellerPayload.phone, sellerContactSalt),
+          sellerPayload.latitude,
+          sellerPayload.longitude,
+          roles
+        )
+        .accounts(accounts)
+        .rpc();
+
+    try {
+      await updateRoles(ROLE_BUYER);
+      expect.fail("dropping the seller role with open stores should fail");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("RoleInUse");
+    }
+
+    await updateRoles(ROLE_BUYER | ROLE_SELLER);
+    expect((await program.account.user.fetch(profilePda)).roles).to.be.equal(
+      ROLE_BUYER | ROLE_SELLER
+    );
+  });
+
+  it("Should allow a seller to update and close a store", async function () {
+    const [profilePda] = PublicKey.findProgramAddressSync(
+      [utf8.encode(USER_TAG), provider.publicKey.toBuffer()],
+      program.programId
+    );
+    const storePda = await createSellerStore();
+
+    await program.methods
+      .updateStore(
+        "Renamed Store",
+        storePayload.description,
+        storePayload.lat,
+        storePayload.long,
+        new BN(5000)
+      )
+      .accounts({
+        user: profilePda,
+        store: storePda,
+        admin: adminPda,
+        geoCell: geoCellPda,
+        newGeoCell: null,
+        authority: provider.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    const store = await program.account.store.fetch(storePda);
+    expect(store.name).to.be.equal("Renamed Store");
+    expect(store.serviceRadius.toNumber()).to.be.equal(5000);
+    expect(store.creator.toBase58()).to.be.equal(
+      provider.publicKey.toBase58()
+    );
+
+    await program.methods
+      .closeStore()
+      .accounts({
+        user: profilePda,
+        store: storePda,
+        geoCell: geoCellPda,
+        userStats: findUserStatsPda(provider.publicKey),
+        authority: provider.publicKey,
+      })
+      .rpc();
+
+    expect(await provider.connection.getAccountInfo(storePda)).to.be.null;
+    const geoCell = await program.account.geoCell.fetch(geoCellPda);
+    expect(geoCell.storeIds.map((id) => id.toNumber())).to.not.include(
+      store.id.toNumber()
+    );
+  });
+
+  it("Should withdraw idle offers when a store closes or changes hands", async function () {
+    const transferredRequestPda = await createBuyerRequest();
+    const closedRequestPda = await createBuyerRequest();
+    const transferredStorePda = await createSellerStore();
+    const closedStorePda = await createSellerStore();
+    const busyStorePda = await createSellerStore();
+
+    const transferredOfferPda = await createSellerOffer(
+      transferredRequestPda,
+      transferredStorePda,
+      10
+    );
+    const closedOfferPda = await createSellerOffer(
+      closedRequestPda,
+      closedStorePda,
+      10
+    );
+
+    const busyRequestPda = await createBuyerRequest();
+    const busyOfferPda = await createSellerOffer(
+      busyRequestPda,
+      busyStorePda,
+      10
+    );
+
+    await program.methods
+      .acceptOffer()
+      .accounts({
+        user: buyerPda,
+        systemProgram: SystemProgram.programId,
+        authority: buyer.publicKey,
+        request: busyRequestPda,
+        offer: busyOfferPda,
+        category: categoryPda,
+        geoCell: geoCellPda,
+        buyerStats: findUserStatsPda(buyer.publicKey),
+        sellerStats: findUserStatsPda(provider.publicKey),
+      })
+      .remainingAccounts(await requestOfferAccounts(busyRequestPda))
+      .signers([buyer])
+      .rpc();
+
+    const newSeller = await createKeypairUser("new_store_owner", ROLE_SELLER);
+
+    const transferStore = (
+      storePda: PublicKey,
+      offerPda: PublicKey,
+      requestPda: PublicKey
+    ) =>
+      program.methods
+        .transferStore()
+        .accounts({
+          user: profilePda,
+          store: storePda,
+          newUser: newSeller.userPda,
+          userStats: findUserStatsPda(provider.publicKey),
+          newUserStats: findUserStatsPda(newSeller.keypair.publicKey),
+          authority: provider.publicKey,
+          newAuthority: newSeller.keypair.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .remainingAccounts([
+          { pubkey: offerPda, isWritable: true, isSigner: false },
+          { pubkey: requestPda, isWritable: true, isSigner: false },
+        ])
+        .signers([newSeller.keypair])
+        .rpc();
+
+    try {
+      await transferStore(busyStorePda, busyOfferPda, busyRequestPda);
+      expect.fail("a store with accepted offers should not change hands");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("StoreHasLiveOffers");
+    }
+
+    try {
+      await program.methods
+        .closeStore()
+        .accounts({
+          user: profilePda,
+          store: transferredStorePda,
+          geoCell: geoCellPda,
+          userStats: findUserStatsPda(provider.publicKey),
+          authority: provider.publicKey,
+        })
+        .remainingAccounts([
+          { pubkey: transferredOfferPda, isWritable: true, isSigner: false },
+        ])
+        .rpc();
+      expect.fail("the request of an idle offer must be passed");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("OfferRequestMissing");
+    }
+
+    await transferStore(
+      transferredStorePda,
+      transferredOfferPda,
+      transferredRequestPda
+    );
+
+    const transferredStore = await program.account.store.fetch(
+      transferredStorePda
+    );
+    expect(transferredStore.authority.toBase58()).to.be.equal(
+      newSeller.keypair.publicKey.toBase58()
+    );
+    expect(transferredStore.offerIds).to.be.empty;
+
+    await program.methods
+      .closeStore()
+      .accounts({
+        user: profilePda,
+        store: closedStorePda,
+        geoCell: geoCellPda,
+        userStats: findUserStatsPda(provider.publicKey),
+        authority: provider.publicKey,
+      })
+      .remainingAccounts([
+        { pubkey: closedOfferPda, isWritable: true, isSigner: false },
+        { pubkey: closedRequestPda, isWritable: true, isSigner: false },
+      ])
+      .rpc();
+
+    for (const [offerPda, requestPda] of [
+      [transferredOfferPda, transferredRequestPda],
+      [closedOfferPda, closedRequestPda],
+    ]) {
+      expect((await program.account.offer.fetch(offerPda)).isWithdrawn).to.be
+        .true;
+      // the withdrawn offer frees its seller's slot on the request
+      expect((await program.account.request.fetch(requestPda)).sellerIds).to.be
+        .empty;
+
+      try {
+        await program.methods
+          .acceptOffer()
+          .accounts({
+            user: buyerPda,
+            systemProgram: SystemProgram.programId,
+            authority: buyer.publicKey,
+            request: requestPda,
+            offer: offerPda,
+            category: categoryPda,
+            geoCell: geoCellPda,
+            buyerStats: findUserStatsPda(buyer.publicKey),
+            sellerStats: findUserStatsPda(provider.publicKey),
+          })
+          .remainingAccounts(await requestOfferAccounts(requestPda))
+          .signers([buyer])
+          .rpc();
+        expect.fail("a withdrawn offer should not be accepted");
+      } catch (error) {
+        expect(error.error.errorCode.code).to.be.equal("OfferWithdrawn");
+      }
+    }
+  });
+
+  it("Should withdraw and drop idle offers when a store is pruned", async function () {
+    const requestPda = await createBuyerRequest();
+    const storePda = await createSellerStore();
+    const offerPda = await createSellerOffer(requestPda, storePda, 10);
+
+    await program.methods
+      .pruneStoreOffers()
+      .accounts({
+        store: storePda,
+        authority: provider.publicKey,
+      })
+      .remainingAccounts([
+        { pubkey: offerPda, isWritable: true, isSigner: false },
+        { pubkey: requestPda, isWritable: true, isSigner: false },
+      ])
+      .rpc();
+
+    const store = await program.account.store.fetch(storePda);
+    expect(store.offerIds).to.be.empty;
+    expect((await program.account.offer.fetch(offerPda)).isWithdrawn).to.be
+      .true;
+
+    // with the slot freed the seller can offer again
+    await createSellerOffer(requestPda, await createSellerStore(), 9);
+  });
+
+  it("Should allow a seller to set opening hours and mark a store away", async function () {
+    const [profilePda] = PublicKey.findProgramAddressSync(
+      [utf8.encode(USER_TAG), provider.publicKey.toBuffer()],
+      program.programId
+    );
+    const storePda = await createSellerStore();
+    const accounts = {
+      user: profilePda,
+      store: storePda,
+      authority: provider.publicKey,
+    };
+
+    // weekdays 09:00-18:00, Saturday overnight 22:00-02:00, Sunday closed
+    const weekdays = Array.from({ length: 7 }, (_, day) =>
+      day < 5
+        ? { open: 9 * 60, close: 18 * 60 }
+        : day === 5
+        ? { open: 22 * 60, close: 2 * 60 }
+        : { open: 0, close: 0 }
+    );
+
+    try {
+      await program.methods
+        .setStoreHours(
+          weekdays.map(() => ({ open: 0, close: 25 * 60 })),
+          0
+        )
+        .accounts(accounts)
+        .rpc();
+      expect.fail("hours past the end of the day should be rejected");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("InvalidOpeningHours");
+    }
+
+    await program.methods
+      .setStoreHours(weekdays, 60)
+      .accounts(accounts)
+      .rpc();
+    await program.methods.setStoreAway(true).accounts(accounts).rpc();
+
+    const store = await program.account.store.fetch(storePda);
+    expect(store.openingHours[0].close).to.be.equal(18 * 60);
+    expect(store.openingHours[5].open).to.be.equal(22 * 60);
+    expect(store.openingHours[5].close).to.be.equal(2 * 60);
+    expect(store.utcOffsetMinutes).to.be.equal(60);
+    expect(store.isAway).to.be.true;
+  });
+
+  it("Should only let the admin or verifier verify a store", async function () {
+    const storePda = await createSellerStore();
+
+    try {
+      await program.methods
+        .verifyStore(2, new BN(0))
+        .accounts({
+          admin: adminPda,
+          store: storePda,
+          authority: buyer.publicKey,
+        })
+        .signers([buyer])
+        .rpc();
+      expect.fail("a buyer should not be able to verify stores");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("UnauthorizedVerifier");
+    }
+
+    await program.methods
+      .verifyStore(2, new BN(0))
+      .accounts({
+        admin: adminPda,
+        store: storePda,
+        authority: provider.publicKey,
+      })
+      .rpc();
+
+    const store = await program.account.store.fetch(storePda);
+    expect(store.verificationLevel).to.be.equal(2);
+    expect(store.verifiedUntil.toNumber()).to.be.equal(0);
+  });
+
+  it("Should allow a seller to add and remove a store delegate", async function () {
+    const storePda = await createSellerStore();
+    const delegate = anchor.web3.Keypair.generate();
+
+    const [storeDelegatePda] = PublicKey.findProgramAddressSync(
+      [
+        utf8.encode(STORE_DELEGATE_TAG),
+        storePda.toBuffer(),
+        delegate.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+
+    const setDelegate = (permissions: number) =>
+      program.methods
+        .setStoreDelegate(delegate.publicKey, permissions)
+        .accounts({
+          store: storePda,
+          storeDelegate: storeDelegatePda,
+          authority: provider.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+    try {
+      await setDelegate(1 << 7);
+      expect.fail("unknown permission bits should be rejected");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal(
+        "InvalidDelegatePermissions"
+      );
+    }
+
+    await setDelegate(DELEGATE_CREATE_OFFERS | DELEGATE_CONFIRM_DELIVERY);
+
+    const storeDelegate = await program.account.storeDelegate.fetch(
+      storeDelegatePda
+    );
+    expect(storeDelegate.delegate.toBase58()).to.be.equal(
+      delegate.publicKey.toBase58()
+    );
+    expect(storeDelegate.permissions).to.be.equal(
+      DELEGATE_CREATE_OFFERS | DELEGATE_CONFIRM_DELIVERY
+    );
+
+    await program.methods
+      .removeStoreDelegate()
+      .accounts({
+        store: storePda,
+        storeDelegate: storeDelegatePda,
+        authority: provider.publicKey,
+      })
+      .rpc();
+
+    expect(await provider.connection.getAccountInfo(storeDelegatePda)).to.be
+      .null;
+  });
+
+  it("Should allow a seller to list an item in a store", async function () {
+    const storePda = await createSellerStore();
+
+    const [listingCounterPda] = PublicKey.findProgramAddressSync(
+      [utf8.encode(LISTING_COUNTER)],
+      program.programId
+    );
+
+    await program.methods
+      .initializeListingCounter()
+      .accounts({
+        listingCounter: listingCounterPda,
+        authority: provider.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    const listingCounter = await program.account.counter.fetch(
+      listingCounterPda
+    );
+
+    const [listingPda] = PublicKey.findProgramAddressSync(
+      [
+        utf8.encode(LISTING_TAG),
+        storePda.toBuffer(),
+        Buffer.from(listingCounter.current.toArray("le", 8)),
+      ],
+      program.programId
+    );
+
+    await program.methods
+      .createListing(
+        "test item",
+        "test description",
+        ["image1"],
+        new BN(10),
+        new BN(3)
+      )
+      .accounts({
+        store: storePda,
+        listing: listingPda,
+        listingCounter: listingCounterPda,
+        category: categoryPda,
+        authority: provider.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    const listing = await program.account.listing.fetch(listingPda);
+    const store = await program.account.store.fetch(storePda);
+    expect(listing.storeId.toNumber()).to.be.equal(store.id.toNumber());
+    expect(listing.title).to.be.equal("test item");
+    expect(listing.stock.toNumber()).to.be.equal(3);
+    expect(listing.category).to.be.equal(categoryName);
+
+    try {
+      await program.methods
+        .updateListing(
+          "test item",
+          "test description",
+          Array(MAX_IMAGES + 1).fill("image1"),
+          new BN(10),
+          new BN(3)
+        )
+        .accounts({
+          store: storePda,
+          listing: listingPda,
+          authority: provider.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+      expect.fail("a listing over the image limit should be rejected");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("TooManyImages");
+    }
+
+    await program.methods
+      .closeListing()
+      .accounts({
+        store: storePda,
+        listing: listingPda,
+        authority: provider.publicKey,
+      })
+      .rpc();
+
+    expect(await provider.connection.getAccountInfo(listingPda)).to.be.null;
+  });
+
+  it("Should migrate a request from the legacy layout once", async function () {
+    // tests/fixtures/legacy_request.json: a pending request with id 900000 in
+    // the pre-versioning layout, with i128 coordinates scaled by 1e18.
+    const legacyRequest = new PublicKey(
+      "4uE4kWHJrzF1TENTFD1FyKJe8nfeScZri5kjPTAjYpBS"
+    );
+    // the fixture's buyer, whose profile is migrated before the request
+    const legacyBuyer = await createKeypairUser(
+      "legacy_buyer",
+      ROLE_BUYER,
+      anchor.web3.Keypair.fromSeed(Buffer.alloc(32, 9))
+    );
+
+    const migrateRequest = () =>
+      program.methods
+        .migrateRequest()
+        .accounts({
+          admin: adminPda,
+          account: legacyRequest,
+          category: categoryPda,
+          geoCell: geoCellPda,
+          user: legacyBuyer.userPda,
+          userStats: findUserStatsPda(legacyBuyer.keypair.publicKey),
+          authority: provider.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+    await migrateRequest();
+
+    const request = await program.account.request.fetch(legacyRequest);
+    expect(Number(request.id)).to.be.equal(900000);
+    expect(request.name).to.be.equal("legacy request");
+    expect(request.images).to.be.deep.equal(["image1"]);
+    expect(Number(request.quantity)).to.be.equal(1);
+    expect(Number(request.acceptedQuantity)).to.be.equal(0);
+    expect(request.mode).to.be.deep.equal({ standard: {} });
+    expect(request.lifecycle).to.be.deep.equal({ pending: {} });
+    expect(request.category).to.be.equal(categoryName);
+    expect(request.geohash).to.be.equal(
+      encodeGeohash(
+        storePayload.lat / 10 ** LOCATION_DECIMALS,
+        storePayload.long / 10 ** LOCATION_DECIMALS
+      )
+    );
+    expect(Number(request.location.latitude)).to.be.equal(
+      coarsenCoordinate(43_830_000)
+    );
+    expect(Number(request.location.longitude)).to.be.equal(
+      coarsenCoordinate(33_800_000)
+    );
+    expect(request.version).to.be.equal(1);
+
+    const category = await program.account.category.fetch(categoryPda);
+    expect(category.requestIds.map(Number)).to.include(900000);
+
+    const legacyBuyerStats = await program.account.userStats.fetch(
+      findUserStatsPda(legacyBuyer.keypair.publicKey)
+    );
+    expect(Number(legacyBuyerStats.openRequests)).to.be.equal(1);
+
+    try {
+      await migrateRequest();
+      expect.fail("a migrated request should not be migrated again");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("AccountAlreadyMigrated");
+    }
+  });
+
+  it("Should allow a buyer to create a request", async function () {
+    const requestCounter = await program.account.counter.fetch(
+      REQUEST_COUNTER_PUBKEY
+    );
+
+    const [requestPda] = PublicKey.findProgramAddressSync(
+      [
+        utf8.encode(REQUEST_TAG),
+        buyer.publicKey.toBuffer(),
+        Buffer.from(requestCounter.current.toArray("le", 8)),
+      ],
+      program.programId
+    );
+
+    await program.methods
+      .createRequest(
+        requestPayload.name,
+        requestPayload.description,
+        requestPayload.images,
+        requestPayload.lat,
+        requestPayload.long,
+        null,
+        null,
+        { solana: {} },
+        { standard: {} },
+        new BN(0),
+        false,
+        new BN(0),
+        new BN(1),
+        false,
+        new BN(0),
+        new BN(0),
+        locationCommitment,
+        0
+      )
+      .accounts({
+        user: buyerPda,
+        systemProgram: SystemProgram.programId,
+        requestCounter: REQUEST_COUNTER_PUBKEY,
+        authority: buyer.publicKey,
+        request: requestPda,
+        category: categoryPda,
+        admin: adminPda,
+        geoCell: geoCellPda,
+        userStats: findUserStatsPda(buyer.publicKey),
+      })
+      .signers([buyer])
+      .rpc();
+    const request = await program.account.request.fetch(requestPda);
+    expect(request.name).to.be.equal(requestPayload.name);
+    expect(request.description).to.be.equal(requestPayload.description);
+    expect(request.images).to.be.deep.equal(requestPayload.images);
+    expect(Number(request.location.latitude)).to.be.equal(requestPayload.lat);
+    expect(request.preciseLocationCommitment).to.be.deep.equal(
+      locationCommitment
+    );
+    expect(request.category).to.be.equal(categoryName);
+
+    const category = await program.account.category.fetch(categoryPda);
+    expect(category.requestIds.map(Number)).to.include(Number(request.id));
+  });
+
+  it("Should reject a request with precise coordinates", async function () {
+    const requestCounter = await program.account.counter.fetch(
+      REQUEST_COUNTER_PUBKEY
+    );
+
+    const [requestPda] = PublicKey.findProgramAddressSync(
+      [
+        utf8.encode(REQUEST_TAG),
+        buyer.publicKey.toBuffer(),
+        Buffer.from(requestCounter.current.toArray("le", 8)),
+      ],
+      program.programId
+    );
+
+    try {
+      await program.methods
+        .createRequest(
+          requestPayload.name,
+          requestPayload.description,
+          requestPayload.images,
+          storePayload.lat,
+          storePayload.long,
+          null,
+          null,
+          { solana: {} },
+          { standard: {} },
+          new BN(0),
+          false,
+          new BN(0),
+          new BN(1),
+          false,
+          new BN(0),
+          new BN(0),
+          locationCommitment,
+          0
+        )
+        .accounts({
+          user: buyerPda,
+          systemProgram: SystemProgram.programId,
+          requestCounter: REQUEST_COUNTER_PUBKEY,
+          authority: buyer.publicKey,
+          request: requestPda,
+          category: categoryPda,
+          admin: adminPda,
+          geoCell: geoCellPda,
+          userStats: findUserStatsPda(buyer.publicKey),
+        })
+        .signers([buyer])
+        .rpc();
+      expect.fail("precise coordinates should be rejected");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("LocationNotCoarse");
+    }
+  });
+
+  it("Should allow a seller to create a offer", async function () {
+    const requestCounter = await program.account.counter.fetch(
+      REQUEST_COUNTER_PUBKEY
+    );
+
+    const [requestPda] = PublicKey.findProgramAddressSync(
+      [
+        utf8.encode(REQUEST_TAG),
+        buyer.publicKey.toBuffer(),
+        Buffer.from(requestCounter.current.toArray("le", 8)),
+      ],
+      program.programId
+    );
+
+    await program.methods
+      .createRequest(
+        requestPayload.name,
+        requestPayload.description,
+        requestPayload.images,
+        requestPayload.lat,
+        requestPayload.long,
+        null,
+        null,
+        { solana: {} },
+        { standard: {} },
+        new BN(0),
+        false,
+        new BN(0),
+        new BN(1),
+        false,
+        new BN(0),
+        new BN(0),
+        locationCommitment,
+        0
+      )
+      .accounts({
+        user: buyerPda,
+        systemProgram: SystemProgram.programId,
+        requestCounter: REQUEST_COUNTER_PUBKEY,
+        authority: buyer.publicKey,
+        request: requestPda,
+        category: categoryPda,
+        admin: adminPda,
+        geoCell: geoCellPda,
+        userStats: findUserStatsPda(buyer.publicKey),
+      })
+      .signers([buyer])
+      .rpc();
+
+    const request = await program.account.request.fetch(requestPda);
+
+    const storePda = await createSellerStore();
+
+    const offerCounter = await program.account.counter.fetch(
+      OFFER_COUNTER_PUBKEY
+    );
+
+    const [offerPda] = PublicKey.findProgramAddressSync(
+      [
+        utf8.encode(OFFER_TAG),
+        provider.publicKey.toBuffer(),
+        Buffer.from(offerCounter.current.toArray("le", 8)),
+      ],
+      program.programId
+    );
+
+    const offerPayload = {
+      price: Math.trunc(10),
+      images: ["image1", "image2"],
+    };
+
+    await program.methods
+      .createOffer(
+        new BN(offerPayload.price.toString()),
+        offerPayload.images,
+        new BN(1)
+      )
+      .accounts({
+        user: profilePda,
+        systemProgram: SystemProgram.programId,
+        offerCounter: OFFER_COUNTER_PUBKEY,
+        authority: provider.publicKey,
+        request: requestPda,
+        offer: offerPda,
+        store: storePda,
+        userStats: findUserStatsPda(provider.publicKey),
+        admin: adminPda,
+        sellerBond: null,
+        storeDelegate: null,
+        priceUpdate: null,
+      })
+      .rpc();
+
+    const offer = await program.account.offer.fetch(offerPda);
+    expect(Number(offer.price)).to.be.equal(offerPayload.price);
+    expect(offer.images).to.be.deep.equal(offerPayload.images);
+    expect(offer.storeName).to.be.equal(storePayload.name);
+  });
+
+  it("Should reject an offer above the request budget", async function () {
+    const requestCounter = await program.account.counter.fetch(
+      REQUEST_COUNTER_PUBKEY
+    );
+
+    const [requestPda] = PublicKey.findProgramAddressSync(
+      [
+        utf8.encode(REQUEST_TAG),
+        buyer.publicKey.toBuffer(),
+        Buffer.from(requestCounter.current.toArray("le", 8)),
+      ],
+      program.programId
+    );
+
+    await program.methods
+      .createRequest(
+        requestPayload.name,
+        requestPayload.description,
+        requestPayload.images,
+        requestPayload.lat,
+        requestPayload.long,
+        new BN(1),
+        new BN(5),
+        { solana: {} },
+        { standard: {} },
+        new BN(0),
+        false,
+        new BN(0),
+        new BN(1),
+        false,
+        new BN(0),
+        new BN(0),
+        locationCommitment,
+        0
+      )
+      .accounts({
+        user: buyerPda,
+        systemProgram: SystemProgram.programId,
+        requestCounter: REQUEST_COUNTER_PUBKEY,
+        authority: buyer.publicKey,
+        request: requestPda,
+        category: categoryPda,
+        admin: adminPda,
+        geoCell: geoCellPda,
+        userStats: findUserStatsPda(buyer.publicKey),
+      })
+      .signers([buyer])
+      .rpc();
+
+    const request = await program.account.request.fetch(requestPda);
+    expect(Number(request.maxPrice)).to.be.equal(5);
+
+    const storePda = await createSellerStore();
+
+    const offerCounter = await program.account.counter.fetch(
+      OFFER_COUNTER_PUBKEY
+    );
+
+    const [offerPda] = PublicKey.findProgramAddressSync(
+      [
+        utf8.encode(OFFER_TAG),
+        provider.publicKey.toBuffer(),
+        Buffer.from(offerCounter.current.toArray("le", 8)),
+      ],
+      program.programId
+    );
+
+    try {
+      await program.methods
+        .createOffer(new BN(10), ["image1"], new BN(1))
+        .accounts({
+          user: profilePda,
+          systemProgram: SystemProgram.programId,
+          offerCounter: OFFER_COUNTER_PUBKEY,
+          authority: provider.publicKey,
+          request: requestPda,
+          offer: offerPda,
+          store: storePda,
+          userStats: findUserStatsPda(provider.publicKey),
+          admin: adminPda,
+          sellerBond: null,
+          storeDelegate: null,
+          priceUpdate: null,
+        })
+        .rpc();
+      expect.fail("offer above budget should be rejected");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("OfferAboveBudget");
+    }
+  });
+
+  it("Should re-check the budget and range when a seller updates an offer", async function () {
+    const requestPda = await createBuyerRequest({ maxPrice: 20, quantity: 2 });
+    const storePda = await createSellerStore();
+    const offerPda = await createSellerOffer(requestPda, storePda, 10);
+
+    const updateOffer = (price: number, quantity: number) =>
+      program.methods
+        .updateOffer(new BN(price), ["image2"], new BN(quantity))
+        .accounts({
+          offer: offerPda,
+          request: requestPda,
+          store: storePda,
+          storeDelegate: null,
+          authority: provider.publicKey,
+          priceUpdate: null,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
+
+    await updateOffer(15, 2);
+
+    const offer = await program.account.offer.fetch(offerPda);
+    expect(Number(offer.price)).to.be.equal(15);
+    expect(Number(offer.quantity)).to.be.equal(2);
+    expect(offer.images).to.be.deep.equal(["image2"]);
+
+    try {
+      await updateOffer(25, 2);
+      expect.fail("an update above the request budget should be rejected");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("OfferAboveBudget");
+    }
+
+    // the request sits a few hundred meters from the store
+    await program.methods
+      .updateStore(
+        storePayload.name,
+        storePayload.description,
+        storePayload.lat,
+        storePayload.long,
+        new BN(100)
+      )
+      .accounts({
+        user: profilePda,
+        store: storePda,
+        admin: adminPda,
+        geoCell: geoCellPda,
+        newGeoCell: null,
+        authority: provider.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    try {
+      await updateOffer(12, 1);
+      expect.fail("an update from a store out of range should be rejected");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("SellerOutOfRange");
+    }
+  });
+
+  it("Should accept a sealed bid once it is revealed", async function () {
+    const now = await chainNow();
+    const requestPda = await createBuyerRequest({
+      biddingDeadline: now + 8,
+      sealedBids: true,
+      revealDeadline: now + 16,
+    });
+    const storePda = await createSellerStore();
+    const salt = Buffer.alloc(32, 7);
+
+    const offerPda = await commitSealedOffer(requestPda, storePda, 10, salt);
+
+    const committedOffer = await program.account.offer.fetch(offerPda);
+    expect(committedOffer.isRevealed).to.be.false;
+    expect(Number(committedOffer.price)).to.be.equal(0);
+
+    try {
+      await revealSealedOffer(requestPda, storePda, offerPda, 10, salt);
+      expect.fail("a bid should not be revealed while bidding is open");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("BiddingNotClosed");
+    }
+
+    await sleep(10);
+    await revealSealedOffer(requestPda, storePda, offerPda, 10, salt);
+
+    const revealedOffer = await program.account.offer.fetch(offerPda);
+    expect(revealedOffer.isRevealed).to.be.true;
+    expect(Number(revealedOffer.price)).to.be.equal(10);
+
+    await sleep(8);
+
+    await program.methods
+      .acceptOffer()
+      .accounts({
+        user: buyerPda,
+        systemProgram: SystemProgram.programId,
+        authority: buyer.publicKey,
+        request: requestPda,
+        offer: offerPda,
+        category: categoryPda,
+        geoCell: geoCellPda,
+        buyerStats: findUserStatsPda(buyer.publicKey),
+        sellerStats: findUserStatsPda(provider.publicKey),
+      })
+      .remainingAccounts(await requestOfferAccounts(requestPda))
+      .signers([buyer])
+      .rpc();
+
+    expect((await program.account.offer.fetch(offerPda)).isAccepted).to.be
+      .true;
+  });
+
+  it("Should reject a sealed bid revealed with the wrong salt", async function () {
+    const now = await chainNow();
+    const requestPda = await createBuyerRequest({
+      biddingDeadline: now + 8,
+      sealedBids: true,
+      revealDeadline: now + 60,
+    });
+    const storePda = await createSellerStore();
+    const salt = Buffer.alloc(32, 7);
+
+    const offerPda = await commitSealedOffer(requestPda, storePda, 10, salt);
+
+    await sleep(10);
+
+    try {
+      await revealSealedOffer(
+        requestPda,
+        storePda,
+        offerPda,
+        10,
+        Buffer.alloc(32, 8)
+      );
+      expect.fail("a reveal with the wrong salt should be rejected");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("CommitmentMismatch");
+    }
+
+    try {
+      await revealSealedOffer(requestPda, storePda, offerPda, 9, salt);
+      expect.fail("a reveal with a different price should be rejected");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("CommitmentMismatch");
+    }
+
+    expect((await program.account.offer.fetch(offerPda)).isRevealed).to.be
+      .false;
+  });
+
+  it("Should reject a sealed bid revealed after the reveal deadline", async function () {
+    const now = await chainNow();
+    const requestPda = await createBuyerRequest({
+      biddingDeadline: now + 8,
+      sealedBids: true,
+      revealDeadline: now + 10,
+    });
+    const storePda = await createSellerStore();
+    const salt = Buffer.alloc(32, 7);
+
+    const offerPda = await commitSealedOffer(requestPda, storePda, 10, salt);
+
+    await sleep(12);
+
+    try {
+      await revealSealedOffer(requestPda, storePda, offerPda, 10, salt);
+      expect.fail("a late reveal should be rejected");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("RevealClosed");
+    }
+
+    try {
+      await program.methods
+        .acceptOffer()
+        .accounts({
+          user: buyerPda,
+          systemProgram: SystemProgram.programId,
+          authority: buyer.publicKey,
+          request: requestPda,
+          offer: offerPda,
+          category: categoryPda,
+          geoCell: geoCellPda,
+          buyerStats: findUserStatsPda(buyer.publicKey),
+          sellerStats: findUserStatsPda(provider.publicKey),
+        })
+        .remainingAccounts(await requestOfferAccounts(requestPda))
+        .signers([buyer])
+        .rpc();
+      expect.fail("an unrevealed bid should not be accepted");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("OfferNotRevealed");
+    }
+  });
+
+  it("Should settle a reverse auction on the lowest offer", async function () {
+    const requestPda = await createBuyerRequest({
+      mode: { reverseAuction: {} },
+      biddingDeadline: (await chainNow()) + 8,
+    });
+    const storePda = await createSellerStore();
+    const rival = await createKeypairUser("auction_rival", ROLE_SELLER);
+    const rivalStorePda = await createSellerStore(
+      undefined,
+      undefined,
+      0,
+      rival
+    );
+
+    const higherOfferPda = await createSellerOffer(requestPda, storePda, 10);
+    const lowerOfferPda = await createSellerOffer(
+      requestPda,
+      rivalStorePda,
+      8,
+      1,
+      rival
+    );
+
+    try {
+      await createSellerOffer(requestPda, storePda, 9);
+      expect.fail("a seller should only have one live offer per request");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("SellerAlreadyOffered");
+    }
+
+    const settleAuction = async () =>
+      program.methods
+        .settleAuction()
+        .accounts({
+          request: requestPda,
+          category: categoryPda,
+          geoCell: geoCellPda,
+          buyerStats: findUserStatsPda(buyer.publicKey),
+          authority: provider.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .remainingAccounts(await auctionSettlementAccounts(requestPda))
+        .rpc();
+
+    try {
+      await settleAuction();
+      expect.fail("an auction should not settle before bidding closes");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("BiddingNotClosed");
+    }
+
+    const buyerStatsBefore = await program.account.userStats.fetch(
+      findUserStatsPda(buyer.publicKey)
+    );
+    const sellerStatsBefore = await program.account.userStats.fetch(
+      findUserStatsPda(rival.keypair.publicKey)
+    );
+
+    await sleep(10);
+    await settleAuction();
+
+    const buyerStats = await program.account.userStats.fetch(
+      findUserStatsPda(buyer.publicKey)
+    );
+    expect(Number(buyerStats.acceptancesGiven)).to.be.equal(
+      Number(buyerStatsBefore.acceptancesGiven) + 1
+    );
+    const sellerStats = await program.account.userStats.fetch(
+      findUserStatsPda(rival.keypair.publicKey)
+    );
+    expect(Number(sellerStats.offersAccepted)).to.be.equal(
+      Number(sellerStatsBefore.offersAccepted) + 1
+    );
+
+    expect((await program.account.offer.fetch(lowerOfferPda)).isAccepted).to
+      .be.true;
+    expect((await program.account.offer.fetch(higherOfferPda)).isAccepted).to
+      .be.false;
+
+    const request = await program.account.request.fetch(requestPda);
+    expect(request.lifecycle).to.be.deep.equal({ acceptedByBuyer: {} });
+    expect(Number(request.acceptedQuantity)).to.be.equal(1);
+
+    try {
+      await settleAuction();
+      expect.fail("an auction should only settle once");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("AuctionAlreadySettled");
+    }
+  });
+
+  it("Should cancel an auction no offer can win without forfeiting the deposit", async function () {
+    const now = await chainNow();
+    const requestPda = await createBuyerRequest({
+      mode: { reverseAuction: {} },
+      biddingDeadline: now + 6,
+      sealedBids: true,
+      revealDeadline: now + 8,
+    });
+    const storePda = await createSellerStore();
+
+    // never revealed, so it cannot win
+    const offerPda = await commitSealedOffer(
+      requestPda,
+      storePda,
+      10,
+      Buffer.alloc(32, 3)
+    );
+
+    const buyerStatsBefore = await program.account.userStats.fetch(
+      findUserStatsPda(buyer.publicKey)
+    );
+
+    await sleep(10);
+
+    await program.methods
+      .settleAuction()
+      .accounts({
+        request: requestPda,
+        category: categoryPda,
+        geoCell: geoCellPda,
+        buyerStats: findUserStatsPda(buyer.publicKey),
+        authority: provider.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .remainingAccounts(await auctionSettlementAccounts(requestPda))
+      .rpc();
+
+    const request = await program.account.request.fetch(requestPda);
+    expect(request.lifecycle).to.be.deep.equal({ cancelled: {} });
+
+    const buyerStats = await program.account.userStats.fetch(
+      findUserStatsPda(buyer.publicKey)
+    );
+    expect(Number(buyerStats.openRequests)).to.be.equal(
+      Number(buyerStatsBefore.openRequests) - 1
+    );
+    expect(Number(buyerStats.requestsCancelled)).to.be.equal(
+      Number(buyerStatsBefore.requestsCancelled)
+    );
+
+    await program.methods
+      .deleteRequest()
+      .accounts({
+        request: requestPda,
+        category: categoryPda,
+        geoCell: geoCellPda,
+        userStats: findUserStatsPda(buyer.publicKey),
+        authority: buyer.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .remainingAccounts(await requestOfferAccounts(requestPda))
+      .signers([buyer])
+      .rpc();
+
+    expect(await provider.connection.getAccountInfo(requestPda)).to.be.null;
+    expect((await program.account.offer.fetch(offerPda)).isWithdrawn).to.be
+      .true;
+  });
+  it("Should allow a buyer to accept an offer", async function () {
+    const requestCounter = await program.account.counter.fetch(
+      REQUEST_COUNTER_PUBKEY
+    );
+
+    const [requestPda] = PublicKey.findProgramAddressSync(
+      [
+        utf8.encode(REQUEST_TAG),
+        buyer.publicKey.toBuffer(),
+        Buffer.from(requestCounter.current.toArray("le", 8)),
+      ],
+      program.programId
+    );
+
+    await program.methods
+      .createRequest(
+        requestPayload.name,
+        requestPayload.description,
+        requestPayload.images,
+        requestPayload.lat,
+        requestPayload.long,
+        null,
+        null,
+        { solana: {} },
+        { standard: {} },
+        new BN(0),
+        false,
+        new BN(0),
+        new BN(1),
+        false,
+        new BN(0),
+        new BN(0),
+        locationCommitment,
+        0
+      )
+      .accounts({
+        user: buyerPda,
+        systemProgram: SystemProgram.programId,
+        requestCounter: REQUEST_COUNTER_PUBKEY,
+        authority: buyer.publicKey,
+        request: requestPda,
+        category: categoryPda,
+        admin: adminPda,
+        geoCell: geoCellPda,
+        userStats: findUserStatsPda(buyer.publicKey),
+      })
+      .signers([buyer])
+      .rpc();
+
+    const request = await program.account.request.fetch(requestPda);
+
+    const storePda = await createSellerStore();
+
+    const offerCounter = await program.account.counter.fetch(
+      OFFER_COUNTER_PUBKEY
+    );
+
+    const [offerPda] = PublicKey.findProgramAddressSync(
+      [
+        utf8.encode(OFFER_TAG),
+        provider.publicKey.toBuffer(),
+        Buffer.from(offerCounter.current.toArray("le", 8)),
+      ],
+      program.programId
+    );
+
+    const offerPayload = {
+      price: Math.trunc(10),
+      images: ["image1", "image2"],
+    };
+
+    await program.methods
+      .createOffer(
+        new BN(offerPayload.price.toString()),
+        offerPayload.images,
+        new BN(1)
+      )
+      .accounts({
+        user: profilePda,
+        systemProgram: SystemProgram.programId,
+        offerCounter: OFFER_COUNTER_PUBKEY,
+        authority: provider.publicKey,
+        request: requestPda,
+        offer: offerPda,
+        store: storePda,
+        userStats: findUserStatsPda(provider.publicKey),
+        admin: adminPda,
+        sellerBond: null,
+        storeDelegate: null,
+        priceUpdate: null,
+      })
+      .rpc();
+
+    const offer = await program.account.offer.fetch(offerPda);
+
+    const offerAccounts = await program.account.offer.all([
+      {
+        memcmp: {
+          offset: 8 + 32 + 8,
+          bytes: ntobs58(offer.requestId),
+        },
+      },
+    ]);
+
+    const acceptOffer = (sellerOffers: PublicKey[]) =>
+      program.methods
+        .acceptOffer()
+        .accounts({
+          user: buyerPda,
+          systemProgram: SystemProgram.programId,
+          authority: buyer.publicKey,
+          request: requestPda,
+          offer: offerPda,
+          category: categoryPda,
+          geoCell: geoCellPda,
+          buyerStats: findUserStatsPda(buyer.publicKey),
+          sellerStats: findUserStatsPda(provider.publicKey),
+        })
+        .remainingAccounts(
+          sellerOffers.map((pubkey) => ({
+            pubkey,
+            isWritable: true,
+            isSigner: false,
+          }))
+        )
+        .signers([buyer])
+        .rpc();
+
+    try {
+      // the request is owned by the program but is not an offer
+      await acceptOffer([requestPda]);
+      expect.fail("an account that is not an offer should be rejected");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("InvalidOffer");
+    }
+
+    await acceptOffer(
+      offerAccounts.map((offerAccount) => offerAccount.publicKey)
+    );
+
+    const updatedOffer = await program.account.offer.fetch(offerPda);
+    expect(updatedOffer.isAccepted).to.be.equal(true);
+
+    const [contactSharePda] = PublicKey.findProgramAddressSync(
+      [
+        utf8.encode(CONTACT_TAG),
+        requestPda.toBuffer(),
+        buyer.publicKey.toBuffer(),
+        provider.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+
+    const encryptedContact = Buffer.from("encrypted contact");
+
+    await program.methods
+      .shareContact(encryptedContact)
+      .accounts({
+        request: requestPda,
+        offer: offerPda,
+        contactShare: contactSharePda,
+        recipient: provider.publicKey,
+        sender: buyer.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([buyer])
+      .rpc();
+
+    const contactShare = await program.account.contactShare.fetch(
+      contactSharePda
+    );
+    expect(contactShare.recipient.toBase58()).to.be.equal(
+      provider.publicKey.toBase58()
+    );
+    expect(Buffer.from(contactShare.encryptedContact)).to.be.deep.equal(
+      encryptedContact
+    );
+  });
+
+  it("Should allow a buyer to accept several partial offers", async function () {
+    const requestCounter = await program.account.counter.fetch(
+      REQUEST_COUNTER_PUBKEY
+    );
+
+    const [requestPda] = PublicKey.findProgramAddressSync(
+      [
+        utf8.encode(REQUEST_TAG),
+        buyer.publicKey.toBuffer(),
+        Buffer.from(requestCounter.current.toArray("le", 8)),
+      ],
+      program.programId
+    );
+
+    await program.methods
+      .createRequest(
+        requestPayload.name,
+        requestPayload.description,
+        requestPayload.images,
+        requestPayload.lat,
+        requestPayload.long,
+        null,
+        null,
+        { solana: {} },
+        { standard: {} },
+        new BN(0),
+        false,
+        new BN(0),
+        new BN(200),
+        false,
+        new BN(0),
+        new BN(0),
+        locationCommitment,
+        0
+      )
+      .accounts({
+        user: buyerPda,
+        systemProgram: SystemProgram.programId,
+        requestCounter: REQUEST_COUNTER_PUBKEY,
+        authority: buyer.publicKey,
+        request: requestPda,
+        category: categoryPda,
+        admin: adminPda,
+        geoCell: geoCellPda,
+        userStats: findUserStatsPda(buyer.publicKey),
+      })
+      .signers([buyer])
+      .rpc();
+
+    const storePda = await createSellerStore();
+    const rival = await createKeypairUser("partial_rival", ROLE_SELLER);
+    const rivalStorePda = await createSellerStore(
+      undefined,
+      undefined,
+      0,
+      rival
+    );
+
+    const offers = [
+      {
+        offerPda: await createSellerOffer(requestPda, storePda, 10, 120),
+        seller: provider.publicKey,
+      },
+      {
+        offerPda: await createSellerOffer(
+          requestPda,
+          rivalStorePda,
+          10,
+          80,
+          rival
+        ),
+        seller: rival.keypair.publicKey,
+      },
+    ];
+
+    for (const { offerPda, seller } of offers) {
+      await program.methods
+        .acceptOffer()
+        .accounts({
+          user: buyerPda,
+          systemProgram: SystemProgram.programId,
+          authority: buyer.publicKey,
+          request: requestPda,
+          offer: offerPda,
+          category: categoryPda,
+          geoCell: geoCellPda,
+          buyerStats: findUserStatsPda(buyer.publicKey),
+          sellerStats: findUserStatsPda(seller),
+        })
+        .signers([buyer])
+        .rpc();
+    }
+
+    const request = await program.account.request.fetch(requestPda);
+    expect(Number(request.acceptedQuantity)).to.be.equal(200);
+    expect(request.lifecycle).to.be.deep.equal({ acceptedByBuyer: {} });
+
+    const buyerStats = await program.account.userStats.fetch(
+      findUserStatsPda(buyer.publicKey)
+    );
+    expect(Number(buyerStats.requestsCreated)).to.be.greaterThan(0);
+    expect(Number(buyerStats.acceptancesGiven)).to.be.greaterThanOrEqual(2);
+  });
+
+  it("Should let a buyer withdraw an unpaid partial acceptance", async function () {
+    const requestPda = await createBuyerRequest({ quantity: 200 });
+    const storePda = await createSellerStore();
+    const rival = await createKeypairUser("unaccept_rival", ROLE_SELLER);
+    const rivalStorePda = await createSellerStore(
+      undefined,
+      undefined,
+      0,
+      rival
+    );
+
+    const firstOfferPda = await createSellerOffer(requestPda, storePda, 10, 120);
+    const secondOfferPda = await createSellerOffer(
+      requestPda,
+      rivalStorePda,
+      10,
+      80,
+      rival
+    );
+
+    for (const [offerPda, seller] of [
+      [firstOfferPda, provider.publicKey],
+      [secondOfferPda, rival.keypair.publicKey],
+    ]) {
+      await program.methods
+        .acceptOffer()
+        .accounts({
+          user: buyerPda,
+          systemProgram: SystemProgram.programId,
+          authority: buyer.publicKey,
+          request: requestPda,
+          offer: offerPda,
+          category: categoryPda,
+          geoCell: geoCellPda,
+          buyerStats: findUserStatsPda(buyer.publicKey),
+          sellerStats: findUserStatsPda(seller),
+        })
+        .signers([buyer])
+        .rpc();
+    }
+
+    const unacceptOffer = (offerPda: PublicKey) =>
+      program.methods
+        .unacceptOffer()
+        .accounts({
+          user: buyerPda,
+          authority: buyer.publicKey,
+          offer: offerPda,
+          request: requestPda,
+          category: categoryPda,
+          geoCell: geoCellPda,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc();
+
+    await unacceptOffer(secondOfferPda);
+
+    expect((await program.account.offer.fetch(secondOfferPda)).isAccepted).to
+      .be.false;
+
+    const request = await program.account.request.fetch(requestPda);
+    expect(Number(request.acceptedQuantity)).to.be.equal(120);
+    expect(request.offerIds.map(Number)).to.not.include(
+      Number((await program.account.offer.fetch(secondOfferPda)).id)
+    );
+    expect(request.lifecycle).to.be.deep.equal({ acceptedByBuyer: {} });
+
+    const category = await program.account.category.fetch(categoryPda);
+    expect(category.requestIds.map(Number)).to.include(Number(request.id));
+
+    try {
+      await unacceptOffer(secondOfferPda);
+      expect.fail("an offer that is not accepted cannot be withdrawn");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("OfferNotAccepted");
+    }
+  });
+
+  it("Should let each party review a completed deal once", async function () {
+    const requestPda = await createBuyerRequest();
+    const storePda = await createSellerStore();
+    const offerPda = await createSellerOffer(requestPda, storePda, 10);
+
+    await program.methods
+      .acceptOffer()
+      .accounts({
+        user: buyerPda,
+        systemProgram: SystemProgram.programId,
+        authority: buyer.publicKey,
+        request: requestPda,
+        offer: offerPda,
+        category: categoryPda,
+        geoCell: geoCellPda,
+        buyerStats: findUserStatsPda(buyer.publicKey),
+        sellerStats: findUserStatsPda(provider.publicKey),
+      })
+      .signers([buyer])
+      .rpc();
+
+    await sleep(62);
+
+    const requestPaymentCounter = await program.account.counter.fetch(
+      requestPaymentCounterPda
+    );
+
+    const [requestPaymentPda] = PublicKey.findProgramAddressSync(
+      [
+        utf8.encode(REQUEST_PAYMENT_TAG),
+        buyer.publicKey.toBuffer(),
+        Buffer.from(requestPaymentCounter.current.toArray("le", 8)),
+      ],
+      program.programId
+    );
+
+    await program.methods
+      .payForRequest({ solana: {} })
+      .accounts({
+        request: requestPda,
+        offer: offerPda,
+        requestPaymentInfo: requestPaymentPda,
+        requestPaymentCounter: requestPaymentCounterPda,
+        buyerStats: findUserStatsPda(buyer.publicKey),
+        sellerStats: findUserStatsPda(provider.publicKey),
+        authority: buyer.publicKey,
+        to: PORTAL_CLIENT,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([buyer])
+      .rpc();
+
+    const confirmDelivery = () =>
+      program.methods
+        .confirmDelivery()
+        .accounts({
+          offer: offerPda,
+          store: storePda,
+          storeDelegate: null,
+          authority: provider.publicKey,
+        })
+        .rpc();
+
+    const markRequestAsCompleted = () =>
+      program.methods
+        .markRequestAsCompleted()
+        .accounts({
+          request: requestPda,
+          offer: offerPda,
+          buyerStats: findUserStatsPda(buyer.publicKey),
+          sellerStats: findUserStatsPda(provider.publicKey),
+          authority: buyer.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc();
+
+    try {
+      await markRequestAsCompleted();
+      expect.fail("a deal should not complete before delivery is confirmed");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("DeliveryNotConfirmed");
+    }
+
+    await confirmDelivery();
+    expect((await program.account.offer.fetch(offerPda)).isDelivered).to.be
+      .true;
+
+    try {
+      await confirmDelivery();
+      expect.fail("delivery should only be confirmed once");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal(
+        "DeliveryAlreadyConfirmed"
+      );
+    }
+
+    await markRequestAsCompleted();
+
+    const leaveReview = (
+      reviewer: anchor.web3.Keypair | null,
+      reviewee: PublicKey,
+      rating: number
+    ) => {
+      const reviewerKey = reviewer ? reviewer.publicKey : provider.publicKey;
+      const [reviewPda] = PublicKey.findProgramAddressSync(
+        [utf8.encode(REVIEW_TAG), offerPda.toBuffer(), reviewerKey.toBuffer()],
+        program.programId
+      );
+
+      return program.methods
+        .leaveReview(rating, Array(32).fill(0))
+        .accounts({
+          request: requestPda,
+          offer: offerPda,
+          review: reviewPda,
+          revieweeStats: findUserStatsPda(reviewee),
+          reviewee,
+          reviewer: reviewerKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers(reviewer ? [reviewer] : [])
+        .rpc();
+    };
+
+    const sellerStatsBefore = await program.account.userStats.fetch(
+      findUserStatsPda(provider.publicKey)
+    );
+
+    try {
+      await leaveReview(buyer, provider.publicKey, 6);
+      expect.fail("ratings above the maximum should be rejected");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("InvalidRating");
+    }
+
+    await leaveReview(buyer, provider.publicKey, 4);
+
+    try {
+      await leaveReview(buyer, provider.publicKey, 5);
+      expect.fail("a party can only review a deal once");
+    } catch (error) {
+      expect(error.logs.join("\n")).to.include("already in use");
+    }
+
+    await leaveReview(null, buyer.publicKey, 5);
+
+    const sellerStats = await program.account.userStats.fetch(
+      findUserStatsPda(provider.publicKey)
+    );
+    expect(Number(sellerStats.ratingCount)).to.be.equal(
+      Number(sellerStatsBefore.ratingCount) + 1
+    );
+    expect(Number(sellerStats.ratingSum)).to.be.equal(
+      Number(sellerStatsBefore.ratingSum) + 4
+    );
+
+    const buyerStats = await program.account.userStats.fetch(
+      findUserStatsPda(buyer.publicKey)
+    );
+    expect(Number(buyerStats.ratingCount)).to.be.equal(1);
+    expect(Number(buyerStats.ratingSum)).to.be.equal(5);
+  });
+
+  it("Should let a buyer review every seller of a split request", async function () {
+    const requestPda = await createBuyerRequest({ quantity: 2 });
+    const rival = await createKeypairUser("review_rival", ROLE_SELLER);
+
+    const sellers = [
+      {
+        keypair: null as anchor.web3.Keypair | null,
+        authority: provider.publicKey,
+        storePda: await createSellerStore(),
+      },
+      {
+        keypair: rival.keypair,
+        authority: rival.keypair.publicKey,
+        storePda: await createSellerStore(undefined, undefined, 0, rival),
+      },
+    ];
+
+    const offerPdas = [
+      await createSellerOffer(requestPda, sellers[0].storePda, 10),
+      await createSellerOffer(requestPda, sellers[1].storePda, 10, 1, rival),
+    ];
+
+    for (const [index, offerPda] of offerPdas.entries()) {
+      await program.methods
+        .acceptOffer()
+        .accounts({
+          user: buyerPda,
+          systemProgram: SystemProgram.programId,
+          authority: buyer.publicKey,
+          request: requestPda,
+          offer: offerPda,
+          category: categoryPda,
+          geoCell: geoCellPda,
+          buyerStats: findUserStatsPda(buyer.publicKey),
+          sellerStats: findUserStatsPda(sellers[index].authority),
+        })
+        .signers([buyer])
+        .rpc();
+    }
+
+    await sleep(62);
+
+    for (const [index, offerPda] of offerPdas.entries()) {
+      const seller = sellers[index];
+      const requestPaymentCounter = await program.account.counter.fetch(
+        requestPaymentCounterPda
+      );
+
+      await program.methods
+        .payForRequest({ solana: {} })
+        .accounts({
+          request: requestPda,
+          offer: offerPda,
+          requestPaymentInfo: PublicKey.findProgramAddressSync(
+            [
+              utf8.encode(REQUEST_PAYMENT_TAG),
+              buyer.publicKey.toBuffer(),
+              Buffer.from(requestPaymentCounter.current.toArray("le", 8)),
+            ],
+            program.programId
+          )[0],
+          requestPaymentCounter: requestPaymentCounterPda,
+          buyerStats: findUserStatsPda(buyer.publicKey),
+          sellerStats: findUserStatsPda(seller.authority),
+          authority: buyer.publicKey,
+          to: PORTAL_CLIENT,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc();
+
+      await program.methods
+        .confirmDelivery()
+        .accounts({
+          offer: offerPda,
+          store: seller.storePda,
+          storeDelegate: null,
+          authority: seller.authority,
+        })
+        .signers(seller.keypair ? [seller.keypair] : [])
+        .rpc();
+
+      await program.methods
+        .markRequestAsCompleted()
+        .accounts({
+          request: requestPda,
+          offer: offerPda,
+          buyerStats: findUserStatsPda(buyer.publicKey),
+          sellerStats: findUserStatsPda(seller.authority),
+          authority: buyer.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc();
+
+      const [reviewPda] = PublicKey.findProgramAddressSync(
+        [utf8.encode(REVIEW_TAG), offerPda.toBuffer(), buyer.publicKey.toBuffer()],
+        program.programId
+      );
+
+      await program.methods
+        .leaveReview(5, Array(32).fill(0))
+        .accounts({
+          request: requestPda,
+          offer: offerPda,
+          review: reviewPda,
+          revieweeStats: findUserStatsPda(seller.authority),
+          reviewee: seller.authority,
+          reviewer: buyer.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc();
+
+      const review = await program.account.review.fetch(reviewPda);
+      expect(review.reviewee.toBase58()).to.be.equal(
+        seller.authority.toBase58()
+      );
+    }
+
+    const rivalStats = await program.account.userStats.fetch(
+      findUserStatsPda(rival.keypair.publicKey)
     );
+    expect(Number(rivalStats.ratingCount)).to.be.equal(1);
+  });
 
-    const [buyerPda_] = PublicKey.findProgramAddressSync(
-      [utf8.encode(USER_TAG), buyer.publicKey.toBuffer()],
+  it("Should only share the committed precise location once accepted", async function () {
+    const requestPda = await createBuyerRequest();
+    const storePda = await createSellerStore();
+    const offerPda = await createSellerOffer(requestPda, storePda, 10);
+    const locationKey = Buffer.from("location key sealed to the seller");
+
+    const [locationKeySharePda] = PublicKey.findProgramAddressSync(
+      [utf8.encode(LOCATION_KEY_TAG), offerPda.toBuffer()],
       program.programId
     );
 
-    profilePda = profilePda_;
-    buyerPda = buyerPda_;
+    const sharePreciseLocation = (location: Buffer) =>
+      program.methods
+        .sharePreciseLocation(location, locationKey)
+        .accounts({
+          request: requestPda,
+          offer: offerPda,
+          locationKeyShare: locationKeySharePda,
+          authority: buyer.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyer])
+        .rpc();
+
+    try {
+      await sharePreciseLocation(encryptedLocation);
+      expect.fail("the location is only shared after acceptance");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("RequestNotAccepted");
+    }
 
     await program.methods
-      .createUser(
-        buyerPayload.username,
-        buyerPayload.phone,
-        buyerPayload.latitude,
-        buyerPayload.longitude,
-        buyerPayload.account_type
-      )
+      .acceptOffer()
       .accounts({
-        user: profilePda,
+        user: buyerPda,
         systemProgram: SystemProgram.programId,
-        userCounter: USER_COUNTER_PUBKEY,
-        authority: provider.publicKey,
+        authority: buyer.publicKey,
+        request: requestPda,
+        offer: offerPda,
+        category: categoryPda,
+        geoCell: geoCellPda,
+        buyerStats: findUserStatsPda(buyer.publicKey),
+        sellerStats: findUserStatsPda(provider.publicKey),
       })
-
+      .signers([buyer])
       .rpc();
 
+    try {
+      await sharePreciseLocation(Buffer.from("another location"));
+      expect.fail("a location that does not open the commitment is rejected");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal(
+        "LocationCommitmentMismatch"
+      );
+    }
+
+    await sharePreciseLocation(encryptedLocation);
+
+    const request = await program.account.request.fetch(requestPda);
+    expect(Buffer.from(request.encryptedLocation)).to.be.deep.equal(
+      encryptedLocation
+    );
+    const locationKeyShare = await program.account.locationKeyShare.fetch(
+      locationKeySharePda
+    );
+    expect(Buffer.from(locationKeyShare.encryptedLocationKey)).to.be.deep.equal(
+      locationKey
+    );
+    expect(locationKeyShare.recipient.toBase58()).to.be.equal(
+      provider.publicKey.toBase58()
+    );
+  });
+
+  it("Should fund and execute a pooled request for exactly its price", async function () {
+    const requestPda = await createBuyerRequest({
+      quantity: 3,
+      pooled: true,
+      poolDeadline: (await chainNow()) + 600,
+    });
+    const storePda = await createSellerStore();
+    const unitPrice = 1_000_000;
+    const offerPda = await createSellerOffer(requestPda, storePda, unitPrice, 3);
+    const neighbour = await createKeypairUser("pool_neighbour");
+
+    await joinPool(requestPda, buyer, buyerPda, 2, 0);
+
+    try {
+      await joinPool(requestPda, neighbour.keypair, neighbour.userPda, 2, 0);
+      expect.fail("joining for more than the unclaimed quantity should fail");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("QuantityExceeded");
+    }
+
+    await joinPool(
+      requestPda,
+      neighbour.keypair,
+      neighbour.userPda,
+      1,
+      unitPrice + 500_000
+    );
+
     await program.methods
-      .createUser(
-        buyerPayload.username,
-        buyerPayload.phone,
-        buyerPayload.latitude,
-        buyerPayload.longitude,
-        buyerPayload.account_type
-      )
+      .acceptOffer()
       .accounts({
         user: buyerPda,
         systemProgram: SystemProgram.programId,
-        userCounter: USER_COUNTER_PUBKEY,
         authority: buyer.publicKey,
+        request: requestPda,
+        offer: offerPda,
+        category: categoryPda,
+        geoCell: geoCellPda,
+        buyerStats: findUserStatsPda(buyer.publicKey),
+        sellerStats: findUserStatsPda(provider.publicKey),
       })
       .signers([buyer])
       .rpc();
-  });
 
-  it("Can create a new user and return true values", async function () {
-    const user = await program.account.user.fetch(profilePda);
-    expect(user.username).to.be.equal(buyerPayload.username);
-    expect(user.phone).to.be.equal(buyerPayload.phone);
-    expect(Number(user.location.latitude)).to.be.equal(
-      Number(buyerPayload.latitude)
+    const fundPool = (participant: anchor.web3.Keypair) =>
+      program.methods
+        .fundPool()
+        .accounts({
+          request: requestPda,
+          offer: offerPda,
+          participant: findPoolParticipantPda(
+            requestPda,
+            participant.publicKey
+          ),
+          authority: participant.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([participant])
+        .rpc();
+
+    try {
+      await fundPool(buyer);
+      expect.fail("a pool should not be funded before the request locks");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("RequestNotLocked");
+    }
+
+    await sleep(62);
+
+    await fundPool(buyer);
+    await fundPool(neighbour.keypair);
+
+    const sellerStatsBefore = await program.account.userStats.fetch(
+      findUserStatsPda(provider.publicKey)
     );
-    expect(Number(user.location.longitude)).to.be.equal(
-      Number(buyerPayload.longitude)
+
+    const fundedRequest = await program.account.request.fetch(requestPda);
+    expect(Number(fundedRequest.poolEscrowed)).to.be.equal(3 * unitPrice);
+    expect(
+      Number(
+        (
+          await program.account.poolParticipant.fetch(
+            findPoolParticipantPda(requestPda, neighbour.keypair.publicKey)
+          )
+        ).escrowed
+      )
+    ).to.be.equal(unitPrice);
+
+    const requestPaymentCounter = await program.account.counter.fetch(
+      requestPaymentCounterPda
+    );
+
+    const [requestPaymentPda] = PublicKey.findProgramAddressSync(
+      [
+        utf8.encode(REQUEST_PAYMENT_TAG),
+        provider.publicKey.toBuffer(),
+        Buffer.from(requestPaymentCounter.current.toArray("le", 8)),
+      ],
+      program.programId
     );
-    expect(user.accountType).to.be.deep.equal(buyerPayload.account_type);
-  });
 
-  it("Can update a user and return true values", async function () {
     await program.methods
-      .updateUser(
-        sellerPayload.username,
-        sellerPayload.phone,
-        sellerPayload.latitude,
-        sellerPayload.longitude,
-        sellerPayload.account_type
-      )
+      .executePool()
       .accounts({
-        user: profilePda,
+        request: requestPda,
+        offer: offerPda,
+        requestPaymentInfo: requestPaymentPda,
+        requestPaymentCounter: requestPaymentCounterPda,
+        buyerStats: findUserStatsPda(buyer.publicKey),
+        sellerStats: findUserStatsPda(provider.publicKey),
         authority: provider.publicKey,
+        to: PORTAL_CLIENT,
+        systemProgram: SystemProgram.programId,
       })
       .rpc();
 
-    const user = await program.account.user.fetch(profilePda);
-    expect(user.username).to.be.equal(sellerPayload.username);
-    expect(user.phone).to.be.equal(sellerPayload.phone);
-    expect(Number(user.location.latitude)).to.be.equal(
-      Number(sellerPayload.latitude)
+    const paidRequest = await program.account.request.fetch(requestPda);
+    expect(paidRequest.paid).to.be.true;
+    expect(Number(paidRequest.poolEscrowed)).to.be.equal(0);
+    expect((await program.account.offer.fetch(offerPda)).isPaid).to.be.true;
+
+    const requestPayment =
+      await program.account.requestPaymentTransaction.fetch(requestPaymentPda);
+    expect(Number(requestPayment.amount)).to.be.equal(3 * unitPrice);
+
+    const sellerStats = await program.account.userStats.fetch(
+      findUserStatsPda(provider.publicKey)
     );
-    expect(Number(user.location.longitude)).to.be.equal(
-      Number(sellerPayload.longitude)
+    expect(Number(sellerStats.solVolume)).to.be.equal(
+      Number(sellerStatsBefore.solVolume) + 3 * unitPrice
     );
-    expect(user.accountType).to.be.deep.equal(sellerPayload.account_type);
   });
 
-  it("Should allow a seller to create a store", async function () {
-    const [profilePda, _] = PublicKey.findProgramAddressSync(
-      [utf8.encode(USER_TAG), provider.publicKey.toBuffer()],
-      program.programId
+  it("Should refund pool deposits once the pool deadline passes", async function () {
+    const requestPda = await createBuyerRequest({
+      quantity: 2,
+      pooled: true,
+      poolDeadline: (await chainNow()) + 8,
+    });
+    const participantPda = findPoolParticipantPda(
+      requestPda,
+      buyer.publicKey
     );
 
-    const storeCounter = await program.account.counter.fetch(
-      STORE_COUNTER_PUBKEY
-    );
+    await joinPool(requestPda, buyer, buyerPda, 1, 1_000);
 
-    const [storePda] = PublicKey.findProgramAddressSync(
-      [
-        utf8.encode(STORE_TAG),
-        provider.publicKey.toBuffer(),
-        Buffer.from(storeCounter.current.toArray("le", 8)),
-      ],
-      program.programId
-    );
+    const refundPool = () =>
+      program.methods
+        .refundPool()
+        .accounts({
+          request: requestPda,
+          participant: participantPda,
+          participantAuthority: buyer.publicKey,
+          authority: provider.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc();
 
-    const receipt = await program.methods
-      .createStore(
-        storePayload.name,
-        storePayload.description,
-        storePayload.phone,
-        new BN(storePayload.lat.toString()),
-        new BN(storePayload.long.toString())
-      )
+    try {
+      await refundPool();
+      expect.fail("a pool should not be refunded before its deadline");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("PoolStillOpen");
+    }
+
+    await sleep(10);
+
+    try {
+      await joinPool(requestPda, buyer, buyerPda, 1, 0);
+      expect.fail("a pool should not be joined after its deadline");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("PoolClosed");
+    }
+
+    await refundPool();
+
+    expect(await provider.connection.getAccountInfo(participantPda)).to.be.null;
+    const request = await program.account.request.fetch(requestPda);
+    expect(Number(request.poolEscrowed)).to.be.equal(0);
+    expect(Number(request.poolParticipants)).to.be.equal(0);
+  });
+
+  it("Should not let buyers join the pool of a cancelled request", async function () {
+    const requestPda = await createBuyerRequest({
+      quantity: 2,
+      pooled: true,
+      poolDeadline: (await chainNow()) + 600,
+    });
+
+    await program.methods
+      .cancelRequest()
       .accounts({
-        user: profilePda,
+        request: requestPda,
+        admin: adminPda,
+        treasury: (await program.account.admin.fetch(adminPda)).treasury,
+        category: categoryPda,
+        geoCell: geoCellPda,
+        userStats: findUserStatsPda(buyer.publicKey),
+        authority: buyer.publicKey,
         systemProgram: SystemProgram.programId,
-        storeCounter: STORE_COUNTER_PUBKEY,
-        authority: provider.publicKey,
-        store: storePda,
       })
+      .signers([buyer])
       .rpc();
 
-    const store = await program.account.store.fetch(storePda);
-    expect(store.name).to.be.equal(storePayload.name);
-    expect(store.description).to.be.equal(storePayload.description);
-    expect(store.phone).to.be.equal(storePayload.phone);
-    expect(Number(store.location.latitude)).to.be.equal(storePayload.lat);
+    try {
+      await joinPool(requestPda, buyer, buyerPda, 1, 0);
+      expect.fail("a cancelled pool should not be joined");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("RequestCancelled");
+    }
   });
 
-  it("Should allow a buyer to create a request", async function () {
+  it("Should reject an offer from a store outside the request range", async function () {
     const requestCounter = await program.account.counter.fetch(
       REQUEST_COUNTER_PUBKEY
     );
@@ -256,8 +3095,21 @@ describe("match-solana-contract", function () {
         requestPayload.name,
         requestPayload.description,
         requestPayload.images,
-        new BN(requestPayload.lat.toString()),
-        new BN(requestPayload.long.toString())
+        requestPayload.lat,
+        requestPayload.long,
+        null,
+        null,
+        { solana: {} },
+        { standard: {} },
+        new BN(0),
+        false,
+        new BN(0),
+        new BN(1),
+        false,
+        new BN(0),
+        new BN(10_000),
+        locationCommitment,
+        0
       )
       .accounts({
         user: buyerPda,
@@ -265,17 +3117,68 @@ describe("match-solana-contract", function () {
         requestCounter: REQUEST_COUNTER_PUBKEY,
         authority: buyer.publicKey,
         request: requestPda,
+        category: categoryPda,
+        admin: adminPda,
+        geoCell: geoCellPda,
+        userStats: findUserStatsPda(buyer.publicKey),
       })
       .signers([buyer])
       .rpc();
-    const request = await program.account.request.fetch(requestPda);
-    expect(request.name).to.be.equal(requestPayload.name);
-    expect(request.description).to.be.equal(requestPayload.description);
-    expect(request.images).to.be.deep.equal(requestPayload.images);
-    expect(Number(request.location.latitude)).to.be.equal(requestPayload.lat);
+
+    // roughly 55km north of the request
+    const storePda = await createSellerStore(
+      Math.trunc(4.883 * 10 ** LOCATION_DECIMALS),
+      storePayload.long
+    );
+
+    const offerCounter = await program.account.counter.fetch(
+      OFFER_COUNTER_PUBKEY
+    );
+
+    const [offerPda] = PublicKey.findProgramAddressSync(
+      [
+        utf8.encode(OFFER_TAG),
+        provider.publicKey.toBuffer(),
+        Buffer.from(offerCounter.current.toArray("le", 8)),
+      ],
+      program.programId
+    );
+
+    try {
+      await program.methods
+        .createOffer(new BN(10), ["image1"], new BN(1))
+        .accounts({
+          user: profilePda,
+          systemProgram: SystemProgram.programId,
+          offerCounter: OFFER_COUNTER_PUBKEY,
+          authority: provider.publicKey,
+          request: requestPda,
+          offer: offerPda,
+          store: storePda,
+          userStats: findUserStatsPda(provider.publicKey),
+          admin: adminPda,
+          sellerBond: null,
+          storeDelegate: null,
+          priceUpdate: null,
+        })
+        .rpc();
+      expect.fail("out of range offer should be rejected");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("SellerOutOfRange");
+    }
   });
 
-  it("Should allow a seller to create a offer", async function () {
+  it("Should require a seller bond once the admin sets a minimum", async function () {
+    const minBond = new BN(1_000_000);
+
+    await program.methods
+      .setBondConfig(minBond, PublicKey.default, new BN(0), new BN(0))
+      .accounts({
+        admin: adminPda,
+        authority: provider.publicKey,
+      })
+      .rpc();
+
     const requestCounter = await program.account.counter.fetch(
       REQUEST_COUNTER_PUBKEY
     );
@@ -294,8 +3197,21 @@ describe("match-solana-contract", function () {
         requestPayload.name,
         requestPayload.description,
         requestPayload.images,
-        new BN(requestPayload.lat.toString()),
-        new BN(requestPayload.long.toString())
+        requestPayload.lat,
+        requestPayload.long,
+        null,
+        null,
+        { solana: {} },
+        { standard: {} },
+        new BN(0),
+        false,
+        new BN(0),
+        new BN(1),
+        false,
+        new BN(0),
+        new BN(0),
+        locationCommitment,
+        0
       )
       .accounts({
         user: buyerPda,
@@ -303,53 +3219,99 @@ describe("match-solana-contract", function () {
         requestCounter: REQUEST_COUNTER_PUBKEY,
         authority: buyer.publicKey,
         request: requestPda,
+        category: categoryPda,
+        admin: adminPda,
+        geoCell: geoCellPda,
+        userStats: findUserStatsPda(buyer.publicKey),
       })
       .signers([buyer])
       .rpc();
 
-    const request = await program.account.request.fetch(requestPda);
+    const storePda = await createSellerStore();
 
-    const offerCounter = await program.account.counter.fetch(
-      OFFER_COUNTER_PUBKEY
-    );
+    const createOffer = async (sellerBond: PublicKey | null) => {
+      const offerCounter = await program.account.counter.fetch(
+        OFFER_COUNTER_PUBKEY
+      );
 
-    const [offerPda] = PublicKey.findProgramAddressSync(
-      [
-        utf8.encode(OFFER_TAG),
-        provider.publicKey.toBuffer(),
-        Buffer.from(offerCounter.current.toArray("le", 8)),
-      ],
-      program.programId
-    );
+      const [offerPda] = PublicKey.findProgramAddressSync(
+        [
+          utf8.encode(OFFER_TAG),
+          provider.publicKey.toBuffer(),
+          Buffer.from(offerCounter.current.toArray("le", 8)),
+        ],
+        program.programId
+      );
 
-    const offerPayload = {
-      price: Math.trunc(10),
-      images: ["image1", "image2"],
-      storeName: "test store",
+      await program.methods
+        .createOffer(new BN(10), ["image1"], new BN(1))
+        .accounts({
+          user: profilePda,
+          systemProgram: SystemProgram.programId,
+          offerCounter: OFFER_COUNTER_PUBKEY,
+          authority: provider.publicKey,
+          request: requestPda,
+          offer: offerPda,
+          store: storePda,
+          userStats: findUserStatsPda(provider.publicKey),
+          admin: adminPda,
+          sellerBond,
+          storeDelegate: null,
+          priceUpdate: null,
+        })
+        .rpc();
+
+      return offerPda;
     };
 
+    try {
+      await createOffer(null);
+      expect.fail("offer without a bond should be rejected");
+    } catch (error) {
+      expect(error.error.errorCode.code).to.be.equal("BondRequired");
+    }
+
+    const [sellerBondPda] = PublicKey.findProgramAddressSync(
+      [utf8.encode(SELLER_BOND_TAG), provider.publicKey.toBuffer()],
+      program.programId
+    );
+
     await program.methods
-      .createOffer(
-        new BN(offerPayload.price.toString()),
-        offerPayload.images,
-        offerPayload.storeName
-      )
+      .stakeBond(minBond)
       .accounts({
         user: profilePda,
-        systemProgram: SystemProgram.programId,
-        offerCounter: OFFER_COUNTER_PUBKEY,
+        sellerBond: sellerBondPda,
         authority: provider.publicKey,
-        request: requestPda,
-        offer: offerPda,
+        systemProgram: SystemProgram.programId,
       })
       .rpc();
 
+    const offerPda = await createOffer(sellerBondPda);
+
     const offer = await program.account.offer.fetch(offerPda);
-    expect(Number(offer.price)).to.be.equal(offerPayload.price);
-    expect(offer.images).to.be.deep.equal(offerPayload.images);
-    expect(offer.storeName).to.be.equal(offerPayload.storeName);
+    expect(Number(offer.requestId)).to.be.equal(Number(requestCounter.current));
+
+    await program.methods
+      .setBondConfig(new BN(0), PublicKey.default, new BN(0), new BN(0))
+      .accounts({
+        admin: adminPda,
+        authority: provider.publicKey,
+      })
+      .rpc();
   });
-  it("Should allow a buyer to accept an offer", async function () {
+
+  it("Should forfeit the request deposit when a buyer cancels after offers", async function () {
+    const deposit = 1_000_000;
+    const treasury = anchor.web3.Keypair.generate();
+
+    await program.methods
+      .setRequestDepositConfig(new BN(deposit), treasury.publicKey, new BN(0))
+      .accounts({
+        admin: adminPda,
+        authority: provider.publicKey,
+      })
+      .rpc();
+
     const requestCounter = await program.account.counter.fetch(
       REQUEST_COUNTER_PUBKEY
     );
@@ -368,8 +3330,21 @@ describe("match-solana-contract", function () {
         requestPayload.name,
         requestPayload.description,
         requestPayload.images,
-        new BN(requestPayload.lat.toString()),
-        new BN(requestPayload.long.toString())
+        requestPayload.lat,
+        requestPayload.long,
+        null,
+        null,
+        { solana: {} },
+        { standard: {} },
+        new BN(0),
+        false,
+        new BN(0),
+        new BN(1),
+        false,
+        new BN(0),
+        new BN(0),
+        locationCommitment,
+        0
       )
       .accounts({
         user: buyerPda,
@@ -377,11 +3352,18 @@ describe("match-solana-contract", function () {
         requestCounter: REQUEST_COUNTER_PUBKEY,
         authority: buyer.publicKey,
         request: requestPda,
+        category: categoryPda,
+        admin: adminPda,
+        geoCell: geoCellPda,
+        userStats: findUserStatsPda(buyer.publicKey),
       })
       .signers([buyer])
       .rpc();
 
     const request = await program.account.request.fetch(requestPda);
+    expect(Number(request.deposit)).to.be.equal(deposit);
+
+    const storePda = await createSellerStore();
 
     const offerCounter = await program.account.counter.fetch(
       OFFER_COUNTER_PUBKEY
@@ -396,18 +3378,8 @@ describe("match-solana-contract", function () {
       program.programId
     );
 
-    const offerPayload = {
-      price: Math.trunc(10),
-      images: ["image1", "image2"],
-      storeName: "test store",
-    };
-
     await program.methods
-      .createOffer(
-        new BN(offerPayload.price.toString()),
-        offerPayload.images,
-        offerPayload.storeName
-      )
+      .createOffer(new BN(10), ["image1"], new BN(1))
       .accounts({
         user: profilePda,
         systemProgram: SystemProgram.programId,
@@ -415,40 +3387,75 @@ describe("match-solana-contract", function () {
         authority: provider.publicKey,
         request: requestPda,
         offer: offerPda,
+        store: storePda,
+        userStats: findUserStatsPda(provider.publicKey),
+        admin: adminPda,
+        sellerBond: null,
+        storeDelegate: null,
+        priceUpdate: null,
       })
       .rpc();
 
-    const offer = await program.account.offer.fetch(offerPda);
+    await program.methods
+      .cancelRequest()
+      .accounts({
+        request: requestPda,
+        admin: adminPda,
+        treasury: treasury.publicKey,
+        category: categoryPda,
+        geoCell: geoCellPda,
+        userStats: findUserStatsPda(buyer.publicKey),
+        authority: buyer.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([buyer])
+      .rpc();
 
-    const offerAccounts = await program.account.offer.all([
-      {
-        memcmp: {
-          offset: 8 + 32 + 8,
-          bytes: ntobs58(offer.requestId),
-        },
-      },
-    ]);
+    const cancelledRequest = await program.account.request.fetch(requestPda);
+    expect(cancelledRequest.lifecycle).to.be.deep.equal({ cancelled: {} });
+    expect(
+      await provider.connection.getBalance(treasury.publicKey)
+    ).to.be.equal(deposit);
+
+    const buyerStatsBefore = await program.account.userStats.fetch(
+      findUserStatsPda(buyer.publicKey)
+    );
 
     await program.methods
-      .acceptOffer()
+      .deleteRequest()
       .accounts({
-        user: buyerPda,
-        systemProgram: SystemProgram.programId,
-        authority: buyer.publicKey,
         request: requestPda,
-        offer: offerPda,
+        category: categoryPda,
+        geoCell: geoCellPda,
+        userStats: findUserStatsPda(buyer.publicKey),
+        authority: buyer.publicKey,
+        systemProgram: SystemProgram.programId,
       })
-      .remainingAccounts(
-        offerAccounts.map((offerAccount) => ({
-          pubkey: offerAccount.publicKey,
-          isWritable: true,
-          isSigner: false,
-        }))
-      )
+      .remainingAccounts(await requestOfferAccounts(requestPda))
       .signers([buyer])
       .rpc();
 
-    const updatedOffer = await program.account.offer.fetch(offerPda);
-    expect(updatedOffer.isAccepted).to.be.equal(true);
+    expect(await provider.connection.getAccountInfo(requestPda)).to.be.null;
+    // the offer is withdrawn so its store can close without the request
+    expect((await program.account.offer.fetch(offerPda)).isWithdrawn).to.be
+      .true;
+
+    const buyerStats = await program.account.userStats.fetch(
+      findUserStatsPda(buyer.publicKey)
+    );
+    expect(Number(buyerStats.requestsCancelled)).to.be.equal(
+      Number(buyerStatsBefore.requestsCancelled)
+    );
+    expect(Number(buyerStats.openRequests)).to.be.equal(
+      Number(buyerStatsBefore.openRequests)
+    );
+
+    await program.methods
+      .setRequestDepositConfig(new BN(0), treasury.publicKey, new BN(0))
+      .accounts({
+        admin: adminPda,
+        authority: provider.publicKey,
+      })
+      .rpc();
   });
 });
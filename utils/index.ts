@@ -1,7 +1,9 @@
 import { BN, utils } from "@coral-xyz/anchor";
 import { PublicKey } from "@solana/web3.js";
+import { createHash, randomBytes } from "crypto";
 
-export const LOCATION_DECIMALS = 18;
+export const LOCATION_DECIMALS = 7;
+export const LOCATION_GRID_SIZE = 100_000;
 export const PROJECT_ID = "73801621aec60dfaa2197c7640c15858";
 export const DEBUG = true;
 
@@ -17,6 +19,42 @@ export const REQUEST_TAG = "REQUEST_STATE";
 
 export const OFFER_TAG = "OFFER_STATE";
 
+export const CATEGORY_TAG = "CATEGORY_STATE";
+
+export const GEO_CELL_TAG = "GEO_CELL_STATE";
+
+export const CONTACT_TAG = "CONTACT_STATE";
+
+export const LOCATION_KEY_TAG = "LOCATION_KEY_STATE";
+
+export const USER_STATS_TAG = "USER_STATS_STATE";
+
+export const REVIEW_TAG = "REVIEW_STATE";
+
+export const SELLER_BOND_TAG = "SELLER_BOND_STATE";
+
+export const BOND_VAULT_TAG = "BOND_VAULT_STATE";
+
+export const LISTING_TAG = "LISTING_STATE";
+
+export const USERNAME_TAG = "USERNAME_STATE";
+
+export const MAX_IMAGES = 5;
+
+export const ROLE_BUYER = 1 << 0;
+
+export const ROLE_SELLER = 1 << 1;
+
+export const STORE_DELEGATE_TAG = "STORE_DELEGATE_STATE";
+
+export const DELEGATE_CREATE_OFFERS = 1 << 0;
+
+export const DELEGATE_UPDATE_OFFERS = 1 << 1;
+
+export const DELEGATE_CONFIRM_DELIVERY = 1 << 2;
+
+export const GEOHASH_PRECISION = 5;
+
 export const USER_COUNTER = "USER_COUNTER";
 
 export const STORE_COUNTER = "STORE_COUNTER";
@@ -25,5 +63,87 @@ export const REQUEST_COUNTER = "REQUEST_COUNTER";
 
 export const OFFER_COUNTER = "OFFER_COUNTER";
 
+export const LISTING_COUNTER = "LISTING_COUNTER";
+
+export const REQUEST_PAYMENT_COUNTER = "REQUEST_PAYMENT_COUNTER";
+
+export const REQUEST_PAYMENT_TAG = "REQUEST_PAYMENT_STATE";
+
+export const POOL_PARTICIPANT_TAG = "POOL_PARTICIPANT_STATE";
+
+export const BPF_UPGRADEABLE_LOADER_ID = new PublicKey(
+  "BPFLoaderUpgradeab1e11111111111111111111111"
+);
+
+export const PORTAL_CLIENT = new PublicKey(
+  "GDCRomxxHbEmBXcWL2WACAqzJ46jMzM8kCsWBhg2Kmjt"
+);
+
+const GEOHASH_ALPHABET = "0123456789bcdefghjkmnpqrstuvwxyz";
+
+export const encodeGeohash = (
+  latitude: number,
+  longitude: number,
+  precision = GEOHASH_PRECISION
+) => {
+  const latitudeRange = [-90, 90];
+  const longitudeRange = [-180, 180];
+  let geohash = "";
+  let evenBit = true;
+
+  while (geohash.length < precision) {
+    let index = 0;
+
+    for (let bit = 0; bit < 5; bit++) {
+      const [value, range] = evenBit
+        ? [longitude, longitudeRange]
+        : [latitude, latitudeRange];
+      const mid = (range[0] + range[1]) / 2;
+
+      index <<= 1;
+
+      if (value >= mid) {
+        index |= 1;
+        range[0] = mid;
+      } else {
+        range[1] = mid;
+      }
+
+      evenBit = !evenBit;
+    }
+
+    geohash += GEOHASH_ALPHABET[index];
+  }
+
+  return geohash;
+};
+
+export const coarsenCoordinate = (coordinate: number) =>
+  Math.floor(coordinate / LOCATION_GRID_SIZE) * LOCATION_GRID_SIZE;
+
+// sha256(encrypted_location). The buyer encrypts the precise location under a
+// fresh key when creating the request and later shares only that key.
+export const preciseLocationCommitment = (encryptedLocation: Buffer) =>
+  Array.from(createHash("sha256").update(encryptedLocation).digest());
+
+// sha256(price_le_u64 || salt), the sealed bid a seller commits to before
+// revealing the price once bidding closes.
+export const offerPriceCommitment = (price: number, salt: Buffer) =>
+  Array.from(
+    createHash("sha256")
+      .update(new BN(price).toArrayLike(Buffer, "le", 8))
+      .update(salt)
+      .digest()
+  );
+
+// sha256(phone || salt). The salt travels with the encrypted contact so only
+// the counterparty can check the phone number against the on-chain hash.
+export const contactHash = (phone: string, salt: Buffer) =>
+  Array.from(createHash("sha256").update(phone).update(salt).digest());
+
+// Phone numbers are few enough to enumerate, so each user hashes theirs with
+// a fresh secret salt that is never published or reused.
+export const newContactSalt = () => randomBytes(32);
+
 export const ntobs58 = (x: any) =>
   utils.bytes.bs58.encode(new BN(x).toArrayLike(Buffer, "le", 8));